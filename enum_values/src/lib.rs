@@ -1,4 +1,3 @@
-#![feature(proc_macro_diagnostic)]
 extern crate proc_macro;
 
 use std::collections::HashMap;
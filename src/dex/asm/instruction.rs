@@ -1,46 +1,19 @@
+use std::convert::{TryFrom, TryInto};
+
 use color_eyre::{
-	eyre::{bail, eyre},
+	eyre::{bail, ensure, eyre},
 	Result,
 };
 
 use crate::dex::{
 	asm::{
 		format::Format,
-		opcode::{Opcode, VALUE_TO_OPCODE},
+		opcode::{Opcode, ReferenceType, VALUE_TO_OPCODE},
 	},
-	parser::{Parse, Parser},
+	parser::{Parse, ParseError, Parser},
+	writer::{FileWriter, Write, Writer},
 };
 
-// trait Instruction {
-// 	fn opcode(&self) -> &Opcode;
-// 	fn code_units(&self) -> u32 {
-// 		self.opcode().format().size() as u32
-// 	}
-// }
-//
-// trait OneRegisterInstruction where Self: Instruction {
-// 	fn register_a(&self) -> u32;
-// }
-//
-// trait TwoRegisterInstruction where Self: OneRegisterInstruction {
-// 	fn register_b(&self) -> u32;
-// }
-//
-// trait WideLiteralInstruction where Self: Instruction {
-// 	fn wide_literal(&self) -> u64;
-// }
-//
-// trait NarrowLiteralInstruction where Self: WideLiteralInstruction {
-// 	fn narrow_literal(&self) -> u64;
-// }
-//
-// trait HatLiteralInstruction where Self: Instruction {
-// 	fn hat_literal(&self) -> u32;
-// }
-//
-// trait NarrowHatLiteralInstruction where Self: NarrowLiteralInstruction + HatLiteralInstruction {}
-// trait LongHatLiteralInstruction where Self: WideLiteralInstruction + HatLiteralInstruction {}
-
 // macro_rules! instructions {
 // 		() => ();
 //     { struct $n:ident { $($field_names:ident = ($fields:expr));* }; $($rest:tt)* } => {
@@ -93,12 +66,12 @@ pub enum Instruction {
 	/// data: `AA|op`
 	///
 	/// `op +AA`
-	Instruction10t(Opcode, (u8,)),
+	Instruction10t(Opcode, (i8,)),
 
 	/// data: `ØØ|op AAAA`
 	///
 	/// `op +AAAA`
-	Instruction20t(Opcode, (u16,)),
+	Instruction20t(Opcode, (i16,)),
 
 	/// data: `AA|op BBBB`
 	///
@@ -112,27 +85,30 @@ pub enum Instruction {
 	/// data: `AA|op BBBB`
 	///
 	/// `op vAA, +BBBB`
-	Instruction21t(Opcode, (u8, u16)),
+	Instruction21t(Opcode, (u8, i16)),
 	/// data: `AA|op BBBB`
 	///
 	/// `op vAA, #+BBBB`
 	Instruction21s(Opcode, (u8, u16)),
 	/// data: `AA|op BBBB`
 	///
-	/// ```
-	/// op vAA, #+BBBB0000
-	/// op vAA, #+BBBB000000000000
-	/// ```
-	Instruction21h(Opcode, (u8, u16)),
+	/// `op vAA, #+BBBB0000`
+	///
+	/// The literal is kept as the value it stands for, `BBBB0000`.
+	///
+	/// `const/high16`
+	Instruction21ih(Opcode, (u8, i32)),
 	/// data: `AA|op BBBB`
 	///
-	// TODO: figure this out
-	// /// ``
-	// Instruction21ih(Opcode, (u16, u16)),
-	// Instruction21lh(Opcode, (u16, u16, u32)),
+	/// `op vAA, #+BBBB000000000000`
+	///
+	/// The literal is kept as the value it stands for, `BBBB000000000000`.
+	///
+	/// `const-wide/high16`
+	Instruction21lh(Opcode, (u8, i64)),
 	/// data: `AA|op BBBB`
 	///
-	/// ```
+	/// ```text
 	/// op vAA, type@BBBB            check-cast
 	/// op vAA, field@BBBB           const-class
 	/// op vAA, method_handle@BBBB   const-method-handle
@@ -153,7 +129,7 @@ pub enum Instruction {
 	/// data: `B|A|op CCCC`
 	///
 	/// `op vA, vB, +CCCC`
-	Instruction22t(Opcode, (u8, u8, u16)),
+	Instruction22t(Opcode, (u8, u8, i16)),
 	/// data: `B|A|op CCCC`
 	///
 	/// `op vA, vB, #+CCCC`
@@ -171,7 +147,7 @@ pub enum Instruction {
 	/// data: `ØØ|op AAAAlo AAAAhi`
 	///
 	/// `op +AAAAAAAA`
-	Instruction30t(Opcode, (u32,)),
+	Instruction30t(Opcode, (i32,)),
 
 	/// data: `ØØ|op AAAA BBBB`
 	///
@@ -185,7 +161,7 @@ pub enum Instruction {
 	/// data: `AA|op BBBBlo BBBBhi`
 	///
 	/// `op vAA, +BBBBBBBB`
-	Instruction31t(Opcode, (u8, u32)),
+	Instruction31t(Opcode, (u8, i32)),
 	/// data: `AA|op BBBBlo BBBBhi`
 	///
 	/// `op vAA, string@BBBBBBBB`
@@ -193,7 +169,7 @@ pub enum Instruction {
 
 	/// data: `A|G|op BBBB F|E|D|C`
 	///
-	/// ```
+	/// ```text
 	/// [A=5] op {vC, vD, vE, vF, vG}, meth@BBBB
 	/// [A=5] op {vC, vD, vE, vF, vG}, site@BBBB
 	/// [A=5] op {vC, vD, vE, vF, vG}, type@BBBB
@@ -208,7 +184,7 @@ pub enum Instruction {
 	Instruction35c(Opcode, (u8, u8, u16, u8, u8, u8, u8)),
 	/// data: `A|G|op BBBB F|E|D|C`
 	///
-	/// ```
+	/// ```text
 	/// [A=5] op {vC, vD, vE, vF, vG}, vtaboff@BBBB
 	/// [A=4] op {vC, vD, vE, vF}, vtaboff@BBBB
 	/// [A=3] op {vC, vD, vE}, vtaboff@BBBB
@@ -220,7 +196,7 @@ pub enum Instruction {
 	Instruction35ms(Opcode, (u8, u8, u16, u8, u8, u8, u8)),
 	/// data: `A|G|op BBBB F|E|D|C`
 	///
-	/// ```
+	/// ```text
 	/// [A=5] op {vC, vD, vE, vF, vG}, inline@BBBB
 	/// [A=4] op {vC, vD, vE, vF}, inline@BBBB
 	/// [A=3] op {vC, vD, vE}, inline@BBBB
@@ -233,7 +209,7 @@ pub enum Instruction {
 
 	/// data: `AA|op BBBB CCCC`
 	///
-	/// ```
+	/// ```text
 	/// op {vCCCC .. vNNNN}, meth@BBBB
 	/// op {vCCCC .. vNNNN}, site@BBBB
 	/// op {vCCCC .. vNNNN}, type@BBBB
@@ -243,7 +219,7 @@ pub enum Instruction {
 	Instruction3rc(Opcode, (u8, u16, u16)),
 	/// data: `AA|op BBBB CCCC`
 	///
-	/// ```
+	/// ```text
 	/// op {vCCCC .. vNNNN}, vtaboff@BBBB
 	///
 	/// where NNNN = CCCC+AA-1, that is A determines the count 0..255, and C determines the first register
@@ -253,7 +229,7 @@ pub enum Instruction {
 	Instruction3rms(Opcode, (u8, u16, u16)),
 	/// data: `AA|op BBBB CCCC`
 	///
-	/// ```
+	/// ```text
 	/// op {vCCCC .. vNNNN}, inline@BBBB
 	///
 	/// where NNNN = CCCC+AA-1, that is A determines the count 0..255, and C determines the first register
@@ -264,7 +240,7 @@ pub enum Instruction {
 
 	/// data: `A|G|op BBBB F|E|D|C HHHH `
 	///
-	/// ```
+	/// ```text
 	/// [A=5] op {vC, vD, vE, vF, vG}, meth@BBBB, proto@HHHH
 	/// [A=4] op {vC, vD, vE, vF}, meth@BBBB, proto@HHHH
 	/// [A=3] op {vC, vD, vE}, meth@BBBB, proto@HHHH
@@ -277,7 +253,7 @@ pub enum Instruction {
 
 	/// data: `AA|op BBBB CCCC HHHH `
 	///
-	/// ```
+	/// ```text
 	/// op> {vCCCC .. vNNNN}, meth@BBBB, proto@HHHH
 	///
 	/// where NNNN = CCCC+AA-1, that is A determines the count 0..255, and C determines the first register
@@ -288,7 +264,7 @@ pub enum Instruction {
 
 	/// data: `AA|op BBBBlo BBBB BBBB BBBBhi`
 	///
-	/// ```
+	/// ```text
 	/// op vAA, #+BBBBBBBBBBBBBBBB
 	/// ```
 	///
@@ -315,40 +291,335 @@ pub enum Instruction {
 	},
 }
 
-#[cfg(not(feature = "trace"))]
-macro_rules! assert_unused_byte {
-	($parser:ident, $format:literal) => {{
-		let __unused = $parser.u8()?;
-		assert_eq!(
-			__unused, 0,
-			"expected unused {} byte to be 0, but got {}",
-			$format, __unused
+impl Instruction {
+	pub fn opcode(&self) -> Opcode {
+		use Instruction::*;
+
+		match self {
+			Instruction10x(op)
+			| Instruction12x(op, _)
+			| Instruction11n(op, _)
+			| Instruction11x(op, _)
+			| Instruction10t(op, _)
+			| Instruction20t(op, _)
+			| Instruction20bc(op, _)
+			| Instruction22x(op, _)
+			| Instruction21t(op, _)
+			| Instruction21s(op, _)
+			| Instruction21ih(op, _)
+			| Instruction21lh(op, _)
+			| Instruction21c(op, _)
+			| Instruction23x(op, _)
+			| Instruction22b(op, _)
+			| Instruction22t(op, _)
+			| Instruction22s(op, _)
+			| Instruction22c(op, _)
+			| Instruction22cs(op, _)
+			| Instruction30t(op, _)
+			| Instruction32x(op, _)
+			| Instruction31i(op, _)
+			| Instruction31t(op, _)
+			| Instruction31c(op, _)
+			| Instruction35c(op, _)
+			| Instruction35ms(op, _)
+			| Instruction35mi(op, _)
+			| Instruction3rc(op, _)
+			| Instruction3rms(op, _)
+			| Instruction3rmi(op, _)
+			| Instruction45cc(op, _)
+			| Instruction4rcc(op, _)
+			| Instruction51l(op, _) => *op,
+			PackedSwitchPayload { .. } => Opcode::PackedSwitchPayload,
+			SparseSwitchPayload { .. } => Opcode::SparseSwitchPayload,
+			FillArrayDataPayload { .. } => Opcode::ArrayPayload,
+		}
+	}
+
+	/// Number of 16-bit code units the instruction takes up, which is what
+	/// branch offsets and addresses are counted in.
+	pub fn code_units(&self) -> u32 {
+		match self {
+			Instruction::PackedSwitchPayload { targets, .. } => targets.len() as u32 * 2 + 4,
+			Instruction::SparseSwitchPayload { keys, .. } => keys.len() as u32 * 4 + 2,
+			Instruction::FillArrayDataPayload { data, .. } => (data.len() as u32).div_ceil(2) + 4,
+			insn => insn.opcode().format().size() as u32 / 2,
+		}
+	}
+
+	/// Registers the instruction operates on, in the order they are written
+	/// in. Lists of `35c`-like formats and ranges of `3rc`-like formats are
+	/// expanded, the second half of a wide register pair isn't listed.
+	pub fn registers(&self) -> Vec<u16> {
+		use Instruction::*;
+
+		match self {
+			Instruction11n(_, (a, _))
+			| Instruction21s(_, (a, _))
+			| Instruction21ih(_, (a, _))
+			| Instruction21lh(_, (a, _))
+			| Instruction21t(_, (a, _))
+			| Instruction21c(_, (a, _))
+			| Instruction31i(_, (a, _))
+			| Instruction31t(_, (a, _))
+			| Instruction31c(_, (a, _))
+			| Instruction51l(_, (a, _))
+			| Instruction11x(_, (a,)) => vec![*a as u16],
+			Instruction12x(_, (a, b))
+			| Instruction22b(_, (a, b, _))
+			| Instruction22t(_, (a, b, _))
+			| Instruction22s(_, (a, b, _))
+			| Instruction22c(_, (a, b, _))
+			| Instruction22cs(_, (a, b, _)) => vec![*a as u16, *b as u16],
+			Instruction22x(_, (aa, bbbb)) => vec![*aa as u16, *bbbb],
+			Instruction32x(_, (aaaa, bbbb)) => vec![*aaaa, *bbbb],
+			Instruction23x(_, (aa, bb, cc)) => vec![*aa as u16, *bb as u16, *cc as u16],
+			Instruction35c(_, (a, g, _, f, e, d, c))
+			| Instruction35ms(_, (a, g, _, f, e, d, c))
+			| Instruction35mi(_, (a, g, _, f, e, d, c))
+			| Instruction45cc(_, (a, g, _, f, e, d, c, _)) => [*c, *d, *e, *f, *g]
+				.iter()
+				.take(*a as usize)
+				.map(|&r| r as u16)
+				.collect(),
+			Instruction3rc(_, (aa, _, cccc))
+			| Instruction3rms(_, (aa, _, cccc))
+			| Instruction3rmi(_, (aa, _, cccc))
+			| Instruction4rcc(_, (aa, _, cccc, _)) => {
+				(0..*aa as u16).map(|i| cccc.wrapping_add(i)).collect()
+			}
+			Instruction10x(_)
+			| Instruction10t(..)
+			| Instruction20t(..)
+			| Instruction30t(..)
+			| Instruction20bc(..)
+			| PackedSwitchPayload { .. }
+			| SparseSwitchPayload { .. }
+			| FillArrayDataPayload { .. } => Vec::new(),
+		}
+	}
+
+	/// The literal of a `const` or `/lit` instruction, sign extended from
+	/// however many bits the format stores.
+	pub fn literal(&self) -> Option<i64> {
+		use Instruction::*;
+
+		Some(match self {
+			// the literal is the high nibble, shift it out and back to extend it
+			Instruction11n(_, (_, b)) => ((*b << 4) as i8 >> 4) as i64,
+			Instruction21s(_, (_, bbbb)) => *bbbb as i16 as i64,
+			Instruction21ih(_, (_, bbbb_0000)) => *bbbb_0000 as i64,
+			Instruction21lh(_, (_, bbbb_0000_0000_0000)) => *bbbb_0000_0000_0000,
+			Instruction22b(_, (_, _, cc)) => *cc as i8 as i64,
+			Instruction22s(_, (_, _, cccc)) => *cccc as i16 as i64,
+			Instruction31i(_, (_, bbbb_bbbb)) => *bbbb_bbbb as i32 as i64,
+			Instruction51l(_, (_, bbbb_bbbb_bbbb_bbbb)) => *bbbb_bbbb_bbbb_bbbb as i64,
+			_ => return None,
+		})
+	}
+
+	/// Offset of a branch in code units, relative to the instruction. For
+	/// `31t` instructions this is the offset of their payload.
+	pub fn branch_offset(&self) -> Option<i32> {
+		use Instruction::*;
+
+		Some(match self {
+			Instruction10t(_, (aa,)) => *aa as i32,
+			Instruction20t(_, (aaaa,)) => *aaaa as i32,
+			Instruction30t(_, (aaaa_aaaa,)) => *aaaa_aaaa,
+			Instruction21t(_, (_, bbbb)) => *bbbb as i32,
+			Instruction22t(_, (_, _, cccc)) => *cccc as i32,
+			Instruction31t(_, (_, bbbb_bbbb)) => *bbbb_bbbb,
+			_ => return None,
+		})
+	}
+
+	/// Address the instruction at `address` branches to, or where the payload
+	/// of a `31t` instruction is. Addresses are in code units, see
+	/// [`CodeItem::instructions_with_offsets`](crate::dex::types::id::CodeItem::instructions_with_offsets).
+	pub fn branch_target(&self, address: u32) -> Option<u32> {
+		self.branch_offset()
+			.map(|offset| address.wrapping_add_signed(offset))
+	}
+
+	/// Keys of a switch payload with the offsets of their targets, which are
+	/// relative to the `packed-switch`/`sparse-switch` instruction and not to
+	/// the payload.
+	pub fn switch_cases(&self) -> Result<Vec<(i32, i32)>> {
+		Ok(match self {
+			Instruction::PackedSwitchPayload {
+				first_key, targets, ..
+			} => targets
+				.iter()
+				.enumerate()
+				.map(|(i, target)| (first_key.wrapping_add(i as i32), *target))
+				.collect(),
+			Instruction::SparseSwitchPayload { keys, targets, .. } => {
+				keys.iter().copied().zip(targets.iter().copied()).collect()
+			}
+			insn => bail!("{} isn't a switch payload", insn.opcode()),
+		})
+	}
+
+	/// Elements of a `fill-array-data` payload, which are stored little
+	/// endian.
+	pub fn array_elements(&self) -> Result<ArrayElements> {
+		let (element_width, data) = match self {
+			Instruction::FillArrayDataPayload {
+				element_width,
+				data,
+				..
+			} => (*element_width as usize, data),
+			insn => bail!("{} isn't an array payload", insn.opcode()),
+		};
+		ensure!(
+			matches!(element_width, 1 | 2 | 4 | 8),
+			"array elements can't be {} bytes wide",
+			element_width
 		);
-	}};
+		let elements = data.chunks_exact(element_width);
+		Ok(match element_width {
+			1 => ArrayElements::Byte(data.iter().map(|&b| b as i8).collect()),
+			2 => ArrayElements::Short(elements.map(|e| i16::from_le_bytes([e[0], e[1]])).collect()),
+			4 => ArrayElements::Int(
+				elements
+					.map(|e| i32::from_le_bytes([e[0], e[1], e[2], e[3]]))
+					.collect(),
+			),
+			_ => ArrayElements::Long(
+				elements
+					.map(|e| i64::from_le_bytes(e.try_into().unwrap()))
+					.collect(),
+			),
+		})
+	}
+
+	/// The kind and index of the item the instruction references, like the
+	/// string of a `const-string` or the method of an `invoke-virtual`.
+	pub fn reference(&self) -> Option<(ReferenceType, u32)> {
+		use Instruction::*;
+
+		let (op, idx) = match self {
+			Instruction21c(op, (_, bbbb))
+			| Instruction22c(op, (_, _, bbbb))
+			| Instruction35c(op, (_, _, bbbb, ..))
+			| Instruction3rc(op, (_, bbbb, _))
+			| Instruction45cc(op, (_, _, bbbb, ..))
+			| Instruction4rcc(op, (_, bbbb, ..)) => (op, *bbbb as u32),
+			Instruction31c(op, (_, bbbb_bbbb)) => (op, *bbbb_bbbb),
+			_ => return None,
+		};
+		Some((op.reference_type(), idx)).filter(|(kind, _)| is_reference(*kind))
+	}
+
+	/// The second reference of `invoke-polymorphic`, the proto of the call.
+	pub fn reference_2(&self) -> Option<(ReferenceType, u32)> {
+		use Instruction::*;
+
+		let (op, idx) = match self {
+			Instruction45cc(op, (.., hhhh)) | Instruction4rcc(op, (.., hhhh)) => (op, *hhhh as u32),
+			_ => return None,
+		};
+		Some((op.reference_type_2(), idx)).filter(|(kind, _)| is_reference(*kind))
+	}
+
+	/// Replaces the indices the instruction references items by with what `f`
+	/// returns for them, for moving code to a file with different sections.
+	pub fn map_references(
+		&mut self,
+		mut f: impl FnMut(ReferenceType, u32) -> Result<u32>,
+	) -> Result<()> {
+		let mut short = |kind: ReferenceType, idx: &mut u16| -> Result<()> {
+			let new = f(kind, *idx as u32)?;
+			*idx = u16::try_from(new)
+				.map_err(|_| eyre!("{:?} index {} doesn't fit into 16 bits", kind, new))?;
+			Ok(())
+		};
+
+		use Instruction::*;
+		match self {
+			Instruction21c(op, (_, bbbb))
+			| Instruction35c(op, (_, _, bbbb, ..))
+			| Instruction3rc(op, (_, bbbb, _)) => short(op.reference_type(), bbbb)?,
+			Instruction22c(op, (_, _, cccc)) => short(op.reference_type(), cccc)?,
+			Instruction45cc(op, (_, _, bbbb, _, _, _, _, hhhh))
+			| Instruction4rcc(op, (_, bbbb, _, hhhh)) => {
+				short(op.reference_type(), bbbb)?;
+				short(op.reference_type_2(), hhhh)?;
+			}
+			Instruction31c(op, (_, bbbb_bbbb)) => {
+				*bbbb_bbbb = f(op.reference_type(), *bbbb_bbbb)?;
+			}
+			_ => {}
+		}
+		Ok(())
+	}
+
+	/// Encodes the instruction into the 16-bit code units it is stored as,
+	/// the reverse of parsing it.
+	pub fn encode(&self) -> Result<Vec<u16>> {
+		let mut writer = FileWriter::default();
+		writer.write_item(self)?;
+		Ok(writer
+			.into_inner()
+			.chunks_exact(2)
+			.map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+			.collect())
+	}
 }
 
-#[cfg(feature = "trace")]
-macro_rules! assert_unused_byte {
-	($parser:ident, $format:literal) => {{
-		let __unused = $parser.u8()?;
-		color_eyre::eyre::ensure!(
-			__unused == 0,
-			"expected unused {} byte to be 0, but got {}",
-			$format,
-			__unused
-		);
+/// Elements of a `fill-array-data` payload, by their width.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ArrayElements {
+	Byte(Vec<i8>),
+	Short(Vec<i16>),
+	Int(Vec<i32>),
+	Long(Vec<i64>),
+}
+
+impl ArrayElements {
+	/// The elements sign extended to 64 bits, whatever their width.
+	pub fn to_i64s(&self) -> Vec<i64> {
+		match self {
+			ArrayElements::Byte(elements) => elements.iter().map(|&e| e as i64).collect(),
+			ArrayElements::Short(elements) => elements.iter().map(|&e| e as i64).collect(),
+			ArrayElements::Int(elements) => elements.iter().map(|&e| e as i64).collect(),
+			ArrayElements::Long(elements) => elements.clone(),
+		}
+	}
+}
+
+fn is_reference(kind: ReferenceType) -> bool {
+	!matches!(kind, ReferenceType::None | ReferenceType::_Undef)
+}
+
+/// Reads the unused byte after the opcode, which has to be 0 unless the
+/// parser is lenient.
+macro_rules! check_unused_byte {
+	($parser:ident, $op:ident, $offset:ident) => {{
+		let value = $parser.u8()?;
+		if value != 0 {
+			if !$parser.is_lenient() {
+				bail!(ParseError::unused_byte($op, $offset, value));
+			}
+			warn!(offset = $offset, value, "unused byte of `{}` isn't 0", $op);
+			$parser.warning(ParseError::unused_byte($op, $offset, value));
+		}
 	}};
 }
 
 impl Parse for Instruction {
 	#[cfg_attr(feature = "trace", instrument(skip(parser), fields(op, offset = parser.get_offset())))]
 	fn parse<P: Parser>(parser: &mut P) -> Result<Self> {
+		let offset = parser.get_offset();
 		let op = {
 			let mut opcode_value = parser.u8()? as u16;
-			// noop could hint at one of the special payloads
+			// noop could hint at one of the special payloads, any other byte
+			// after it is the unused one
 			if opcode_value == 0 {
-				opcode_value = (parser.u8()? as u16) << 8;
-				if opcode_value == 0 {
+				let payload = (parser.u8()? as u16) << 8;
+				if payload != 0 && VALUE_TO_OPCODE.contains_key(&payload) {
+					opcode_value = payload;
+				} else {
 					parser.seek(std::io::SeekFrom::Current(-1))?;
 				}
 			}
@@ -364,7 +635,7 @@ impl Parse for Instruction {
 
 		Ok(match op.format() {
 			Format::Format10x => {
-				assert_unused_byte!(parser, "10x");
+				check_unused_byte!(parser, op, offset);
 				Instruction::Instruction10x(op)
 			}
 
@@ -382,13 +653,13 @@ impl Parse for Instruction {
 				Instruction::Instruction11x(op, (aa,))
 			}
 			Format::Format10t => {
-				let aa = parser.u8()?;
+				let aa = parser.u8()? as i8;
 				Instruction::Instruction10t(op, (aa,))
 			}
 
 			Format::Format20t => {
-				assert_unused_byte!(parser, "20t");
-				let aaaa = parser.u16()?;
+				check_unused_byte!(parser, op, offset);
+				let aaaa = parser.i16()?;
 
 				Instruction::Instruction20t(op, (aaaa,))
 			}
@@ -408,7 +679,7 @@ impl Parse for Instruction {
 			}
 			Format::Format21t => {
 				let aa = parser.u8()?;
-				let bbbb = parser.u16()?;
+				let bbbb = parser.i16()?;
 
 				Instruction::Instruction21t(op, (aa, bbbb))
 			}
@@ -422,15 +693,13 @@ impl Parse for Instruction {
 				let aa = parser.u8()?;
 				let bbbb = parser.u16()?;
 
-				Instruction::Instruction21h(op, (aa, bbbb))
-				// Instruction::Instruction21ih(op, (0, 0))
+				Instruction::Instruction21ih(op, (aa, ((bbbb as u32) << 16) as i32))
 			}
 			Format::Format21lh => {
 				let aa = parser.u8()?;
 				let bbbb = parser.u16()?;
 
-				Instruction::Instruction21h(op, (aa, bbbb))
-				// Instruction::Instruction21lh(op, (0, 0, 0))
+				Instruction::Instruction21lh(op, (aa, ((bbbb as u64) << 48) as i64))
 			}
 			Format::Format21c => {
 				let aa = parser.u8()?;
@@ -456,7 +725,7 @@ impl Parse for Instruction {
 
 			Format::Format22t => {
 				let (a, b) = parser.split_u8()?;
-				let cccc = parser.u16()?;
+				let cccc = parser.i16()?;
 
 				Instruction::Instruction22t(op, (a, b, cccc))
 			}
@@ -480,14 +749,14 @@ impl Parse for Instruction {
 			}
 
 			Format::Format30t => {
-				assert_unused_byte!(parser, "30t");
-				let aaaa_aaaa = parser.u32()?;
+				check_unused_byte!(parser, op, offset);
+				let aaaa_aaaa = parser.i32()?;
 
 				Instruction::Instruction30t(op, (aaaa_aaaa,))
 			}
 
 			Format::Format32x => {
-				assert_unused_byte!(parser, "32x");
+				check_unused_byte!(parser, op, offset);
 				let aaaa = parser.u16()?;
 				let bbbb = parser.u16()?;
 
@@ -502,7 +771,7 @@ impl Parse for Instruction {
 			}
 			Format::Format31t => {
 				let aa = parser.u8()?;
-				let bbbb_bbbb = parser.u32()?;
+				let bbbb_bbbb = parser.i32()?;
 
 				Instruction::Instruction31t(op, (aa, bbbb_bbbb))
 			}
@@ -514,26 +783,26 @@ impl Parse for Instruction {
 			}
 
 			Format::Format35c => {
-				let (a, g) = parser.split_u8()?;
+				let (g, a) = parser.split_u8()?;
 				let bbbb = parser.u16()?;
-				let (f, e) = parser.split_u8()?;
-				let (d, c) = parser.split_u8()?;
+				let (c, d) = parser.split_u8()?;
+				let (e, f) = parser.split_u8()?;
 
 				Instruction::Instruction35c(op, (a, g, bbbb, f, e, d, c))
 			}
 			Format::Format35ms => {
-				let (a, g) = parser.split_u8()?;
+				let (g, a) = parser.split_u8()?;
 				let bbbb = parser.u16()?;
-				let (f, e) = parser.split_u8()?;
-				let (d, c) = parser.split_u8()?;
+				let (c, d) = parser.split_u8()?;
+				let (e, f) = parser.split_u8()?;
 
 				Instruction::Instruction35ms(op, (a, g, bbbb, f, e, d, c))
 			}
 			Format::Format35mi => {
-				let (a, g) = parser.split_u8()?;
+				let (g, a) = parser.split_u8()?;
 				let bbbb = parser.u16()?;
-				let (f, e) = parser.split_u8()?;
-				let (d, c) = parser.split_u8()?;
+				let (c, d) = parser.split_u8()?;
+				let (e, f) = parser.split_u8()?;
 
 				Instruction::Instruction35mi(op, (a, g, bbbb, f, e, d, c))
 			}
@@ -561,10 +830,10 @@ impl Parse for Instruction {
 			}
 
 			Format::Format45cc => {
-				let (a, g) = parser.split_u8()?;
+				let (g, a) = parser.split_u8()?;
 				let bbbb = parser.u16()?;
-				let (f, e) = parser.split_u8()?;
-				let (d, c) = parser.split_u8()?;
+				let (c, d) = parser.split_u8()?;
+				let (e, f) = parser.split_u8()?;
 				let hhhh = parser.u16()?;
 
 				Instruction::Instruction45cc(op, (a, g, bbbb, f, e, d, c, hhhh))
@@ -614,7 +883,7 @@ impl Parse for Instruction {
 				let data = parser.parse_list(element_width as u32 * size)?;
 				// > Note: The total number of code units for an instance of this table is (size * element_width + 1) / 2 + 4.
 				// this is padding?
-				if (element_width as u32 * size) % 2 != 0 {
+				if !(element_width as u32 * size).is_multiple_of(2) {
 					parser.u8()?;
 				}
 
@@ -629,3 +898,179 @@ impl Parse for Instruction {
 		})
 	}
 }
+
+impl Write for Instruction {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		use Instruction::*;
+
+		match self {
+			PackedSwitchPayload { .. }
+			| SparseSwitchPayload { .. }
+			| FillArrayDataPayload { .. } => {
+				writer.u16(self.opcode().value())?;
+			}
+			_ => writer.u8(self.opcode().value() as u8)?,
+		}
+
+		match self {
+			Instruction10x(_) => writer.u8(0)?,
+
+			Instruction12x(_, (a, b)) | Instruction11n(_, (a, b)) => writer.join_u8(*a, *b)?,
+
+			Instruction11x(_, (aa,)) => writer.u8(*aa)?,
+			Instruction10t(_, (aa,)) => writer.u8(*aa as u8)?,
+
+			Instruction20t(_, (aaaa,)) => {
+				writer.u8(0)?;
+				writer.i16(*aaaa)?;
+			}
+
+			Instruction20bc(_, (aa, bbbb))
+			| Instruction22x(_, (aa, bbbb))
+			| Instruction21s(_, (aa, bbbb))
+			| Instruction21c(_, (aa, bbbb)) => {
+				writer.u8(*aa)?;
+				writer.u16(*bbbb)?;
+			}
+
+			Instruction21t(_, (aa, bbbb)) => {
+				writer.u8(*aa)?;
+				writer.i16(*bbbb)?;
+			}
+
+			Instruction21ih(_, (aa, bbbb_0000)) => {
+				ensure!(
+					bbbb_0000 & 0xffff == 0,
+					"the low 16 bits of the literal {:#x} aren't 0",
+					bbbb_0000
+				);
+				writer.u8(*aa)?;
+				writer.u16((*bbbb_0000 >> 16) as u16)?;
+			}
+			Instruction21lh(_, (aa, bbbb_0000_0000_0000)) => {
+				ensure!(
+					bbbb_0000_0000_0000 & 0xffff_ffff_ffff == 0,
+					"the low 48 bits of the literal {:#x} aren't 0",
+					bbbb_0000_0000_0000
+				);
+				writer.u8(*aa)?;
+				writer.u16((*bbbb_0000_0000_0000 >> 48) as u16)?;
+			}
+
+			Instruction23x(_, (aa, bb, cc)) | Instruction22b(_, (aa, bb, cc)) => {
+				writer.u8(*aa)?;
+				writer.u8(*bb)?;
+				writer.u8(*cc)?;
+			}
+
+			Instruction22t(_, (a, b, cccc)) => {
+				writer.join_u8(*a, *b)?;
+				writer.i16(*cccc)?;
+			}
+
+			Instruction22s(_, (a, b, cccc))
+			| Instruction22c(_, (a, b, cccc))
+			| Instruction22cs(_, (a, b, cccc)) => {
+				writer.join_u8(*a, *b)?;
+				writer.u16(*cccc)?;
+			}
+
+			Instruction30t(_, (aaaa_aaaa,)) => {
+				writer.u8(0)?;
+				writer.i32(*aaaa_aaaa)?;
+			}
+
+			Instruction32x(_, (aaaa, bbbb)) => {
+				writer.u8(0)?;
+				writer.u16(*aaaa)?;
+				writer.u16(*bbbb)?;
+			}
+
+			Instruction31i(_, (aa, bbbb_bbbb)) | Instruction31c(_, (aa, bbbb_bbbb)) => {
+				writer.u8(*aa)?;
+				writer.u32(*bbbb_bbbb)?;
+			}
+			Instruction31t(_, (aa, bbbb_bbbb)) => {
+				writer.u8(*aa)?;
+				writer.i32(*bbbb_bbbb)?;
+			}
+
+			Instruction35c(_, (a, g, bbbb, f, e, d, c))
+			| Instruction35ms(_, (a, g, bbbb, f, e, d, c))
+			| Instruction35mi(_, (a, g, bbbb, f, e, d, c)) => {
+				writer.join_u8(*g, *a)?;
+				writer.u16(*bbbb)?;
+				writer.join_u8(*c, *d)?;
+				writer.join_u8(*e, *f)?;
+			}
+
+			Instruction3rc(_, (aa, bbbb, cccc))
+			| Instruction3rms(_, (aa, bbbb, cccc))
+			| Instruction3rmi(_, (aa, bbbb, cccc)) => {
+				writer.u8(*aa)?;
+				writer.u16(*bbbb)?;
+				writer.u16(*cccc)?;
+			}
+
+			Instruction45cc(_, (a, g, bbbb, f, e, d, c, hhhh)) => {
+				writer.join_u8(*g, *a)?;
+				writer.u16(*bbbb)?;
+				writer.join_u8(*c, *d)?;
+				writer.join_u8(*e, *f)?;
+				writer.u16(*hhhh)?;
+			}
+
+			Instruction4rcc(_, (aa, bbbb, cccc, hhhh)) => {
+				writer.u8(*aa)?;
+				writer.u16(*bbbb)?;
+				writer.u16(*cccc)?;
+				writer.u16(*hhhh)?;
+			}
+
+			Instruction51l(_, (aa, bbbb_bbbb_bbbb_bbbb)) => {
+				writer.u8(*aa)?;
+				writer.u64(*bbbb_bbbb_bbbb_bbbb)?;
+			}
+
+			PackedSwitchPayload {
+				first_key, targets, ..
+			} => {
+				writer.u16(targets.len() as u16)?;
+				writer.i32(*first_key)?;
+				writer.write_list(targets)?;
+			}
+			SparseSwitchPayload { keys, targets, .. } => {
+				ensure!(
+					keys.len() == targets.len(),
+					"sparse switch has {} keys but {} targets",
+					keys.len(),
+					targets.len()
+				);
+				writer.u16(keys.len() as u16)?;
+				writer.write_list(keys)?;
+				writer.write_list(targets)?;
+			}
+			FillArrayDataPayload {
+				element_width,
+				size,
+				data,
+			} => {
+				ensure!(
+					*element_width as usize * *size as usize == data.len(),
+					"array data has {} bytes but should have {} elements of width {}",
+					data.len(),
+					size,
+					element_width
+				);
+				writer.u16(*element_width)?;
+				writer.u32(*size)?;
+				writer.bytes(data)?;
+				if !data.len().is_multiple_of(2) {
+					writer.u8(0)?;
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
@@ -269,7 +269,9 @@ pub enum Opcode {
 		flags = "OpcodeFlags::CAN_CONTINUE | OpcodeFlags::SETS_REGISTER"
 	)]
 	Const16,
+	/// Move the given literal value into the specified register.
 	///
+	/// `const vAA, #+BBBBBBBB`
 	#[enum_values(
 		value = "0x14",
 		name = "const",
@@ -2084,6 +2086,7 @@ pub enum Opcode {
 	ConstMethodType,
 }
 
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
 pub enum ReferenceType {
 	String       = 0,
 	Type         = 1,
@@ -2093,17 +2096,13 @@ pub enum ReferenceType {
 	CallSite     = 5,
 	MethodHandle = 6,
 	None         = 7,
+	#[default]
 	_Undef       = -1,
 }
 
-impl Default for ReferenceType {
-	fn default() -> Self {
-		ReferenceType::_Undef
-	}
-}
-
 lazy_static! {
 	pub static ref VALUE_TO_OPCODE: HashMap<u16, Opcode> = Opcode::gen_value_map();
+	pub static ref NAME_TO_OPCODE: HashMap<String, Opcode> = Opcode::gen_name_map();
 }
 
 impl Opcode {
@@ -2115,6 +2114,19 @@ impl Opcode {
 		map
 	}
 
+	pub fn gen_name_map() -> HashMap<String, Opcode> {
+		let mut map = HashMap::new();
+		for opcode in Self::all() {
+			map.insert(opcode.name(), opcode);
+		}
+		map
+	}
+
+	/// Looks up an opcode by its smali name, the reverse of [`Opcode::name`].
+	pub fn from_name(name: &str) -> Option<Opcode> {
+		NAME_TO_OPCODE.get(name).copied()
+	}
+
 	pub fn all() -> Vec<Opcode> {
 		use Opcode::*;
 
@@ -0,0 +1,943 @@
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	convert::TryFrom,
+};
+
+use eyre::{bail, ensure, eyre, Result, WrapErr};
+
+use crate::dex::{
+	asm::opcode::{Opcode, ReferenceType},
+	smali::assembler::Pool,
+	types::{
+		access::HiddenapiFlags,
+		debug::DebugInfo,
+		descriptor::TypeDescriptor,
+		file::DexFile,
+		header::{EndianConstant, Header, HEADER_SIZE},
+		id::*,
+		map::{MapList, TypeCode},
+		refs::{Idx, Ref, NO_INDEX},
+		Annotations,
+		Class,
+		ClassData,
+		Field,
+		FieldId,
+		Method,
+		MethodId,
+		Proto,
+	},
+};
+
+/// return type, parameters
+type ProtoKey = (TypeDescriptor, Vec<TypeDescriptor>);
+/// class, name, type
+type FieldKey = (TypeDescriptor, String, TypeDescriptor);
+/// class, name, proto
+type MethodKey = (TypeDescriptor, String, ProtoKey);
+
+/// Lays assembled [`Class`]es out as a new [`DexFile`], the reverse of
+/// resolving them.
+///
+/// Indices in the code and static values of the classes point into the
+/// [`Pool`] they were assembled with, they are renumbered to the sections of
+/// the new file, which are sorted the way the format requires.
+pub struct DexBuilder<'a> {
+	pool: &'a Pool,
+}
+
+impl<'a> DexBuilder<'a> {
+	pub fn new(pool: &'a Pool) -> Self {
+		DexBuilder { pool }
+	}
+
+	pub fn build(&self, classes: &[Class]) -> Result<DexFile> {
+		let mut items = Items::default();
+		items.add_pool(self.pool);
+		classes.iter().for_each(|class| items.add_class(class));
+
+		let mut builder = Builder {
+			pool:        self.pool,
+			ids:         Ids::new(items),
+			file:        empty_file(format_version(classes)),
+			next_offset: HEADER_SIZE,
+			type_lists:  HashMap::new(),
+			hiddenapi:   Vec::new(),
+		};
+		builder.ids()?;
+		for class in class_order(classes)? {
+			builder
+				.class_def(class)
+				.wrap_err_with(|| format!("building {}", class.name))?;
+		}
+		builder.hiddenapi_class_data();
+		Ok(builder.file)
+	}
+}
+
+/// Everything the file has to contain, before it is sorted.
+#[derive(Default)]
+struct Items {
+	strings: HashSet<String>,
+	types:   HashSet<TypeDescriptor>,
+	protos:  HashSet<ProtoKey>,
+	fields:  HashSet<FieldKey>,
+	methods: HashSet<MethodKey>,
+}
+
+impl Items {
+	fn add_pool(&mut self, pool: &Pool) {
+		self.strings.extend(pool.strings.iter().cloned());
+		pool.types.iter().for_each(|typ| self.add_type(typ));
+		pool.protos.iter().for_each(|proto| self.add_proto(proto));
+		pool.fields
+			.iter()
+			.for_each(|field| self.add_field(field_key(field)));
+		pool.methods
+			.iter()
+			.for_each(|method| self.add_method(method));
+	}
+
+	fn add_class(&mut self, class: &Class) {
+		self.add_type(&class.name);
+		self.add_type(&class.superclass);
+		class
+			.interfaces
+			.iter()
+			.flatten()
+			.for_each(|interface| self.add_type(interface));
+		self.strings.extend(class.source_file.iter().cloned());
+
+		// members can be annotated without being defined by the class
+		if let Some(annotations) = &class.annotations {
+			for (field, _) in annotations.fields.iter() {
+				self.add_field(field_key(field));
+			}
+			let methods = annotations.methods.iter().map(|(method, _)| method);
+			let parameters = annotations.parameters.iter().map(|(method, _)| method);
+			methods
+				.chain(parameters)
+				.for_each(|method| self.add_method(method));
+		}
+
+		let class_data = match &class.class_data {
+			Some(class_data) => class_data,
+			None => return,
+		};
+		for field in class_data
+			.static_fields
+			.iter()
+			.chain(class_data.instance_fields.iter())
+		{
+			self.add_field(field_key(&field.id));
+		}
+		for method in class_data
+			.direct_methods
+			.iter()
+			.chain(class_data.virtual_methods.iter())
+		{
+			self.add_method(&method.id);
+			if let Some(debug_info) = &method.debug_info {
+				self.add_debug_info(debug_info);
+			}
+		}
+	}
+
+	fn add_type(&mut self, typ: &TypeDescriptor) {
+		self.strings.insert(typ.to_string());
+		self.types.insert(typ.clone());
+	}
+
+	fn add_proto(&mut self, proto: &Proto) {
+		let key = proto_key(proto);
+		self.strings.insert(Proto::shorty_of(&key.0, &key.1));
+		self.add_type(&key.0);
+		key.1.iter().for_each(|typ| self.add_type(typ));
+		self.protos.insert(key);
+	}
+
+	fn add_field(&mut self, key: FieldKey) {
+		self.add_type(&key.0);
+		self.strings.insert(key.1.clone());
+		self.add_type(&key.2);
+		self.fields.insert(key);
+	}
+
+	fn add_method(&mut self, method: &MethodId) {
+		self.add_type(&method.class);
+		self.strings.insert(method.name.clone());
+		self.add_proto(&method.proto);
+		self.methods.insert(method_key(method));
+	}
+
+	fn add_debug_info(&mut self, debug_info: &DebugInfo) {
+		let strings = debug_info
+			.parameter_names
+			.iter()
+			.chain(debug_info.source_files.iter().map(|(_, file)| file))
+			.chain(debug_info.locals.iter().map(|local| &local.name))
+			.chain(debug_info.locals.iter().map(|local| &local.signature))
+			.flatten()
+			.cloned();
+		self.strings.extend(strings);
+		for typ in debug_info
+			.locals
+			.iter()
+			.filter_map(|local| local.typ.as_ref())
+		{
+			self.add_type(typ);
+		}
+	}
+}
+
+/// The items in their final order, by their index in the file.
+struct Ids {
+	strings: Vec<String>,
+	types:   Vec<TypeDescriptor>,
+	protos:  Vec<ProtoKey>,
+	fields:  Vec<FieldKey>,
+	methods: Vec<MethodKey>,
+
+	string_idx: HashMap<String, u32>,
+	type_idx:   HashMap<TypeDescriptor, u32>,
+	proto_idx:  HashMap<ProtoKey, u32>,
+	field_idx:  HashMap<FieldKey, u32>,
+	method_idx: HashMap<MethodKey, u32>,
+}
+
+impl Ids {
+	/// Sorts the items like the format wants them: strings by their UTF-16
+	/// code units and everything else by the indices of its parts.
+	fn new(items: Items) -> Self {
+		fn indices<T: Clone + Eq + std::hash::Hash>(items: &[T]) -> HashMap<T, u32> {
+			items
+				.iter()
+				.enumerate()
+				.map(|(idx, item)| (item.clone(), idx as u32))
+				.collect()
+		}
+
+		let mut strings: Vec<String> = items.strings.into_iter().collect();
+		strings.sort_unstable_by(|a, b| a.encode_utf16().cmp(b.encode_utf16()));
+		let string_idx = indices(&strings);
+
+		let mut types: Vec<TypeDescriptor> = items.types.into_iter().collect();
+		types.sort_unstable_by_key(|typ| string_idx[&typ.to_string()]);
+		let type_idx = indices(&types);
+
+		let mut protos: Vec<ProtoKey> = items.protos.into_iter().collect();
+		protos.sort_unstable_by_key(|(return_type, parameters)| {
+			let parameters: Vec<u32> = parameters.iter().map(|typ| type_idx[typ]).collect();
+			(type_idx[return_type], parameters)
+		});
+		let proto_idx = indices(&protos);
+
+		let mut fields: Vec<FieldKey> = items.fields.into_iter().collect();
+		fields.sort_unstable_by_key(|(class, name, typ)| {
+			(type_idx[class], string_idx[name], type_idx[typ])
+		});
+		let field_idx = indices(&fields);
+
+		let mut methods: Vec<MethodKey> = items.methods.into_iter().collect();
+		methods.sort_unstable_by_key(|(class, name, proto)| {
+			(type_idx[class], string_idx[name], proto_idx[proto])
+		});
+		let method_idx = indices(&methods);
+
+		Ids {
+			strings,
+			types,
+			protos,
+			fields,
+			methods,
+			string_idx,
+			type_idx,
+			proto_idx,
+			field_idx,
+			method_idx,
+		}
+	}
+
+	fn string(&self, string: &str) -> u32 {
+		self.string_idx[string]
+	}
+
+	fn typ(&self, typ: &TypeDescriptor) -> u32 {
+		self.type_idx[typ]
+	}
+
+	/// Index in the file of the item at `idx` in the pool.
+	fn reference(&self, pool: &Pool, kind: ReferenceType, idx: u32) -> Result<u32> {
+		fn get<T>(items: &[T], kind: ReferenceType, idx: u32) -> Result<&T> {
+			items
+				.get(idx as usize)
+				.ok_or_else(|| eyre!("{:?} index {} is out of bounds of the pool", kind, idx))
+		}
+
+		Ok(match kind {
+			ReferenceType::String => self.string(get(&pool.strings, kind, idx)?),
+			ReferenceType::Type => self.typ(get(&pool.types, kind, idx)?),
+			ReferenceType::MethodProto => self.proto_idx[&proto_key(get(&pool.protos, kind, idx)?)],
+			ReferenceType::Field => self.field_idx[&field_key(get(&pool.fields, kind, idx)?)],
+			ReferenceType::Method => self.method_idx[&method_key(get(&pool.methods, kind, idx)?)],
+			kind => bail!("{:?} references can't be written yet", kind),
+		})
+	}
+}
+
+struct Builder<'a> {
+	pool:        &'a Pool,
+	ids:         Ids,
+	file:        DexFile,
+	/// Items get made up offsets in the order they are added, see
+	/// [`DexFile::item_offsets`].
+	next_offset: u32,
+	/// Type lists are shared between everything that has the same types.
+	type_lists:  HashMap<Vec<u32>, u32>,
+	/// Hidden API flags of the members of each class def, `None` where they
+	/// have none.
+	hiddenapi:   Vec<Vec<Option<HiddenapiFlags>>>,
+}
+
+impl Builder<'_> {
+	fn offset(&mut self, typ: TypeCode) -> u32 {
+		let offset = self.next_offset;
+		self.next_offset += 1;
+		self.file.item_offsets.entry(typ).or_default().push(offset);
+		offset
+	}
+
+	fn ids(&mut self) -> Result<()> {
+		for string in std::mem::take(&mut self.ids.strings) {
+			self.offset(TypeCode::StringIdItem);
+			let string_data_off = self.offset(TypeCode::StringDataItem);
+			self.file.string_ids.push(StringIdItem {
+				string_data_off: Ref::new(string_data_off),
+			});
+			self.file.string_data.push(StringDataItem::new(&string));
+		}
+
+		for typ in std::mem::take(&mut self.ids.types) {
+			self.offset(TypeCode::TypeIdItem);
+			self.file.type_ids.push(TypeIdItem {
+				descriptor_idx: Idx::new(self.ids.string(&typ.to_string()) as usize),
+			});
+		}
+
+		for (return_type, parameters) in std::mem::take(&mut self.ids.protos) {
+			let shorty = Proto::shorty_of(&return_type, &parameters);
+			let parameters = self.type_list(&parameters)?;
+			self.offset(TypeCode::ProtoIdItem);
+			self.file.proto_ids.push(ProtoIdItem {
+				shorty_idx:      Idx::new(self.ids.string(&shorty) as usize),
+				return_type_idx: Idx::new(self.ids.typ(&return_type) as usize),
+				parameters:      Ref::new(parameters),
+			});
+		}
+
+		for (class, name, typ) in std::mem::take(&mut self.ids.fields) {
+			self.offset(TypeCode::FieldIdItem);
+			self.file.field_ids.push(FieldIdItem {
+				class_idx: Idx::new(self.ids.typ(&class) as usize),
+				type_idx:  Idx::new(self.ids.typ(&typ) as usize),
+				name_idx:  Idx::new(self.ids.string(&name) as usize),
+			});
+		}
+
+		for (class, name, proto) in std::mem::take(&mut self.ids.methods) {
+			self.offset(TypeCode::MethodIdItem);
+			self.file.method_ids.push(MethodIdItem {
+				class_idx: Idx::new(self.ids.typ(&class) as usize),
+				proto_idx: Idx::new(self.ids.proto_idx[&proto] as usize),
+				name_idx:  Idx::new(self.ids.string(&name) as usize),
+			});
+		}
+
+		Ok(())
+	}
+
+	/// Offset of a list with `types`, `0` for no types.
+	fn type_list(&mut self, types: &[TypeDescriptor]) -> Result<u32> {
+		if types.is_empty() {
+			return Ok(0);
+		}
+		let indices: Vec<u32> = types.iter().map(|typ| self.ids.typ(typ)).collect();
+		if let Some(&offset) = self.type_lists.get(&indices) {
+			return Ok(offset);
+		}
+
+		let offset = self.offset(TypeCode::TypeList);
+		self.file.type_lists.push(TypeList {
+			size: indices.len() as u32,
+			list: indices
+				.iter()
+				.map(|&idx| TypeItem {
+					type_idx: Idx::new(idx as usize),
+				})
+				.collect(),
+		});
+		self.type_lists.insert(indices, offset);
+		Ok(offset)
+	}
+
+	fn class_def(&mut self, class: &Class) -> Result<()> {
+		let interfaces_off = self.type_list(class.interfaces.as_deref().unwrap_or_default())?;
+		let (class_data_off, static_values_off) = match &class.class_data {
+			Some(class_data) => self.class_data(class_data)?,
+			None => {
+				self.hiddenapi.push(Vec::new());
+				(0, 0)
+			}
+		};
+
+		let annotations_off = match &class.annotations {
+			Some(annotations) => self.annotations_directory(annotations)?,
+			None => 0,
+		};
+
+		self.offset(TypeCode::ClassDefItem);
+		self.file.class_defs.push(ClassDefItem {
+			class_idx:         Idx::new(self.ids.typ(&class.name) as usize),
+			access_flags:      class.access_flags.bits(),
+			superclass_idx:    Idx::new(self.ids.typ(&class.superclass) as usize),
+			interfaces_off:    Ref::new(interfaces_off),
+			source_file_idx:   Idx::new(
+				class
+					.source_file
+					.as_ref()
+					.map_or(NO_INDEX, |file| self.ids.string(file) as usize),
+			),
+			annotations_off:   Ref::new(annotations_off),
+			class_data_off:    Ref::new(class_data_off),
+			static_values_off: Ref::new(static_values_off),
+		});
+		Ok(())
+	}
+
+	/// Offsets of the class data and of the static values, which are `0`
+	/// if all static fields start out with their default value.
+	fn class_data(&mut self, class_data: &ClassData) -> Result<(u32, u32)> {
+		let static_fields = self.sorted_fields(&class_data.static_fields)?;
+		let instance_fields = self.sorted_fields(&class_data.instance_fields)?;
+		let direct_methods = self.sorted_methods(&class_data.direct_methods)?;
+		let virtual_methods = self.sorted_methods(&class_data.virtual_methods)?;
+
+		let static_values_off = self.static_values(&static_fields)?;
+		let item = ClassDataItem {
+			static_fields_size:   (static_fields.len() as u32).into(),
+			instance_fields_size: (instance_fields.len() as u32).into(),
+			direct_methods_size:  (direct_methods.len() as u32).into(),
+			virtual_methods_size: (virtual_methods.len() as u32).into(),
+			static_fields:        encode_fields(&static_fields),
+			instance_fields:      encode_fields(&instance_fields),
+			direct_methods:       self.encode_methods(&direct_methods)?,
+			virtual_methods:      self.encode_methods(&virtual_methods)?,
+		};
+
+		let class_data_off = self.offset(TypeCode::ClassDataItem);
+		self.file.class_data.push(item);
+
+		let fields = static_fields.iter().chain(instance_fields.iter());
+		let methods = direct_methods.iter().chain(virtual_methods.iter());
+		self.hiddenapi.push(
+			fields
+				.map(|(_, field)| field.hiddenapi_flags)
+				.chain(methods.map(|(_, method)| method.hiddenapi_flags))
+				.collect(),
+		);
+		Ok((class_data_off, static_values_off))
+	}
+
+	/// Offset of the directory with the annotations of a class and its
+	/// members, which are listed by increasing index.
+	fn annotations_directory(&mut self, annotations: &Annotations) -> Result<u32> {
+		let class_annotations_off = self.annotation_set(&annotations.class)?;
+
+		let mut field_annotations = Vec::with_capacity(annotations.fields.len());
+		for (field, items) in annotations.fields.iter() {
+			field_annotations.push(FieldAnnotation {
+				field_idx:       Idx::new(self.ids.field_idx[&field_key(field)] as usize),
+				annotations_off: Ref::new(self.annotation_set(items)?),
+			});
+		}
+		field_annotations.sort_by_key(|a| *a.field_idx);
+
+		let mut method_annotations = Vec::with_capacity(annotations.methods.len());
+		for (method, items) in annotations.methods.iter() {
+			method_annotations.push(MethodAnnotation {
+				method_idx:      Idx::new(self.ids.method_idx[&method_key(method)] as usize),
+				annotations_off: Ref::new(self.annotation_set(items)?),
+			});
+		}
+		method_annotations.sort_by_key(|a| *a.method_idx);
+
+		let mut parameter_annotations = Vec::with_capacity(annotations.parameters.len());
+		for (method, parameters) in annotations.parameters.iter() {
+			let list = parameters
+				.iter()
+				.map(|items| {
+					Ok(AnnotationSetRefItem {
+						annotations_off: Ref::new(self.annotation_set(items)?),
+					})
+				})
+				.collect::<Result<Vec<_>>>()?;
+			let annotations_off = self.offset(TypeCode::AnnotationSetRefList);
+			self.file
+				.annotation_set_ref_lists
+				.push(AnnotationSetRefList {
+					size: list.len() as u32,
+					list,
+				});
+			parameter_annotations.push(ParameterAnnotation {
+				method_idx:      Idx::new(self.ids.method_idx[&method_key(method)] as usize),
+				annotations_off: Ref::new(annotations_off),
+			});
+		}
+		parameter_annotations.sort_by_key(|a| *a.method_idx);
+
+		let offset = self.offset(TypeCode::AnnotationsDirectoryItem);
+		self.file
+			.annotation_directories
+			.push(AnnotationsDirectoryItem {
+				class_annotations_off:     Ref::new(class_annotations_off),
+				fields_size:               field_annotations.len() as u32,
+				annotated_methods_size:    method_annotations.len() as u32,
+				annotated_parameters_size: parameter_annotations.len() as u32,
+				field_annotations:         (!field_annotations.is_empty())
+					.then_some(field_annotations),
+				method_annotations:        (!method_annotations.is_empty())
+					.then_some(method_annotations),
+				parameter_annotations:     (!parameter_annotations.is_empty())
+					.then_some(parameter_annotations),
+			});
+		Ok(offset)
+	}
+
+	/// Offset of a set with `items`, sorted by their type, `0` for no items.
+	fn annotation_set(&mut self, items: &[AnnotationItem]) -> Result<u32> {
+		if items.is_empty() {
+			return Ok(0);
+		}
+
+		let mut annotations = items
+			.iter()
+			.map(|item| {
+				Ok(AnnotationItem {
+					visibility: item.visibility,
+					annotation: self.annotation(&item.annotation)?,
+				})
+			})
+			.collect::<Result<Vec<_>>>()?;
+		// the types are in the pool, which only has each type once
+		let mut types = HashSet::new();
+		for item in items {
+			let typ = &self.pool.types[*item.annotation.type_idx as usize];
+			ensure!(types.insert(typ), "annotation {} is there twice", typ);
+		}
+		annotations.sort_by_key(|item| *item.annotation.type_idx);
+
+		let entries = annotations
+			.into_iter()
+			.map(|item| {
+				let annotations_off = self.offset(TypeCode::AnnotationItem);
+				self.file.annotations.push(item);
+				AnnotationOffItem {
+					annotations_off: Ref::new(annotations_off),
+				}
+			})
+			.collect::<Vec<_>>();
+		let offset = self.offset(TypeCode::AnnotationSetItem);
+		self.file.annotation_sets.push(AnnotationSetItem {
+			size: entries.len() as u32,
+			entries,
+		});
+		Ok(offset)
+	}
+
+	/// Only files with any hidden API flags get a section for them, members
+	/// without flags next to ones with flags get `0`.
+	fn hiddenapi_class_data(&mut self) {
+		if self.hiddenapi.iter().flatten().all(Option::is_none) {
+			return;
+		}
+		let flags = self
+			.hiddenapi
+			.iter()
+			.map(|class| {
+				if class.iter().all(Option::is_none) {
+					return Vec::new();
+				}
+				class
+					.iter()
+					.map(|flags| flags.unwrap_or_default())
+					.collect()
+			})
+			.collect();
+		self.offset(TypeCode::HiddenapiClassDataItem);
+		self.file.hiddenapi_class_data = Some(HiddenapiClassDataItem { flags });
+	}
+
+	/// Members are listed by increasing index, each index only once.
+	fn sorted_fields<'f>(&self, fields: &'f [Field]) -> Result<Vec<(u32, &'f Field)>> {
+		let mut sorted = fields
+			.iter()
+			.map(|field| {
+				let idx = self.ids.field_idx[&field_key(&field.id)];
+				Ok((idx, field))
+			})
+			.collect::<Result<Vec<_>>>()?;
+		sorted.sort_by_key(|(idx, _)| *idx);
+		if let Some(pair) = sorted.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+			bail!("field {} is defined twice", pair[0].1.id.name);
+		}
+		Ok(sorted)
+	}
+
+	fn sorted_methods<'m>(&self, methods: &'m [Method]) -> Result<Vec<(u32, &'m Method)>> {
+		let mut sorted: Vec<_> = methods
+			.iter()
+			.map(|method| (self.ids.method_idx[&method_key(&method.id)], method))
+			.collect();
+		sorted.sort_by_key(|(idx, _)| *idx);
+		if let Some(pair) = sorted.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+			bail!("method {} is defined twice", pair[0].1.id.name);
+		}
+		Ok(sorted)
+	}
+
+	fn encode_methods(&mut self, methods: &[(u32, &Method)]) -> Result<Vec<EncodedMethod>> {
+		let mut previous = 0;
+		methods
+			.iter()
+			.map(|&(idx, method)| {
+				let code_off = match &method.code {
+					Some(code) => self
+						.code(code, method.debug_info.as_ref())
+						.wrap_err_with(|| format!("building the code of {}", method.id.name))?,
+					None => 0,
+				};
+				let diff = idx - previous;
+				previous = idx;
+				Ok(EncodedMethod {
+					method_idx_diff: diff.into(),
+					access_flags:    method.access_flags.bits().into(),
+					code_off:        Ref::new(code_off),
+				})
+			})
+			.collect()
+	}
+
+	fn code(&mut self, code: &CodeItem, debug_info: Option<&DebugInfo>) -> Result<u32> {
+		let mut code = code.clone();
+		let (ids, pool) = (&self.ids, self.pool);
+		for insn in code.insns.iter_mut() {
+			insn.map_references(|kind, idx| ids.reference(pool, kind, idx))?;
+		}
+
+		if let (Some(tries), Some(handlers)) = (code.tries.as_mut(), code.handlers.as_mut()) {
+			// the type indices are uleb128s, so the handlers can move around
+			let old_offsets = handler_offsets(handlers);
+			for pair in handlers.list.iter_mut().flat_map(|h| h.handlers.iter_mut()) {
+				pair.type_idx = ids
+					.reference(pool, ReferenceType::Type, *pair.type_idx)?
+					.into();
+			}
+			let new_offsets = handler_offsets(handlers);
+			for try_item in tries.iter_mut() {
+				let handler = old_offsets
+					.iter()
+					.position(|&offset| offset == try_item.handler_off as u32)
+					.ok_or_else(|| {
+						eyre!("no catch handler starts at offset {}", try_item.handler_off)
+					})?;
+				try_item.handler_off = u16::try_from(new_offsets[handler])
+					.wrap_err("catch handlers don't fit into 64 KiB")?;
+			}
+		}
+
+		if let Some(debug_info) = debug_info {
+			let item = debug_info.encode(|s| Ok(ids.string(s)), |typ| Ok(ids.typ(typ)))?;
+			let offset = self.offset(TypeCode::DebugInfoItem);
+			self.file.debug_info.push(item);
+			code.debug_info_off = Ref::new(offset);
+		}
+
+		let offset = self.offset(TypeCode::CodeItem);
+		self.file.code.push(code);
+		Ok(offset)
+	}
+
+	/// The initial values of the static fields up to the last one that isn't
+	/// the default.
+	fn static_values(&mut self, fields: &[(u32, &Field)]) -> Result<u32> {
+		let count = fields
+			.iter()
+			.rposition(|(_, field)| field.initial_value.is_some())
+			.map_or(0, |last| last + 1);
+		if count == 0 {
+			return Ok(0);
+		}
+
+		let values = fields[..count]
+			.iter()
+			.map(|(_, field)| match &field.initial_value {
+				Some(value) => self.value(value),
+				None => Ok(default_value(&field.id.typ)),
+			})
+			.collect::<Result<Vec<_>>>()?;
+		let offset = self.offset(TypeCode::EncodedArrayItem);
+		self.file.encoded_arrays.push(EncodedArrayItem {
+			value: EncodedArray {
+				size: (values.len() as u32).into(),
+				values,
+			},
+		});
+		Ok(offset)
+	}
+
+	fn value(&self, value: &EncodedValue) -> Result<EncodedValue> {
+		let reference = |kind, idx| self.ids.reference(self.pool, kind, idx);
+		Ok(match value {
+			EncodedValue::String(idx) => {
+				EncodedValue::String(reference(ReferenceType::String, *idx)?)
+			}
+			EncodedValue::Type(idx) => EncodedValue::Type(reference(ReferenceType::Type, *idx)?),
+			EncodedValue::Field(idx) => EncodedValue::Field(reference(ReferenceType::Field, *idx)?),
+			EncodedValue::Enum(idx) => EncodedValue::Enum(reference(ReferenceType::Field, *idx)?),
+			EncodedValue::Method(idx) => {
+				EncodedValue::Method(reference(ReferenceType::Method, *idx)?)
+			}
+			EncodedValue::MethodType(idx) => {
+				EncodedValue::MethodType(reference(ReferenceType::MethodProto, *idx)?)
+			}
+			EncodedValue::MethodHandle(_) => bail!("method handles can't be written yet"),
+			EncodedValue::Array(array) => EncodedValue::Array(EncodedArray {
+				size:   array.size,
+				values: array
+					.values
+					.iter()
+					.map(|value| self.value(value))
+					.collect::<Result<_>>()?,
+			}),
+			EncodedValue::Annotation(annotation) => {
+				EncodedValue::Annotation(self.annotation(annotation)?)
+			}
+			value => value.clone(),
+		})
+	}
+
+	/// Elements of annotations are sorted by the index of their name.
+	fn annotation(&self, annotation: &EncodedAnnotation) -> Result<EncodedAnnotation> {
+		let reference = |kind, idx| self.ids.reference(self.pool, kind, idx);
+		let mut elements = annotation
+			.elements
+			.iter()
+			.map(|element| {
+				Ok(AnnotationElement {
+					name_idx: Idx::new(
+						reference(ReferenceType::String, *element.name_idx as u32)? as usize
+					),
+					value:    self.value(&element.value)?,
+				})
+			})
+			.collect::<Result<Vec<_>>>()?;
+		elements.sort_by_key(|element| *element.name_idx);
+		Ok(EncodedAnnotation {
+			type_idx: reference(ReferenceType::Type, *annotation.type_idx)?.into(),
+			size: annotation.size,
+			elements,
+		})
+	}
+}
+
+fn proto_key(proto: &Proto) -> ProtoKey {
+	(
+		proto.return_type.clone(),
+		proto.parameters.clone().unwrap_or_default(),
+	)
+}
+
+fn field_key(field: &FieldId) -> FieldKey {
+	(field.class.clone(), field.name.clone(), field.typ.clone())
+}
+
+fn method_key(method: &MethodId) -> MethodKey {
+	(
+		method.class.clone(),
+		method.name.clone(),
+		proto_key(&method.proto),
+	)
+}
+
+fn encode_fields(fields: &[(u32, &Field)]) -> Vec<EncodedField> {
+	let mut previous = 0;
+	fields
+		.iter()
+		.map(|&(idx, field)| {
+			let diff = idx - previous;
+			previous = idx;
+			EncodedField {
+				field_idx_diff: diff.into(),
+				access_flags:   field.access_flags.bits().into(),
+			}
+		})
+		.collect()
+}
+
+/// Byte offsets of the handlers from the start of the list, as
+/// [`TryItem::handler_off`] references them.
+fn handler_offsets(handlers: &EncodedCatchHandlerList) -> Vec<u32> {
+	let mut offset = handlers.size.encoded_size();
+	handlers
+		.list
+		.iter()
+		.map(|handler| {
+			let start = offset;
+			offset += handler.encoded_size();
+			start
+		})
+		.collect()
+}
+
+/// The value a static field without an initial value starts out with.
+fn default_value(typ: &TypeDescriptor) -> EncodedValue {
+	match typ {
+		TypeDescriptor::Boolean => EncodedValue::Boolean(false),
+		TypeDescriptor::Byte => EncodedValue::Byte(0),
+		TypeDescriptor::Short => EncodedValue::Short(0),
+		TypeDescriptor::Char => EncodedValue::Char(0),
+		TypeDescriptor::Int => EncodedValue::Int(0),
+		TypeDescriptor::Long => EncodedValue::Long(0),
+		TypeDescriptor::Float => EncodedValue::Float(0.0),
+		TypeDescriptor::Double => EncodedValue::Double(0.0),
+		_ => EncodedValue::Null,
+	}
+}
+
+/// Classes have to come after their superclass and interfaces if those are
+/// defined in the same file.
+fn class_order(classes: &[Class]) -> Result<Vec<&Class>> {
+	fn visit<'c>(
+		idx: usize,
+		classes: &'c [Class],
+		by_name: &HashMap<&TypeDescriptor, usize>,
+		visited: &mut [Option<bool>],
+		order: &mut Vec<&'c Class>,
+	) -> Result<()> {
+		match visited[idx] {
+			Some(true) => return Ok(()),
+			Some(false) => bail!("{} inherits from itself", classes[idx].name),
+			None => {}
+		}
+		visited[idx] = Some(false);
+		let class = &classes[idx];
+		for parent in std::iter::once(&class.superclass).chain(class.interfaces.iter().flatten()) {
+			if let Some(&parent) = by_name.get(parent) {
+				visit(parent, classes, by_name, visited, order)?;
+			}
+		}
+		visited[idx] = Some(true);
+		order.push(class);
+		Ok(())
+	}
+
+	let mut by_name = HashMap::new();
+	for (idx, class) in classes.iter().enumerate() {
+		if by_name.insert(&class.name, idx).is_some() {
+			bail!("{} is defined twice", class.name);
+		}
+	}
+
+	let mut visited = vec![None; classes.len()];
+	let mut order = Vec::with_capacity(classes.len());
+	for idx in 0..classes.len() {
+		visit(idx, classes, &by_name, &mut visited, &mut order)?;
+	}
+	Ok(order)
+}
+
+/// The oldest format version that has all the instructions the classes use.
+fn format_version(classes: &[Class]) -> u32 {
+	classes
+		.iter()
+		.filter_map(|class| class.class_data.as_ref())
+		.flat_map(|class_data| {
+			class_data
+				.direct_methods
+				.iter()
+				.chain(class_data.virtual_methods.iter())
+		})
+		.filter_map(|method| method.code.as_ref())
+		.flat_map(|code| code.insns.iter())
+		.map(|insn| match insn.opcode() {
+			Opcode::ConstMethodHandle | Opcode::ConstMethodType => 39,
+			Opcode::InvokePolymorphic
+			| Opcode::InvokePolymorphicRange
+			| Opcode::InvokeCustom
+			| Opcode::InvokeCustomRange => 38,
+			_ => 35,
+		})
+		.max()
+		.unwrap_or(35)
+}
+
+/// A file without any items, the header and map list are filled in by
+/// [`DexFile::to_bytes`].
+fn empty_file(format_version: u32) -> DexFile {
+	let mut item_offsets = BTreeMap::new();
+	item_offsets.insert(TypeCode::HeaderItem, vec![0]);
+
+	DexFile {
+		header: Header {
+			format_version,
+			checksum: 0,
+			signature: [0; 20],
+			file_size: 0,
+			header_size: HEADER_SIZE,
+			endian_tag: EndianConstant::EndianConstant,
+			link_size: 0,
+			link_off: 0,
+			map_off: 0,
+			string_ids_size: 0,
+			string_ids_off: 0,
+			type_ids_size: 0,
+			type_ids_off: 0,
+			proto_ids_size: 0,
+			proto_ids_off: 0,
+			field_ids_size: 0,
+			field_ids_off: 0,
+			method_ids_size: 0,
+			method_ids_off: 0,
+			class_defs_size: 0,
+			class_defs_off: 0,
+			data_size: 0,
+			data_off: 0,
+		},
+		map_list: MapList {
+			size: 0,
+			list: Vec::new(),
+		},
+		string_ids: Vec::new(),
+		type_ids: Vec::new(),
+		proto_ids: Vec::new(),
+		field_ids: Vec::new(),
+		method_ids: Vec::new(),
+		class_defs: Vec::new(),
+		code: Vec::new(),
+		debug_info: Vec::new(),
+		type_lists: Vec::new(),
+		string_data: Vec::new(),
+		annotations: Vec::new(),
+		class_data: Vec::new(),
+		encoded_arrays: Vec::new(),
+		annotation_sets: Vec::new(),
+		annotation_set_ref_lists: Vec::new(),
+		annotation_directories: Vec::new(),
+		call_site_ids: Vec::new(),
+		method_handles: Vec::new(),
+		hiddenapi_class_data: None,
+		data: Vec::new(),
+		link_data: Vec::new(),
+		item_offsets,
+		ids_only: false,
+	}
+}
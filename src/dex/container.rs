@@ -0,0 +1,190 @@
+use std::{
+	collections::{hash_map::Entry, HashMap, HashSet},
+	fs,
+	io::{Cursor, Read, Seek},
+	path::Path,
+};
+
+use eyre::{bail, Result, WrapErr};
+use zip::ZipArchive;
+
+use crate::dex::{
+	parser::FileParser,
+	resolver::{Resolve, Resolver},
+	types::{descriptor::TypeDescriptor, Class},
+};
+
+/// Resolver of one dex file of a [`DexContainer`], read into memory.
+pub type DexResolver = Resolver<FileParser<Cursor<Vec<u8>>>>;
+
+pub(crate) const DEX_MAGIC: &[u8] = b"dex\n";
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+/// Where archives keep the dex files the runtime loads, the root of an APK
+/// or JAR and the base module of an AAB.
+const DEX_DIRECTORIES: &[&str] = &["", "base/dex"];
+
+/// The dex files of an APK, AAB or JAR (`classes.dex`, `classes2.dex`, ...)
+/// in the order the runtime loads them, or a single plain dex file.
+///
+/// Classes are looked up across all dex files, a class defined in more than
+/// one of them resolves to the first definition like it does at runtime.
+pub struct DexContainer {
+	dex_files: Vec<(String, DexResolver)>,
+	/// class name -> (index into `dex_files`, index of the class def)
+	classes:   HashMap<TypeDescriptor, (usize, usize)>,
+	/// Classes hidden by a definition in an earlier dex file.
+	shadowed:  HashSet<(usize, usize)>,
+}
+
+impl DexContainer {
+	/// Opens a ZIP based archive or a plain dex file, told apart by their
+	/// magic.
+	pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		let bytes = fs::read(path).wrap_err_with(|| format!("reading {}", path.display()))?;
+		if bytes.starts_with(DEX_MAGIC) {
+			let name = path.file_name().map_or_else(
+				|| "classes.dex".into(),
+				|n| n.to_string_lossy().into_owned(),
+			);
+			Self::new(vec![(name, bytes)])
+		} else if bytes.starts_with(ZIP_MAGIC) {
+			Self::from_zip(Cursor::new(bytes))
+		} else {
+			bail!("{} is neither a dex file nor a ZIP archive", path.display())
+		}
+	}
+
+	/// Reads the `classesN.dex` entries at the root of an APK or JAR, or in
+	/// `base/dex/` of an AAB. Dex files anywhere else, like a plugin under
+	/// `assets/`, aren't loaded by the runtime and are left out.
+	pub fn from_zip<R: Read + Seek>(reader: R) -> Result<Self> {
+		let mut archive = ZipArchive::new(reader).wrap_err("opening archive")?;
+
+		let entries: Vec<(&str, u32, &str)> = archive
+			.file_names()
+			.filter_map(|name| {
+				let (directory, file_name) = name.rsplit_once('/').unwrap_or(("", name));
+				Some((directory, dex_number(file_name)?, name))
+			})
+			.collect();
+		let directory = DEX_DIRECTORIES
+			.iter()
+			.find(|&&directory| entries.iter().any(|&(d, ..)| d == directory));
+		let mut entries: Vec<(u32, &str)> = entries
+			.into_iter()
+			.filter(|&(d, ..)| Some(&d) == directory)
+			.map(|(_, number, name)| (number, name))
+			.collect();
+		entries.sort_unstable();
+		let names: Vec<String> = entries
+			.into_iter()
+			.map(|(_, name)| name.to_string())
+			.collect();
+
+		let mut dex_files = Vec::with_capacity(names.len());
+		for name in names {
+			let mut entry = archive
+				.by_name(&name)
+				.wrap_err_with(|| format!("opening {}", name))?;
+			let mut bytes = Vec::with_capacity(entry.size() as usize);
+			entry
+				.read_to_end(&mut bytes)
+				.wrap_err_with(|| format!("extracting {}", name))?;
+			dex_files.push((name, bytes));
+		}
+		if dex_files.is_empty() {
+			bail!("archive doesn't contain any dex files at its root or in base/dex/");
+		}
+
+		Self::new(dex_files)
+	}
+
+	/// Parses dex files given in load order as `(name, contents)`.
+	pub fn new(dex_files: Vec<(String, Vec<u8>)>) -> Result<Self> {
+		let dex_files = dex_files
+			.into_iter()
+			.map(|(name, bytes)| {
+				let resolver = FileParser::new(Cursor::new(bytes))
+					.and_then(Resolver::new)
+					.wrap_err_with(|| format!("parsing {}", name))?;
+				Ok((name, resolver))
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		let mut classes = HashMap::new();
+		let mut shadowed = HashSet::new();
+		for (file_idx, (name, resolver)) in dex_files.iter().enumerate() {
+			for (class_idx, class_def) in resolver.dex_file().class_defs.iter().enumerate() {
+				let class_name = class_def
+					.class_type(resolver)
+					.and_then(|typ| typ.descriptor(resolver))
+					.wrap_err_with(|| format!("resolving class names of {}", name))?;
+				match classes.entry(class_name) {
+					Entry::Vacant(entry) => {
+						entry.insert((file_idx, class_idx));
+					}
+					Entry::Occupied(_) => {
+						shadowed.insert((file_idx, class_idx));
+					}
+				}
+			}
+		}
+
+		Ok(DexContainer {
+			dex_files,
+			classes,
+			shadowed,
+		})
+	}
+
+	/// The dex files by their name in the archive, in load order.
+	pub fn dex_files(&self) -> impl Iterator<Item = (&str, &DexResolver)> + '_ {
+		self.dex_files
+			.iter()
+			.map(|(name, resolver)| (name.as_str(), resolver))
+	}
+
+	/// Looks a class up in all dex files, along with the resolver of the dex
+	/// file it is defined in that indices in the class refer to.
+	pub fn class(&self, name: &TypeDescriptor) -> Result<Option<(&DexResolver, Class)>> {
+		self.classes
+			.get(name)
+			.map(|&(file_idx, class_idx)| self.resolve(file_idx, class_idx))
+			.transpose()
+	}
+
+	/// Resolves the classes of all dex files in load order, leaving out
+	/// classes that an earlier dex file already defines.
+	pub fn iter_classes(&self) -> impl Iterator<Item = Result<(&DexResolver, Class)>> + '_ {
+		self.dex_files
+			.iter()
+			.enumerate()
+			.flat_map(move |(file_idx, (_, resolver))| {
+				(0..resolver.dex_file().class_defs.len())
+					.map(move |class_idx| (file_idx, class_idx))
+			})
+			.filter(move |entry| !self.shadowed.contains(entry))
+			.map(move |(file_idx, class_idx)| self.resolve(file_idx, class_idx))
+	}
+
+	fn resolve(&self, file_idx: usize, class_idx: usize) -> Result<(&DexResolver, Class)> {
+		let (name, resolver) = &self.dex_files[file_idx];
+		let class = Class::resolve(class_idx, resolver)
+			.wrap_err_with(|| format!("resolving class {} of {}", class_idx, name))?;
+		Ok((resolver, class))
+	}
+}
+
+/// The position of `classesN.dex` in the load order, `classes.dex` is the
+/// first one.
+fn dex_number(file_name: &str) -> Option<u32> {
+	let number = file_name.strip_prefix("classes")?.strip_suffix(".dex")?;
+	if number.is_empty() {
+		return Some(1);
+	}
+	if number.starts_with('0') {
+		return None;
+	}
+	number.parse().ok().filter(|&n| n > 1)
+}
@@ -1,6 +1,11 @@
 pub mod asm;
+pub mod builder;
+pub mod container;
 #[macro_use]
 pub mod parser;
 pub mod resolver;
+pub mod smali;
 #[macro_use]
 pub mod types;
+pub mod view;
+pub mod writer;
@@ -1,5 +1,6 @@
 #[macro_use]
 pub mod parse;
+pub mod slice;
 
 use std::{
 	io,
@@ -11,9 +12,13 @@ use color_eyre::{
 	Result,
 };
 pub use parse::{Parse, ReadThings};
+pub use slice::SliceParser;
 use thiserror::Error;
 
-use crate::dex::types::file::DexFile;
+use crate::dex::{
+	asm::opcode::Opcode,
+	types::{file::DexFile, header::EndianConstant},
+};
 
 #[derive(Debug, Error)]
 pub enum ParseError {
@@ -21,6 +26,14 @@ pub enum ParseError {
 	GenericError(String),
 	#[error("parsing failed: bad UTF-8 byte {value:#04x} at offset {offset:#04x}")]
 	BadUTF8 { value: u16, offset: usize },
+	#[error(
+		"parsing failed: unused byte of `{opcode}` at offset {offset:#x} is {value:#04x} instead of 0"
+	)]
+	UnusedByte {
+		opcode: Opcode,
+		offset: u32,
+		value:  u8,
+	},
 	#[error("parsing failed with IO error")]
 	Io(#[from] io::Error),
 }
@@ -33,6 +46,14 @@ impl ParseError {
 	pub fn bad_utf8(value: u16, offset: usize) -> ParseError {
 		ParseError::BadUTF8 { value, offset }
 	}
+
+	pub fn unused_byte(opcode: Opcode, offset: u32, value: u8) -> ParseError {
+		ParseError::UnusedByte {
+			opcode,
+			offset,
+			value,
+		}
+	}
 }
 
 pub trait Parser: Seek + ReadThings + Sized {
@@ -164,14 +185,19 @@ pub trait Parser: Seek + ReadThings + Sized {
 #[cfg_attr(feature = "trace", instrument(skip(p)))]
 fn parse_utf8_bytes_utf16_len_string<P: Parser>(p: &mut P, len: u32) -> Result<(Vec<u8>, String)> {
 	let mut bytes: Vec<u8> = Vec::new();
-	let mut chars: Vec<u16> = Vec::with_capacity(len as usize);
-
-	let mut next_byte = || -> Result<u16> {
+	let string = decode_mutf8(len, || {
 		let byte = p.u8()?;
 		bytes.push(byte);
+		Ok(byte)
+	})?;
+	Ok((bytes, string))
+}
 
-		Ok(byte as u16 & 0xFF)
-	};
+/// Decodes `len` UTF-16 code units of MUTF-8 from the bytes `next_byte`
+/// returns.
+pub(crate) fn decode_mutf8(len: u32, mut next_byte: impl FnMut() -> Result<u8>) -> Result<String> {
+	let mut chars: Vec<u16> = Vec::with_capacity(len as usize);
+	let mut next_byte = || -> Result<u16> { Ok(next_byte()? as u16) };
 
 	let mut at = 0;
 	for _ in 0..len {
@@ -210,7 +236,7 @@ fn parse_utf8_bytes_utf16_len_string<P: Parser>(p: &mut P, len: u32) -> Result<(
 					bail!(ParseError::bad_utf8(v1, at + 1));
 				}
 				let v2 = next_byte()?;
-				if (v1 & 0xc0) != 0x80 {
+				if (v2 & 0xc0) != 0x80 {
 					bail!(ParseError::bad_utf8(v2, at + 2));
 				}
 				let value = ((v0 & 0x0f) << 12) | ((v1 & 0x3f) << 6) | (v2 & 0x3f);
@@ -229,25 +255,46 @@ fn parse_utf8_bytes_utf16_len_string<P: Parser>(p: &mut P, len: u32) -> Result<(
 		chars.push(out);
 	}
 
-	Ok((bytes, String::from_utf16_lossy(&chars).to_string()))
+	Ok(String::from_utf16_lossy(&chars))
 }
 
 pub struct FileParser<R: Read + Seek> {
-	reader: R,
+	reader:   R,
+	endian:   EndianConstant,
+	lenient:  bool,
+	warnings: Vec<ParseError>,
 	// header: Header,
 }
 
 impl<R: Read + Seek> FileParser<R> {
+	/// Reads the endian tag of the header first, so the rest of the file is
+	/// read in the byte order it was written in.
 	#[cfg_attr(feature = "trace", instrument(skip(reader)))]
-	pub fn new(reader: R) -> Result<Self> {
-		// let header = Header::parse(&mut reader)?;
+	pub fn new(mut reader: R) -> Result<Self> {
+		let endian = EndianConstant::read_tag(&mut reader).wrap_err("reading endian tag")?;
 
 		Ok(FileParser {
 			reader,
+			endian,
+			lenient: false,
+			warnings: Vec::new(),
 			// header
 		})
 	}
 
+	/// Only warns about malformed bytes that don't change the meaning of the
+	/// file, like nonzero unused bytes of instructions, instead of failing.
+	/// Packers put garbage there on purpose.
+	pub fn lenient(mut self) -> Self {
+		self.lenient = true;
+		self
+	}
+
+	/// What a lenient parser skipped over so far, in the order it was read.
+	pub fn warnings(&self) -> &[ParseError] {
+		&self.warnings
+	}
+
 	// #[cfg_attr(feature = "trace", instrument(skip(self)))]
 	// fn parse_array<T: Parse>(&mut self, size: u32, offset: Option<u32>) -> Result<Vec<T>> {
 	// 	if let Some(offset) = offset {
@@ -365,7 +412,21 @@ impl<R: Read + Seek> FileParser<R> {
 // 	}
 // }
 
-impl<R: Read + Seek> Parser for R {}
+impl<R: Seek + ReadThings> Parser for R {}
+
+impl<R: Read + Seek> ReadThings for FileParser<R> {
+	fn endian(&self) -> EndianConstant {
+		self.endian
+	}
+
+	fn is_lenient(&self) -> bool {
+		self.lenient
+	}
+
+	fn warning(&mut self, warning: ParseError) {
+		self.warnings.push(warning);
+	}
+}
 
 impl<R: Read + Seek> Read for FileParser<R> {
 	fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
@@ -1,9 +1,18 @@
-use std::{convert::TryInto, num::TryFromIntError, ops::Deref};
+use std::{
+	convert::TryInto,
+	fs::File,
+	io::{BufReader, Cursor, Read},
+	num::TryFromIntError,
+	ops::Deref,
+};
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 use eyre::{Result, WrapErr};
 
-use crate::dex::parser::Parser;
+use crate::dex::{
+	parser::{ParseError, Parser},
+	types::header::EndianConstant,
+};
 
 pub trait Parse
 where
@@ -26,6 +35,8 @@ macro_rules! parse_simple {
 
 parse_simple!(u8, u16, i16, u32, i32);
 
+/// Implements [`Parse`] and its [`Write`](crate::dex::writer::Write)
+/// counterpart for a struct made up of fields that are read one after another.
 macro_rules! parse_struct_default {
 	($name:ident $align:literal { $($field:ident),* } $span:literal) => {
 		impl Parse for $name {
@@ -39,6 +50,18 @@ macro_rules! parse_struct_default {
 				})
 			}
 		}
+
+		impl Write for $name {
+			const ALIGNMENT: u32 = if $align != 0 { $align } else { 1 };
+
+			fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+				if $align != 0 {
+					writer.align($align)?;
+				}
+				$(writer.write_item(&self.$field)?;)*
+				Ok(())
+			}
+		}
 	};
 	($name:ident $align:literal { $($field:ident),* }) => {
 		::with_builtin_macros::with_builtin! {
@@ -67,6 +90,13 @@ impl Parse for Uleb128 {
 	}
 }
 
+impl Uleb128 {
+	/// Number of bytes the value takes up in its shortest encoding.
+	pub fn encoded_size(&self) -> u32 {
+		(32 - self.0.leading_zeros()).max(1).div_ceil(7)
+	}
+}
+
 impl Deref for Uleb128 {
 	type Target = u32;
 
@@ -75,9 +105,15 @@ impl Deref for Uleb128 {
 	}
 }
 
-impl Into<u32> for Uleb128 {
-	fn into(self) -> u32 {
-		self.0
+impl From<u32> for Uleb128 {
+	fn from(val: u32) -> Uleb128 {
+		Uleb128(val)
+	}
+}
+
+impl From<Uleb128> for u32 {
+	fn from(val: Uleb128) -> u32 {
+		val.0
 	}
 }
 
@@ -98,6 +134,18 @@ impl Parse for Sleb128 {
 	}
 }
 
+impl Sleb128 {
+	/// Number of bytes the value takes up in its shortest encoding.
+	pub fn encoded_size(&self) -> u32 {
+		let bits = if self.0 < 0 {
+			33 - self.0.leading_ones()
+		} else {
+			33 - self.0.leading_zeros()
+		};
+		bits.div_ceil(7)
+	}
+}
+
 impl Deref for Sleb128 {
 	type Target = i32;
 
@@ -106,16 +154,51 @@ impl Deref for Sleb128 {
 	}
 }
 
-impl Into<i32> for Sleb128 {
-	fn into(self) -> i32 {
-		self.0
+impl From<i32> for Sleb128 {
+	fn from(val: i32) -> Sleb128 {
+		Sleb128(val)
 	}
 }
 
+impl From<Sleb128> for i32 {
+	fn from(val: Sleb128) -> i32 {
+		val.0
+	}
+}
+
+/// Reads a multi-byte value in the byte order of the reader.
+macro_rules! read_ordered {
+	($reader:ident.$read:ident, $what:literal) => {
+		match $reader.endian() {
+			EndianConstant::EndianConstant => $reader.$read::<LittleEndian>(),
+			EndianConstant::ReverseEndianConstant => $reader.$read::<BigEndian>(),
+		}
+		.wrap_err(concat!("reading ", $what))
+	};
+}
+
 pub trait ReadThings: ReadBytesExt {
+	/// Byte order multi-byte values are stored in, files are little endian
+	/// unless their header says otherwise.
+	fn endian(&self) -> EndianConstant {
+		EndianConstant::EndianConstant
+	}
+
+	/// Whether malformed bytes that can be skipped only produce a warning,
+	/// see [`FileParser::lenient`](super::FileParser::lenient).
+	fn is_lenient(&self) -> bool {
+		false
+	}
+
+	/// Keeps a problem a lenient parser skipped over, see
+	/// [`FileParser::warnings`](super::FileParser::warnings). Parsers that
+	/// have nowhere to keep them, like [`SliceParser`](super::SliceParser),
+	/// only log it.
+	fn warning(&mut self, _warning: ParseError) {}
+
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
 	fn u8(&mut self) -> Result<u8> {
-		Ok(self.read_u8().wrap_err("reading u8")?)
+		self.read_u8().wrap_err("reading u8")
 	}
 
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
@@ -126,42 +209,42 @@ pub trait ReadThings: ReadBytesExt {
 
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
 	fn u16(&mut self) -> Result<u16> {
-		Ok(self.read_u16::<LittleEndian>().wrap_err("reading u16")?)
+		read_ordered!(self.read_u16, "u16")
 	}
 
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
 	fn i16(&mut self) -> Result<i16> {
-		Ok(self.read_i16::<LittleEndian>().wrap_err("reading i16")?)
+		read_ordered!(self.read_i16, "i16")
 	}
 
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
 	fn u32(&mut self) -> Result<u32> {
-		Ok(self.read_u32::<LittleEndian>().wrap_err("reading u32")?)
+		read_ordered!(self.read_u32, "u32")
 	}
 
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
 	fn i32(&mut self) -> Result<i32> {
-		Ok(self.read_i32::<LittleEndian>().wrap_err("reading i32")?)
+		read_ordered!(self.read_i32, "i32")
 	}
 
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
 	fn u64(&mut self) -> Result<u64> {
-		Ok(self.read_u64::<LittleEndian>().wrap_err("reading u64")?)
+		read_ordered!(self.read_u64, "u64")
 	}
 
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
 	fn i64(&mut self) -> Result<i64> {
-		Ok(self.read_i64::<LittleEndian>().wrap_err("reading i64")?)
+		read_ordered!(self.read_i64, "i64")
 	}
 
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
 	fn f32(&mut self) -> Result<f32> {
-		Ok(self.read_f32::<LittleEndian>().wrap_err("reading f32")?)
+		read_ordered!(self.read_f32, "f32")
 	}
 
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
 	fn f64(&mut self) -> Result<f64> {
-		Ok(self.read_f64::<LittleEndian>().wrap_err("reading f64")?)
+		read_ordered!(self.read_f64, "f64")
 	}
 
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
@@ -177,4 +260,10 @@ pub trait ReadThings: ReadBytesExt {
 	}
 }
 
-impl<T: ReadBytesExt> ReadThings for T {}
+impl ReadThings for &[u8] {}
+
+impl<T: AsRef<[u8]>> ReadThings for Cursor<T> {}
+
+impl ReadThings for File {}
+
+impl<R: Read> ReadThings for BufReader<R> {}
@@ -0,0 +1,112 @@
+use std::{
+	convert::TryFrom,
+	io,
+	io::{Cursor, Read, Seek, SeekFrom},
+};
+
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+
+use crate::dex::{parser::ReadThings, types::header::EndianConstant};
+
+/// Parser over a file that is already in memory, like a memory mapped one,
+/// which can hand out parts of it without copying them.
+#[derive(Debug, Copy, Clone)]
+pub struct SliceParser<'a> {
+	data:     &'a [u8],
+	position: usize,
+	endian:   EndianConstant,
+	lenient:  bool,
+}
+
+impl<'a> SliceParser<'a> {
+	/// Reads the endian tag of the header first, like
+	/// [`FileParser::new`](super::FileParser::new).
+	pub fn new(data: &'a [u8]) -> Result<Self> {
+		let endian =
+			EndianConstant::read_tag(&mut Cursor::new(data)).wrap_err("reading endian tag")?;
+		Ok(SliceParser {
+			data,
+			position: 0,
+			endian,
+			lenient: false,
+		})
+	}
+
+	/// Same as [`FileParser::lenient`](super::FileParser::lenient).
+	pub fn lenient(mut self) -> Self {
+		self.lenient = true;
+		self
+	}
+
+	/// The whole file, independent of the position.
+	pub fn data(&self) -> &'a [u8] {
+		self.data
+	}
+
+	/// Borrows the next `len` bytes and moves past them.
+	pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+		let bytes = self
+			.position
+			.checked_add(len)
+			.and_then(|end| self.data.get(self.position..end))
+			.ok_or_else(|| {
+				eyre!(
+					"{} bytes at {:#x} are past the end of the file",
+					len,
+					self.position
+				)
+			})?;
+		self.position += len;
+		Ok(bytes)
+	}
+
+	/// Borrows the bytes up to the next zero byte and moves past the zero.
+	pub fn take_until_nul(&mut self) -> Result<&'a [u8]> {
+		let len = self.data[self.position.min(self.data.len())..]
+			.iter()
+			.position(|&b| b == 0)
+			.ok_or_else(|| eyre!("no terminating zero after {:#x}", self.position))?;
+		let bytes = self.take(len)?;
+		self.position += 1;
+		Ok(bytes)
+	}
+}
+
+impl ReadThings for SliceParser<'_> {
+	fn endian(&self) -> EndianConstant {
+		self.endian
+	}
+
+	fn is_lenient(&self) -> bool {
+		self.lenient
+	}
+}
+
+impl Read for SliceParser<'_> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let rest = self.data.get(self.position..).unwrap_or_default();
+		let len = buf.len().min(rest.len());
+		buf[..len].copy_from_slice(&rest[..len]);
+		self.position += len;
+		Ok(len)
+	}
+}
+
+impl Seek for SliceParser<'_> {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		let position = match pos {
+			SeekFrom::Start(offset) => Some(offset),
+			SeekFrom::End(delta) => (self.data.len() as u64).checked_add_signed(delta),
+			SeekFrom::Current(delta) => (self.position as u64).checked_add_signed(delta),
+		};
+		self.position = position
+			.and_then(|p| usize::try_from(p).ok())
+			.ok_or_else(|| {
+				io::Error::new(io::ErrorKind::InvalidInput, "seeking to a negative offset")
+			})?;
+		Ok(self.position as u64)
+	}
+}
@@ -1,14 +1,15 @@
 use std::{
-	cell::RefCell,
-	collections::HashMap,
+	any::Any,
+	cell::{Ref, RefCell},
+	collections::BTreeMap,
 	io::{Read, Seek},
 };
 
-use eyre::{Result, WrapErr};
+use eyre::{eyre, Result, WrapErr};
 
 use crate::dex::{
-	parser::{FileParser, Parser},
-	types::{file::DexFile, Class, MethodId},
+	parser::{FileParser, Parse, ParseError, Parser},
+	types::{file::DexFile, map::TypeCode, refs::DataItem, Class, MethodId},
 };
 
 pub trait ResolveFrom<T>
@@ -43,16 +44,33 @@ where
 		T::resolve_from(from, self)
 	}
 
-	fn string(&self, idx: usize) -> String {
-		self.dex_file().string_data[idx].string.clone()
+	fn string(&self, idx: usize) -> Result<String> {
+		self.dex_file()
+			.string_data
+			.get(idx)
+			.map(|data| data.string.clone())
+			.ok_or_else(|| eyre!("string index {} out of bounds", idx))
+	}
+
+	/// The item of a data section at `offset`.
+	fn data_item<T: DataItem + Parse + Clone + 'static>(&self, offset: u32) -> Result<T> {
+		self.dex_file()
+			.data_item(offset)
+			.cloned()
+			.ok_or_else(|| eyre!("no {:?} at offset {:#x}", T::TYPE_CODE, offset))
 	}
 }
 
+/// Parsed data items by their type and offset.
+type ItemCache = BTreeMap<(TypeCode, u32), Box<dyn Any>>;
+
 pub struct Resolver<P: Parser> {
 	parser:       RefCell<P>,
 	pub dex_file: DexFile,
 
-	string_cache: RefCell<HashMap<usize, String>>,
+	/// Data items parsed on demand, only used if the data sections weren't
+	/// parsed up front.
+	cache: Option<RefCell<ItemCache>>,
 }
 
 impl<R: Read + Seek> Resolver<FileParser<R>> {
@@ -63,23 +81,35 @@ impl<R: Read + Seek> Resolver<FileParser<R>> {
 			parser: RefCell::new(parser),
 			dex_file,
 
-			string_cache: RefCell::new(HashMap::new()),
+			cache: None,
+		})
+	}
+
+	/// Only parses the header, the map list and the id sections up front.
+	///
+	/// Class data, code, debug info, annotations and the other data items are
+	/// parsed the first time they are resolved and cached from then on, so
+	/// a broken method only fails resolving its own class. The data sections
+	/// of [`Resolver::dex_file`] stay empty.
+	#[cfg_attr(feature = "trace", instrument(skip(parser)))]
+	pub fn lazy(mut parser: FileParser<R>) -> Result<Self> {
+		let dex_file = DexFile::parse_ids(&mut parser).wrap_err("parsing file")?;
+		Ok(Self {
+			parser: RefCell::new(parser),
+			dex_file,
+
+			cache: Some(RefCell::new(BTreeMap::new())),
 		})
 	}
+
+	/// What a lenient parser skipped over so far, a lazy resolver only
+	/// knows about the items it has parsed.
+	pub fn warnings(&self) -> Ref<'_, [ParseError]> {
+		Ref::map(self.parser.borrow(), |parser| parser.warnings())
+	}
 }
 
 impl<P: Parser> Resolver<P> {
-	// #[cfg_attr(feature = "trace", instrument(skip(parser)))]
-	// pub fn get_string(&self, idx: usize) -> String {
-	// 	let off = self.dex_file.string_ids[idx].string_data_off;
-	//
-	// 	let map: &mut HashMap<usize, String> = &mut *self.string_cache.borrow_mut();
-	//
-	// 	map.entry(idx)
-	// 		.or_insert_with(|| self.parser.borrow_mut().parse_string(*off).unwrap())
-	// 		.clone()
-	// }
-
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
 	pub fn class_names(&mut self) -> Result<Vec<String>> {
 		for class_def in self.dex_file.class_defs.iter() {
@@ -100,11 +130,14 @@ impl<P: Parser> Resolver<P> {
 
 	#[cfg_attr(feature = "trace", instrument(skip(self)))]
 	pub fn classes(&mut self) -> Result<Vec<Class>> {
-		let mut methods = Vec::with_capacity(self.dex_file.method_ids.len());
-		for class_def in self.dex_file.class_defs[0..5].iter() {
-			methods.push(Class::resolve_from(class_def, self)?);
-		}
-		Ok(methods)
+		self.iter_classes().collect()
+	}
+
+	/// Resolves the classes one at a time as the iterator is advanced, so only
+	/// the current class has to be kept around.
+	pub fn iter_classes(&self) -> impl Iterator<Item = Result<Class>> + '_ {
+		(0..self.dex_file.class_defs.len())
+			.map(move |class_def_idx| Class::resolve(class_def_idx, self))
 	}
 }
 
@@ -112,4 +145,35 @@ impl<P: Parser> Resolve for Resolver<P> {
 	fn dex_file(&self) -> &DexFile {
 		&self.dex_file
 	}
+
+	fn data_item<T: DataItem + Parse + Clone + 'static>(&self, offset: u32) -> Result<T> {
+		let cache = match &self.cache {
+			Some(cache) => cache,
+			None => {
+				return self
+					.dex_file
+					.data_item(offset)
+					.cloned()
+					.ok_or_else(|| eyre!("no {:?} at offset {:#x}", T::TYPE_CODE, offset))
+			}
+		};
+
+		let key = (T::TYPE_CODE, offset);
+		if let Some(item) = cache
+			.borrow()
+			.get(&key)
+			.and_then(|item| item.downcast_ref())
+		{
+			return Ok(T::clone(item));
+		}
+
+		let item: T = self
+			.parser
+			.borrow_mut()
+			.offset(offset)?
+			.parse()
+			.wrap_err_with(|| format!("parsing {:?} at offset {:#x}", T::TYPE_CODE, offset))?;
+		cache.borrow_mut().insert(key, Box::new(item.clone()));
+		Ok(item)
+	}
 }
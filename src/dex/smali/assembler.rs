@@ -0,0 +1,1862 @@
+use std::{
+	collections::{BTreeSet, HashMap},
+	convert::TryFrom,
+	fmt::Display,
+};
+
+use eyre::{bail, ensure, eyre, Report, Result};
+use thiserror::Error;
+
+use super::lexer::{tokenize, Register, Spanned, Token};
+use crate::dex::{
+	asm::{
+		format::Format,
+		instruction::Instruction,
+		opcode::{Opcode, OpcodeFlags, ReferenceType},
+	},
+	parser::parse::Uleb128,
+	types::{
+		access::{AccessContext, AccessFlags},
+		debug::{DebugEvent, DebugInfo, DebugInfoBuilder},
+		descriptor::TypeDescriptor,
+		id::{
+			AnnotationElement,
+			AnnotationItem,
+			CodeItem,
+			EncodedAnnotation,
+			EncodedArray,
+			EncodedCatchHandler,
+			EncodedCatchHandlerList,
+			EncodedTypeAddrPair,
+			EncodedValue,
+			TryItem,
+			VISIBILITY_BUILD,
+			VISIBILITY_RUNTIME,
+			VISIBILITY_SYSTEM,
+		},
+		refs::{Idx, Ref},
+		Annotations,
+		Class,
+		ClassData,
+		Field,
+		FieldId,
+		Method,
+		MethodId,
+		Proto,
+	},
+};
+
+/// Errors for smali the assembler understands but can't assemble, as opposed
+/// to malformed source.
+#[derive(Debug, Error)]
+pub enum AssembleError {
+	#[error("line {line}: {feature} can't be assembled yet, found `{found}`")]
+	Unsupported {
+		line:    usize,
+		feature: &'static str,
+		found:   String,
+	},
+}
+
+/// The strings, types, protos, fields and methods referenced by assembled
+/// code, in the order they were first used. Reference operands of assembled
+/// [`Instruction`]s and encoded values are indices into these lists.
+#[derive(Debug, Default, Clone)]
+pub struct Pool {
+	pub strings: Vec<String>,
+	pub types:   Vec<TypeDescriptor>,
+	pub protos:  Vec<Proto>,
+	pub fields:  Vec<FieldId>,
+	pub methods: Vec<MethodId>,
+	indices:     HashMap<(ReferenceType, String), u32>,
+}
+
+impl Pool {
+	pub fn string(&mut self, string: &str) -> u32 {
+		self.intern(ReferenceType::String, string, |pool| {
+			pool.strings.push(string.to_string())
+		})
+	}
+
+	/// `Lfoo/Bar;`, `[I`, ...
+	pub fn type_idx(&mut self, descriptor: &str) -> Result<u32> {
+		let typ = descriptor.parse()?;
+		Ok(self.intern(ReferenceType::Type, descriptor, |pool| pool.types.push(typ)))
+	}
+
+	/// `(ILjava/lang/String;)V`
+	pub fn proto(&mut self, descriptor: &str) -> Result<u32> {
+		let proto = parse_proto(descriptor)?;
+		Ok(self.intern(ReferenceType::MethodProto, descriptor, |pool| {
+			pool.protos.push(proto)
+		}))
+	}
+
+	/// `Lfoo/Bar;->name:I`
+	pub fn field(&mut self, reference: &str) -> Result<u32> {
+		if let Some(&idx) = self
+			.indices
+			.get(&(ReferenceType::Field, reference.to_string()))
+		{
+			return Ok(idx);
+		}
+		let (class, member) = split_member(reference)?;
+		let (name, typ) = member
+			.split_once(':')
+			.ok_or_else(|| eyre!("expected a field reference, found `{}`", reference))?;
+		let field = FieldId {
+			class: class.parse()?,
+			typ:   typ.parse()?,
+			name:  name.to_string(),
+		};
+		Ok(self.intern(ReferenceType::Field, reference, |pool| {
+			pool.fields.push(field)
+		}))
+	}
+
+	/// `Lfoo/Bar;->name(I)V`
+	pub fn method(&mut self, reference: &str) -> Result<u32> {
+		let (class, member) = split_member(reference)?;
+		let paren = member
+			.find('(')
+			.ok_or_else(|| eyre!("expected a method reference, found `{}`", reference))?;
+		let (name, proto) = member.split_at(paren);
+		let method = MethodId {
+			name:  name.to_string(),
+			class: class.parse()?,
+			proto: parse_proto(proto)?,
+		};
+		Ok(self.intern(ReferenceType::Method, reference, |pool| {
+			pool.methods.push(method)
+		}))
+	}
+
+	fn reference(&mut self, kind: ReferenceType, word: &str) -> Result<u32> {
+		match kind {
+			ReferenceType::Type => self.type_idx(word),
+			ReferenceType::Field => self.field(word),
+			ReferenceType::Method => self.method(word),
+			ReferenceType::MethodProto => self.proto(word),
+			ReferenceType::String => bail!("expected a string literal, found `{}`", word),
+			ReferenceType::MethodHandle | ReferenceType::CallSite => {
+				bail!("{:?} references aren't pooled, found `{}`", kind, word)
+			}
+			kind => bail!("{:?} references can't be assembled", kind),
+		}
+	}
+
+	fn intern(&mut self, kind: ReferenceType, key: &str, add: impl FnOnce(&mut Self)) -> u32 {
+		let key = (kind, key.to_string());
+		if let Some(&idx) = self.indices.get(&key) {
+			return idx;
+		}
+
+		let idx = match kind {
+			ReferenceType::String => self.strings.len(),
+			ReferenceType::Type => self.types.len(),
+			ReferenceType::MethodProto => self.protos.len(),
+			ReferenceType::Field => self.fields.len(),
+			_ => self.methods.len(),
+		} as u32;
+		add(self);
+		self.indices.insert(key, idx);
+		idx
+	}
+}
+
+/// Turns `.smali` source back into [`Class`]es, the reverse of
+/// [`Disassembler`](super::disassembler::Disassembler).
+///
+/// References in the assembled code point into the assembler's [`Pool`], which
+/// is shared between all classes assembled with the same instance.
+#[derive(Debug, Default)]
+pub struct Assembler {
+	pool: Pool,
+}
+
+impl Assembler {
+	pub fn new() -> Self {
+		Default::default()
+	}
+
+	pub fn pool(&self) -> &Pool {
+		&self.pool
+	}
+
+	pub fn into_pool(self) -> Pool {
+		self.pool
+	}
+
+	#[cfg_attr(feature = "trace", instrument(skip(self, source)))]
+	pub fn class(&mut self, source: &str) -> Result<Class> {
+		let mut tokens = Tokens::new(tokenize(source)?);
+
+		tokens.directive("class")?;
+		let access_flags = tokens.access_flags(AccessContext::Class);
+		let name = tokens.type_descriptor()?;
+
+		let mut superclass = None;
+		let mut interfaces = Vec::new();
+		let mut source_file = None;
+		let mut class_data = ClassData {
+			static_fields:   Vec::new(),
+			instance_fields: Vec::new(),
+			direct_methods:  Vec::new(),
+			virtual_methods: Vec::new(),
+		};
+		let mut annotations = Annotations {
+			class:      Vec::new(),
+			fields:     Vec::new(),
+			methods:    Vec::new(),
+			parameters: Vec::new(),
+		};
+
+		while let Some(token) = tokens.next_opt() {
+			let directive = match token {
+				Token::Directive(directive) => directive,
+				token => {
+					return Err(tokens.error(format!("expected a directive, found `{}`", token)))
+				}
+			};
+			match directive.as_str() {
+				"super" => superclass = Some(tokens.type_descriptor()?),
+				"implements" => interfaces.push(tokens.type_descriptor()?),
+				"source" => source_file = Some(tokens.string()?),
+				"field" => {
+					let (field, field_annotations) = self.field(&mut tokens, &name)?;
+					if !field_annotations.is_empty() {
+						annotations
+							.fields
+							.push((field.id.clone(), field_annotations));
+					}
+					if field.access_flags.contains(AccessFlags::STATIC) {
+						class_data.static_fields.push(field);
+					} else {
+						class_data.instance_fields.push(field);
+					}
+				}
+				"method" => {
+					let (method, method_annotations, parameter_annotations) =
+						self.method(&mut tokens, &name)?;
+					if !method_annotations.is_empty() {
+						annotations
+							.methods
+							.push((method.id.clone(), method_annotations));
+					}
+					if parameter_annotations.iter().any(|a| !a.is_empty()) {
+						annotations
+							.parameters
+							.push((method.id.clone(), parameter_annotations));
+					}
+					if method.access_flags.intersects(
+						AccessFlags::STATIC | AccessFlags::PRIVATE | AccessFlags::CONSTRUCTOR,
+					) {
+						class_data.direct_methods.push(method);
+					} else {
+						class_data.virtual_methods.push(method);
+					}
+				}
+				"annotation" => annotations.class.push(self.annotation(&mut tokens)?),
+				"debug" => tokens.skip_line(),
+				directive => {
+					return Err(tokens.error(format!("unexpected directive `.{}`", directive)))
+				}
+			}
+		}
+
+		let superclass = superclass.ok_or_else(|| eyre!("{} has no .super directive", name))?;
+		let has_members = !(class_data.static_fields.is_empty()
+			&& class_data.instance_fields.is_empty()
+			&& class_data.direct_methods.is_empty()
+			&& class_data.virtual_methods.is_empty());
+		let has_annotations = !(annotations.class.is_empty()
+			&& annotations.fields.is_empty()
+			&& annotations.methods.is_empty()
+			&& annotations.parameters.is_empty());
+
+		Ok(Class {
+			name,
+			access_flags,
+			superclass,
+			interfaces: Some(interfaces),
+			source_file,
+			annotations: has_annotations.then_some(annotations),
+			class_data: has_members.then_some(class_data),
+		})
+	}
+
+	/// Parses a field and the annotations in its `.field` block.
+	fn field(
+		&mut self,
+		tokens: &mut Tokens,
+		class: &TypeDescriptor,
+	) -> Result<(Field, Vec<AnnotationItem>)> {
+		let access_flags = tokens.access_flags(AccessContext::Field);
+		let declaration = tokens.word()?;
+		let (name, typ) = declaration.split_once(':').ok_or_else(|| {
+			tokens.error(format!("expected `name:Type`, found `{}`", declaration))
+		})?;
+		let typ: TypeDescriptor = typ.parse().map_err(|e| tokens.error(e))?;
+
+		let initial_value = if tokens.on_same_line() && tokens.eat(&Token::Word("=".to_string())) {
+			// only static fields have a place for values in the dex file
+			if !access_flags.contains(AccessFlags::STATIC) {
+				return Err(tokens.error(format!(
+					"instance field `{}` can't have an initial value",
+					name
+				)));
+			}
+			Some(self.value(tokens, Some(&typ))?)
+		} else {
+			None
+		};
+
+		let mut annotations = Vec::new();
+		loop {
+			match tokens.peek() {
+				Some(Token::Directive(d)) if d == "annotation" => {
+					tokens.next()?;
+					annotations.push(self.annotation(tokens)?);
+				}
+				Some(Token::Directive(d))
+					if d == "end"
+						&& tokens.peek_nth(1) == Some(&Token::Word("field".to_string())) =>
+				{
+					tokens.end("field")?;
+					break;
+				}
+				_ => break,
+			}
+		}
+
+		let field = Field {
+			id: FieldId {
+				class: class.clone(),
+				typ,
+				name: name.to_string(),
+			},
+			access_flags,
+			initial_value,
+			hiddenapi_flags: None,
+		};
+		Ok((field, annotations))
+	}
+
+	/// Parses the rest of `.annotation <visibility> Ltype;` up to and including
+	/// `.end annotation`.
+	fn annotation(&mut self, tokens: &mut Tokens) -> Result<AnnotationItem> {
+		let visibility = match tokens.word()?.as_str() {
+			"build" => VISIBILITY_BUILD,
+			"runtime" => VISIBILITY_RUNTIME,
+			"system" => VISIBILITY_SYSTEM,
+			visibility => return Err(tokens.error(format!("unknown visibility `{}`", visibility))),
+		};
+		let annotation = self.encoded_annotation(tokens)?;
+		tokens.end("annotation")?;
+		Ok(AnnotationItem {
+			visibility,
+			annotation,
+		})
+	}
+
+	/// Parses the type and the `name = value` elements of an annotation, up to
+	/// its `.end` directive.
+	fn encoded_annotation(&mut self, tokens: &mut Tokens) -> Result<EncodedAnnotation> {
+		let typ = tokens.type_descriptor()?;
+		let type_idx = self
+			.pool
+			.type_idx(&typ.to_string())
+			.map_err(|e| tokens.error(e))?;
+		let mut elements = Vec::new();
+		while tokens.peek() != Some(&Token::Directive("end".to_string())) {
+			let name = tokens.word()?;
+			tokens.expect(Token::Word("=".to_string()))?;
+			elements.push(AnnotationElement {
+				name_idx: Idx::new(self.pool.string(&name) as usize),
+				value:    self.value(tokens, None)?,
+			});
+		}
+		Ok(EncodedAnnotation {
+			type_idx: type_idx.into(),
+			size: (elements.len() as u32).into(),
+			elements,
+		})
+	}
+
+	/// Parses an encoded value of type `typ`, which decides how number
+	/// literals are read. Values of unknown type, like the elements of
+	/// sub-annotations, go by the literal's suffix instead.
+	fn value(&mut self, tokens: &mut Tokens, typ: Option<&TypeDescriptor>) -> Result<EncodedValue> {
+		let word = match tokens.next()? {
+			Token::String(string) => return Ok(EncodedValue::String(self.pool.string(&string))),
+			Token::Char(c) => return Ok(EncodedValue::Char(c)),
+			Token::OpenBrace => {
+				let element = typ.and_then(TypeDescriptor::element_type);
+				let mut values = Vec::new();
+				if !tokens.eat(&Token::CloseBrace) {
+					loop {
+						values.push(self.value(tokens, element.as_ref())?);
+						if !tokens.eat(&Token::Comma) {
+							break;
+						}
+					}
+					tokens.expect(Token::CloseBrace)?;
+				}
+				return Ok(EncodedValue::Array(EncodedArray {
+					size: (values.len() as u32).into(),
+					values,
+				}));
+			}
+			Token::Directive(d) if d == "enum" => {
+				let field = tokens.word()?;
+				let idx = self.pool.field(&field).map_err(|e| tokens.error(e))?;
+				return Ok(EncodedValue::Enum(idx));
+			}
+			Token::Directive(d) if d == "subannotation" => {
+				let annotation = self.encoded_annotation(tokens)?;
+				tokens.end("subannotation")?;
+				return Ok(EncodedValue::Annotation(annotation));
+			}
+			Token::Word(word) => word,
+			token => return Err(tokens.error(format!("expected a value, found `{}`", token))),
+		};
+
+		let value = match word.as_str() {
+			"null" => EncodedValue::Null,
+			"true" => EncodedValue::Boolean(true),
+			"false" => EncodedValue::Boolean(false),
+			// `invoke-static@Lfoo/Bar;->m()V`
+			_ if word.contains('@') => return Err(tokens.unsupported("method handle values", word)),
+			_ if word.contains("->") && word.contains('(') => {
+				EncodedValue::Method(self.pool.method(&word).map_err(|e| tokens.error(e))?)
+			}
+			_ if word.contains("->") => {
+				EncodedValue::Field(self.pool.field(&word).map_err(|e| tokens.error(e))?)
+			}
+			_ if word.starts_with('(') => {
+				EncodedValue::MethodType(self.pool.proto(&word).map_err(|e| tokens.error(e))?)
+			}
+			_ if word.starts_with(['L', '[']) => {
+				EncodedValue::Type(self.pool.type_idx(&word).map_err(|e| tokens.error(e))?)
+			}
+			_ => literal(&word, typ).map_err(|e| tokens.error(e))?,
+		};
+		Ok(value)
+	}
+
+	/// Parses a method with its own annotations and those of each parameter.
+	fn method(
+		&mut self,
+		tokens: &mut Tokens,
+		class: &TypeDescriptor,
+	) -> Result<(Method, Vec<AnnotationItem>, Vec<Vec<AnnotationItem>>)> {
+		let access_flags = tokens.access_flags(AccessContext::Method);
+		let declaration = tokens.word()?;
+		let (name, proto) = declaration
+			.find('(')
+			.map(|paren| declaration.split_at(paren))
+			.ok_or_else(|| {
+				tokens.error(format!("expected `name(...)`, found `{}`", declaration))
+			})?;
+		let proto = parse_proto(proto).map_err(|e| tokens.error(e))?;
+
+		let mut body = MethodBody::default();
+		let mut annotations = Vec::new();
+		loop {
+			let token = tokens.next()?;
+			let line = tokens.line();
+			match token {
+				Token::Directive(directive) => match directive.as_str() {
+					"end" => match tokens.word()?.as_str() {
+						"method" => break,
+						"local" => {
+							let register = tokens.register()?;
+							tokens.skip_line();
+							body.statements
+								.push((line, Statement::Debug(Debug::EndLocal(register))));
+						}
+						kind => return Err(tokens.error(format!("unexpected `.end {}`", kind))),
+					},
+					"registers" => body.registers = Some(Registers::Total(tokens.unsigned()?)),
+					"locals" => body.registers = Some(Registers::Locals(tokens.unsigned()?)),
+					"annotation" => annotations.push(self.annotation(tokens)?),
+					"line" => {
+						let number = tokens.integer(32, true)? as u32;
+						body.statements
+							.push((line, Statement::Debug(Debug::Line(number))));
+					}
+					"local" => {
+						let local = self.local(tokens)?;
+						body.statements.push((line, Statement::Debug(local)));
+					}
+					"restart" => {
+						tokens.expect(Token::Word("local".to_string()))?;
+						let register = tokens.register()?;
+						tokens.skip_line();
+						body.statements
+							.push((line, Statement::Debug(Debug::RestartLocal(register))));
+					}
+					"prologue" => body
+						.statements
+						.push((line, Statement::Debug(Debug::Prologue))),
+					"epilogue" => body
+						.statements
+						.push((line, Statement::Debug(Debug::Epilogue))),
+					"source" => {
+						let file = match tokens.peek() {
+							Some(Token::String(_)) if tokens.on_same_line() => {
+								let file = tokens.string()?;
+								self.pool.string(&file);
+								Some(file)
+							}
+							_ => None,
+						};
+						body.statements
+							.push((line, Statement::Debug(Debug::Source(file))));
+					}
+					"param" => {
+						let register = tokens.register()?;
+						if tokens.on_same_line() && tokens.eat(&Token::Comma) {
+							let name = tokens.string()?;
+							self.pool.string(&name);
+							body.parameters.push((line, register, name));
+						}
+
+						// like in smali, the annotations only belong to the
+						// parameter if `.end param` closes the block
+						let mut parameter = Vec::new();
+						while tokens.eat(&Token::Directive("annotation".to_string())) {
+							parameter.push(self.annotation(tokens)?);
+						}
+						if tokens.peek() == Some(&Token::Directive("end".to_string()))
+							&& tokens.peek_nth(1) == Some(&Token::Word("param".to_string()))
+						{
+							tokens.end("param")?;
+							body.parameter_annotations.push((line, register, parameter));
+						} else {
+							annotations.extend(parameter);
+						}
+					}
+					"catch" | "catchall" => {
+						let exception = if directive == "catch" {
+							let descriptor = tokens.word()?;
+							Some(
+								self.pool
+									.type_idx(&descriptor)
+									.map_err(|e| tokens.error(e))?,
+							)
+						} else {
+							None
+						};
+						tokens.expect(Token::OpenBrace)?;
+						let start = tokens.label()?;
+						tokens.expect(Token::Word("..".to_string()))?;
+						let end = tokens.label()?;
+						tokens.expect(Token::CloseBrace)?;
+						let handler = tokens.label()?;
+						body.catches.push(Catch {
+							line,
+							exception,
+							start,
+							end,
+							handler,
+						});
+					}
+					"packed-switch" => {
+						let first_key = tokens.integer(32, true)? as i32;
+						let mut targets = Vec::new();
+						while let Some(Token::Label(_)) = tokens.peek() {
+							targets.push(tokens.label()?);
+						}
+						tokens.end("packed-switch")?;
+						body.statements
+							.push((line, Statement::PackedSwitch { first_key, targets }));
+					}
+					"sparse-switch" => {
+						let mut keys = Vec::new();
+						let mut targets = Vec::new();
+						while let Some(Token::Word(_)) = tokens.peek() {
+							keys.push(tokens.integer(32, true)? as i32);
+							tokens.expect(Token::Word("->".to_string()))?;
+							targets.push(tokens.label()?);
+						}
+						tokens.end("sparse-switch")?;
+						body.statements
+							.push((line, Statement::SparseSwitch { keys, targets }));
+					}
+					"array-data" => {
+						let element_width = tokens.unsigned()?;
+						if !matches!(element_width, 1 | 2 | 4 | 8) {
+							return Err(
+								tokens.error(format!("invalid element width {}", element_width))
+							);
+						}
+						let mut data = Vec::new();
+						loop {
+							let value = match tokens.peek() {
+								Some(Token::Char(c)) => {
+									let c = *c as i64;
+									tokens.next()?;
+									c
+								}
+								Some(Token::Word(_)) => {
+									tokens.integer(element_width as u32 * 8, true)?
+								}
+								_ => break,
+							};
+							data.extend_from_slice(&value.to_le_bytes()[..element_width as usize]);
+						}
+						tokens.end("array-data")?;
+						body.statements.push((
+							line,
+							Statement::ArrayData {
+								element_width,
+								data,
+							},
+						));
+					}
+					directive => {
+						return Err(tokens.error(format!("unexpected directive `.{}`", directive)))
+					}
+				},
+				Token::Label(label) => body.statements.push((line, Statement::Label(label))),
+				Token::Word(name) => {
+					let opcode = Opcode::from_name(&name)
+						.ok_or_else(|| tokens.error(format!("unknown instruction `{}`", name)))?;
+					let operands = self.operands(tokens, opcode)?;
+					body.statements
+						.push((line, Statement::Instruction(opcode, operands)));
+				}
+				token => return Err(tokens.error(format!("unexpected `{}`", token))),
+			}
+		}
+
+		let is_static = access_flags.contains(AccessFlags::STATIC);
+		let parameters = proto.parameters.as_deref().unwrap_or_default();
+		let mut parameter_annotations = vec![Vec::new(); parameters.len()];
+		for (line, register, annotations) in body.parameter_annotations.iter() {
+			let idx = body
+				.parameter_index(&proto, is_static, *register)
+				.map_err(|e| eyre!("line {}: {}", line, e))?;
+			parameter_annotations[idx].extend(annotations.iter().cloned());
+		}
+
+		let has_code = body
+			.statements
+			.iter()
+			.any(|(_, s)| !matches!(s, Statement::Label(_) | Statement::Debug(_)));
+		let (code, debug_info) = if has_code {
+			let (code, debug_info) = body.assemble(&proto, is_static)?;
+			(Some(code), debug_info)
+		} else {
+			(None, None)
+		};
+
+		let method = Method {
+			id: MethodId {
+				name: name.to_string(),
+				class: class.clone(),
+				proto,
+			},
+			access_flags,
+			code,
+			debug_info,
+			hiddenapi_flags: None,
+		};
+		Ok((method, annotations, parameter_annotations))
+	}
+
+	/// Parses the rest of `.local v0, "name":Ltype;` with an optional
+	/// `, "signature"`, unknown names and types are written as `null`.
+	fn local(&mut self, tokens: &mut Tokens) -> Result<Debug> {
+		let register = tokens.register()?;
+		tokens.expect(Token::Comma)?;
+		let (name, typ) = match tokens.next()? {
+			Token::String(name) => match tokens.next()? {
+				Token::Label(typ) => (Some(name), typ),
+				token => return Err(tokens.error(format!("expected `:type`, found `{}`", token))),
+			},
+			Token::Word(word) if word.starts_with("null:") => (None, word[5..].to_string()),
+			token => return Err(tokens.error(format!("expected a local name, found `{}`", token))),
+		};
+		let typ = match typ.as_str() {
+			"null" => None,
+			typ => Some(typ.parse::<TypeDescriptor>().map_err(|e| tokens.error(e))?),
+		};
+		let signature = if tokens.on_same_line() && tokens.eat(&Token::Comma) {
+			Some(tokens.string()?)
+		} else {
+			None
+		};
+
+		for string in name.iter().chain(signature.iter()) {
+			self.pool.string(string);
+		}
+		if let Some(typ) = &typ {
+			self.pool
+				.type_idx(&typ.to_string())
+				.map_err(|e| tokens.error(e))?;
+		}
+
+		Ok(Debug::Local {
+			register,
+			name,
+			typ,
+			signature,
+		})
+	}
+
+	/// Comma separated operands following an instruction name on the same line.
+	fn operands(&mut self, tokens: &mut Tokens, opcode: Opcode) -> Result<Vec<Operand>> {
+		let mut operands = Vec::new();
+		if !tokens.on_same_line() {
+			return Ok(operands);
+		}
+
+		let kinds = [opcode.reference_type(), opcode.reference_type_2()];
+		let mut references = 0;
+		loop {
+			let operand = match tokens.next()? {
+				Token::Register(register) => Operand::Register(register),
+				Token::Label(label) => Operand::Label(label),
+				Token::Char(c) => Operand::Literal(c as i128),
+				Token::OpenBrace => {
+					let mut registers = Vec::new();
+					if !tokens.eat(&Token::CloseBrace) {
+						registers.push(tokens.register()?);
+						if tokens.eat(&Token::Word("..".to_string())) {
+							let last = tokens.register()?;
+							tokens.expect(Token::CloseBrace)?;
+							operands.push(Operand::Range(registers[0], last));
+							if !tokens.eat(&Token::Comma) {
+								break;
+							}
+							continue;
+						}
+						while tokens.eat(&Token::Comma) {
+							registers.push(tokens.register()?);
+						}
+						tokens.expect(Token::CloseBrace)?;
+					}
+					Operand::List(registers)
+				}
+				Token::String(string) if kinds.get(references) == Some(&ReferenceType::String) => {
+					references += 1;
+					Operand::Reference(self.pool.string(&string))
+				}
+				Token::Word(word) => match parse_integer(&word) {
+					Some(value) => Operand::Literal(value),
+					None => {
+						let kind = kinds.get(references).copied().unwrap_or_default();
+						references += 1;
+						if let Some(feature) = unsupported_reference(kind) {
+							return Err(tokens.unsupported(feature, word));
+						}
+						let idx = self
+							.pool
+							.reference(kind, &word)
+							.map_err(|e| tokens.error(e))?;
+						Operand::Reference(idx)
+					}
+				},
+				token => return Err(tokens.error(format!("unexpected operand `{}`", token))),
+			};
+			operands.push(operand);
+
+			if !tokens.eat(&Token::Comma) {
+				break;
+			}
+		}
+
+		Ok(operands)
+	}
+}
+
+enum Registers {
+	/// `.registers`, the whole frame.
+	Total(u16),
+	/// `.locals`, the frame without the parameters.
+	Locals(u16),
+}
+
+enum Operand {
+	Register(Register),
+	/// `{v0, v1}`
+	List(Vec<Register>),
+	/// `{v0 .. v5}`
+	Range(Register, Register),
+	Label(String),
+	Literal(i128),
+	/// Index into the [`Pool`] list for the opcode's reference type.
+	Reference(u32),
+}
+
+/// A debug directive, which applies to the address of the next instruction.
+enum Debug {
+	Line(u32),
+	Local {
+		register:  Register,
+		name:      Option<String>,
+		typ:       Option<TypeDescriptor>,
+		signature: Option<String>,
+	},
+	EndLocal(Register),
+	RestartLocal(Register),
+	Prologue,
+	Epilogue,
+	Source(Option<String>),
+}
+
+impl Debug {
+	fn event(&self, frame: &Frame) -> Result<DebugEvent> {
+		Ok(match self {
+			Debug::Line(line) => DebugEvent::Line(*line),
+			Debug::Local {
+				register,
+				name,
+				typ,
+				signature,
+			} => DebugEvent::StartLocal {
+				register:  frame.register(*register)?,
+				name:      name.clone(),
+				typ:       typ.clone(),
+				signature: signature.clone(),
+			},
+			Debug::EndLocal(register) => DebugEvent::EndLocal(frame.register(*register)?),
+			Debug::RestartLocal(register) => DebugEvent::RestartLocal(frame.register(*register)?),
+			Debug::Prologue => DebugEvent::PrologueEnd,
+			Debug::Epilogue => DebugEvent::EpilogueBegin,
+			Debug::Source(file) => DebugEvent::SetFile(file.clone()),
+		})
+	}
+}
+
+enum Statement {
+	Label(String),
+	Debug(Debug),
+	Instruction(Opcode, Vec<Operand>),
+	PackedSwitch {
+		first_key: i32,
+		targets:   Vec<String>,
+	},
+	SparseSwitch {
+		keys:    Vec<i32>,
+		targets: Vec<String>,
+	},
+	ArrayData {
+		element_width: u16,
+		data:          Vec<u8>,
+	},
+}
+
+impl Statement {
+	fn code_units(&self) -> u32 {
+		match self {
+			Statement::Label(_) | Statement::Debug(_) => 0,
+			Statement::Instruction(opcode, _) => opcode.format().size().max(0) as u32 / 2,
+			Statement::PackedSwitch { targets, .. } => targets.len() as u32 * 2 + 4,
+			Statement::SparseSwitch { keys, .. } => keys.len() as u32 * 4 + 2,
+			Statement::ArrayData { data, .. } => (data.len() as u32).div_ceil(2) + 4,
+		}
+	}
+
+	fn is_payload(&self) -> bool {
+		matches!(
+			self,
+			Statement::PackedSwitch { .. }
+				| Statement::SparseSwitch { .. }
+				| Statement::ArrayData { .. }
+		)
+	}
+}
+
+struct Catch {
+	line:      usize,
+	exception: Option<u32>,
+	start:     String,
+	end:       String,
+	handler:   String,
+}
+
+#[derive(Clone, Default, PartialEq)]
+struct Handler {
+	pairs:     Vec<(u32, u32)>,
+	catch_all: Option<u32>,
+}
+
+#[derive(Default)]
+struct MethodBody {
+	registers:             Option<Registers>,
+	statements:            Vec<(usize, Statement)>,
+	catches:               Vec<Catch>,
+	/// `.param` names by their register.
+	parameters:            Vec<(usize, Register, String)>,
+	/// Annotations of `.param ... .end param` blocks by their register.
+	parameter_annotations: Vec<(usize, Register, Vec<AnnotationItem>)>,
+}
+
+impl MethodBody {
+	fn assemble(self, proto: &Proto, is_static: bool) -> Result<(CodeItem, Option<DebugInfo>)> {
+		let ins = ins_size(proto, is_static);
+		let registers_size = match self.registers {
+			Some(Registers::Total(registers)) => registers,
+			Some(Registers::Locals(locals)) => locals
+				.checked_add(ins)
+				.ok_or_else(|| eyre!("too many registers"))?,
+			None => bail!("method has no .registers or .locals directive"),
+		};
+		ensure!(
+			registers_size >= ins,
+			"method needs at least {} registers for its parameters, but only has {}",
+			ins,
+			registers_size
+		);
+
+		// payloads have to be 4 byte aligned, a nop is inserted in front of
+		// them where needed; labels and debug directives right before a
+		// payload point to the payload
+		let mut labels = HashMap::new();
+		let mut pending = Vec::new();
+		let mut pending_debug = Vec::new();
+		let mut debug = Vec::new();
+		let mut layout = Vec::new();
+		let mut addr = 0u32;
+		for (line, statement) in self.statements.iter() {
+			match statement {
+				Statement::Label(label) => {
+					pending.push((*line, label));
+					continue;
+				}
+				Statement::Debug(directive) => {
+					pending_debug.push((*line, directive));
+					continue;
+				}
+				_ => {}
+			}
+			if statement.is_payload() && !addr.is_multiple_of(2) {
+				layout.push((addr, *line, None));
+				addr += 1;
+			}
+			for (line, label) in pending.drain(..) {
+				if labels.insert(label.clone(), addr).is_some() {
+					bail!("line {}: duplicate label :{}", line, label);
+				}
+			}
+			debug.extend(pending_debug.drain(..).map(|(line, d)| (addr, line, d)));
+			layout.push((addr, *line, Some(statement)));
+			addr += statement.code_units();
+		}
+		for (line, label) in pending.drain(..) {
+			if labels.insert(label.clone(), addr).is_some() {
+				bail!("line {}: duplicate label :{}", line, label);
+			}
+		}
+		debug.extend(pending_debug.drain(..).map(|(line, d)| (addr, line, d)));
+
+		let frame = Frame {
+			registers: registers_size,
+			ins,
+			labels: &labels,
+		};
+
+		// switch and array payload targets are relative to the instruction using them
+		let mut owners = HashMap::new();
+		for (addr, line, statement) in layout.iter() {
+			if let Some(Statement::Instruction(opcode, operands)) = statement {
+				if opcode.format() == Format::Format31t {
+					if let Some(Operand::Label(label)) = operands.get(1) {
+						let target = frame
+							.label(label)
+							.map_err(|e| eyre!("line {}: {}", line, e))?;
+						owners.insert(target, *addr);
+					}
+				}
+			}
+		}
+
+		let mut insns = Vec::with_capacity(layout.len());
+		let mut outs_size = 0;
+		for (addr, line, statement) in layout {
+			let relative_targets = |targets: &[String]| -> Result<Vec<i32>> {
+				let base = *owners.get(&addr).ok_or_else(|| {
+					eyre!("line {}: payload is not used by any instruction", line)
+				})?;
+				targets
+					.iter()
+					.map(|t| Ok(frame.label(t)? as i32 - base as i32))
+					.collect::<Result<Vec<_>>>()
+					.map_err(|e| eyre!("line {}: {}", line, e))
+			};
+
+			let insn = match statement {
+				None => Instruction::Instruction10x(Opcode::Nop),
+				Some(Statement::Instruction(opcode, operands)) => {
+					let operands = Operands {
+						operands,
+						frame: &frame,
+						addr,
+					};
+					encode(*opcode, &operands).map_err(|e| eyre!("line {}: {}", line, e))?
+				}
+				Some(Statement::PackedSwitch { first_key, targets }) => {
+					Instruction::PackedSwitchPayload {
+						size:      u16::try_from(targets.len())?,
+						first_key: *first_key,
+						targets:   relative_targets(targets)?,
+					}
+				}
+				Some(Statement::SparseSwitch { keys, targets }) => {
+					Instruction::SparseSwitchPayload {
+						size:    u16::try_from(keys.len())?,
+						keys:    keys.clone(),
+						targets: relative_targets(targets)?,
+					}
+				}
+				Some(Statement::ArrayData {
+					element_width,
+					data,
+				}) => Instruction::FillArrayDataPayload {
+					element_width: *element_width,
+					size:          data.len() as u32 / *element_width as u32,
+					data:          data.clone(),
+				},
+				Some(Statement::Label(_) | Statement::Debug(_)) => {
+					unreachable!("labels and debug directives are not part of the layout")
+				}
+			};
+			outs_size = outs_size.max(invoke_arguments(&insn));
+			insns.push(insn);
+		}
+
+		let (tries, handlers) = self.tries(&frame)?;
+		let debug_info = self.debug_info(&frame, proto, is_static, &debug)?;
+
+		let code = CodeItem {
+			registers_size,
+			ins_size: ins,
+			outs_size,
+			tries_size: tries.len() as u16,
+			debug_info_off: Ref::new(0),
+			padding: (!tries.is_empty() && !addr.is_multiple_of(2)).then_some(0),
+			insns,
+			tries: (!tries.is_empty()).then_some(tries),
+			handlers,
+		};
+		Ok((code, debug_info))
+	}
+
+	/// Index of the parameter starting at `register`. Only methods with a
+	/// `.registers` or `.locals` directive can name parameters by `vN`.
+	fn parameter_index(&self, proto: &Proto, is_static: bool, register: Register) -> Result<usize> {
+		let ins = ins_size(proto, is_static);
+		let parameter = match (register, &self.registers) {
+			(Register::Parameter(p), _) => Some(p),
+			(Register::Local(r), Some(Registers::Total(registers))) => {
+				r.checked_sub(registers.saturating_sub(ins))
+			}
+			(Register::Local(r), Some(Registers::Locals(locals))) => r.checked_sub(*locals),
+			(Register::Local(_), None) => {
+				bail!("{} needs a .registers or .locals directive", register)
+			}
+		};
+
+		// `this` comes before the parameters
+		let mut first = if is_static { 0 } else { 1 };
+		for (i, typ) in proto.parameters.iter().flatten().enumerate() {
+			if parameter == Some(first) {
+				return Ok(i);
+			}
+			first += typ.register_width();
+		}
+		bail!("{} is not the first register of a parameter", register)
+	}
+
+	/// Runs the debug directives through the same state machine as the debug
+	/// info of a parsed method, `None` if there are none.
+	fn debug_info(
+		&self,
+		frame: &Frame,
+		proto: &Proto,
+		is_static: bool,
+		debug: &[(u32, usize, &Debug)],
+	) -> Result<Option<DebugInfo>> {
+		if debug.is_empty() && self.parameters.is_empty() {
+			return Ok(None);
+		}
+
+		let parameters = proto.parameters.as_deref().unwrap_or_default();
+		let mut parameter_names = vec![None; parameters.len()];
+		for (line, register, name) in self.parameters.iter() {
+			let idx = self
+				.parameter_index(proto, is_static, *register)
+				.map_err(|e| eyre!("line {}: {}", line, e))?;
+			parameter_names[idx] = Some(name.clone());
+		}
+
+		let line_start = debug
+			.iter()
+			.find_map(|(_, _, d)| match d {
+				Debug::Line(line) => Some(*line),
+				_ => None,
+			})
+			.unwrap_or(0);
+		let mut builder = DebugInfoBuilder::new(line_start, parameter_names);
+		for (addr, line, directive) in debug {
+			let event = directive
+				.event(frame)
+				.map_err(|e| eyre!("line {}: {}", line, e))?;
+			builder.event(*addr, event);
+		}
+		Ok(Some(builder.finish()))
+	}
+
+	/// Splits the possibly overlapping `.catch` ranges into the disjoint try
+	/// blocks the dex format wants, sharing identical handlers.
+	fn tries(&self, frame: &Frame) -> Result<(Vec<TryItem>, Option<EncodedCatchHandlerList>)> {
+		let mut ranges = Vec::with_capacity(self.catches.len());
+		let mut bounds = BTreeSet::new();
+		for catch in self.catches.iter() {
+			let range = (|| {
+				let start = frame.label(&catch.start)?;
+				let end = frame.label(&catch.end)?;
+				ensure!(
+					start < end,
+					"try range :{} .. :{} is empty",
+					catch.start,
+					catch.end
+				);
+				Ok((start, end, catch.exception, frame.label(&catch.handler)?))
+			})()
+			.map_err(|e| eyre!("line {}: {}", catch.line, e))?;
+			bounds.insert(range.0);
+			bounds.insert(range.1);
+			ranges.push(range);
+		}
+
+		let mut blocks: Vec<(u32, u32, Handler)> = Vec::new();
+		for (&start, &end) in bounds.iter().zip(bounds.iter().skip(1)) {
+			let mut handler = Handler::default();
+			for &(_, _, exception, addr) in
+				ranges.iter().filter(|(s, e, ..)| *s <= start && end <= *e)
+			{
+				match exception {
+					Some(typ) if !handler.pairs.iter().any(|(t, _)| *t == typ) => {
+						handler.pairs.push((typ, addr))
+					}
+					None if handler.catch_all.is_none() => handler.catch_all = Some(addr),
+					_ => {}
+				}
+			}
+			if handler == Handler::default() {
+				continue;
+			}
+			match blocks.last_mut() {
+				Some(last) if last.1 == start && last.2 == handler => last.1 = end,
+				_ => blocks.push((start, end, handler)),
+			}
+		}
+
+		if blocks.is_empty() {
+			return Ok((Vec::new(), None));
+		}
+
+		let mut unique: Vec<&Handler> = Vec::new();
+		for (_, _, handler) in blocks.iter() {
+			if !unique.contains(&handler) {
+				unique.push(handler);
+			}
+		}
+		let size = Uleb128::from(unique.len() as u32);
+		let list = unique
+			.iter()
+			.map(|handler| {
+				let count = handler.pairs.len() as i32;
+				EncodedCatchHandler {
+					size:           if handler.catch_all.is_some() {
+						-count
+					} else {
+						count
+					}
+					.into(),
+					handlers:       handler
+						.pairs
+						.iter()
+						.map(|&(typ, addr)| EncodedTypeAddrPair {
+							type_idx: typ.into(),
+							addr:     addr.into(),
+						})
+						.collect(),
+					catch_all_addr: handler.catch_all.map(Into::into),
+				}
+			})
+			.collect::<Vec<_>>();
+
+		let mut offsets = Vec::with_capacity(list.len());
+		let mut offset = size.encoded_size();
+		for handler in list.iter() {
+			offsets.push(offset);
+			offset += handler.encoded_size();
+		}
+
+		let tries = blocks
+			.iter()
+			.map(|(start, end, handler)| {
+				let idx = unique
+					.iter()
+					.position(|h| *h == handler)
+					.unwrap_or_default();
+				Ok(TryItem {
+					start_addr:  *start,
+					insn_count:  u16::try_from(end - start)
+						.map_err(|_| eyre!("try block at {:#x} is too long", start))?,
+					handler_off: u16::try_from(offsets[idx])
+						.map_err(|_| eyre!("too many catch handlers"))?,
+				})
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		Ok((tries, Some(EncodedCatchHandlerList { size, list })))
+	}
+}
+
+/// Registers taken by the parameters of a method, including `this`.
+fn ins_size(proto: &Proto, is_static: bool) -> u16 {
+	proto
+		.parameters
+		.iter()
+		.flatten()
+		.map(TypeDescriptor::register_width)
+		.sum::<u16>()
+		+ if is_static { 0 } else { 1 }
+}
+
+/// What a reference of `kind` is called if the assembler can't assemble it.
+fn unsupported_reference(kind: ReferenceType) -> Option<&'static str> {
+	match kind {
+		ReferenceType::MethodHandle => Some("method handle references"),
+		ReferenceType::CallSite => Some("call site references"),
+		_ => None,
+	}
+}
+
+/// Number of argument words an invoke instruction passes, zero for everything else.
+fn invoke_arguments(insn: &Instruction) -> u16 {
+	use Instruction::*;
+
+	match insn {
+		Instruction35c(op, (a, ..)) | Instruction45cc(op, (a, ..))
+			if op.reference_type() == ReferenceType::Method =>
+		{
+			*a as u16
+		}
+		Instruction3rc(op, (aa, ..)) | Instruction4rcc(op, (aa, ..))
+			if op.reference_type() == ReferenceType::Method =>
+		{
+			*aa as u16
+		}
+		_ => 0,
+	}
+}
+
+struct Frame<'a> {
+	registers: u16,
+	ins:       u16,
+	labels:    &'a HashMap<String, u32>,
+}
+
+impl Frame<'_> {
+	fn register(&self, register: Register) -> Result<u16> {
+		match register {
+			Register::Local(r) if r < self.registers => Ok(r),
+			Register::Parameter(p) if p < self.ins => Ok(self.registers - self.ins + p),
+			Register::Local(_) => bail!(
+				"{} is out of range, the method has {} registers",
+				register,
+				self.registers
+			),
+			Register::Parameter(_) => bail!(
+				"{} is out of range, the method has {} parameter registers",
+				register,
+				self.ins
+			),
+		}
+	}
+
+	fn label(&self, label: &str) -> Result<u32> {
+		self.labels
+			.get(label)
+			.copied()
+			.ok_or_else(|| eyre!("undefined label :{}", label))
+	}
+}
+
+struct Operands<'a> {
+	operands: &'a [Operand],
+	frame:    &'a Frame<'a>,
+	addr:     u32,
+}
+
+impl Operands<'_> {
+	fn get(&self, i: usize) -> &Operand {
+		&self.operands[i]
+	}
+
+	fn register(&self, i: usize, bits: u32) -> Result<u16> {
+		let register = match self.get(i) {
+			Operand::Register(register) => *register,
+			_ => bail!("operand {} has to be a register", i + 1),
+		};
+		let r = self.frame.register(register)?;
+		ensure!(
+			(r as u32) < 1 << bits,
+			"{} doesn't fit into {} bits",
+			register,
+			bits
+		);
+		Ok(r)
+	}
+
+	fn literal(&self, i: usize, bits: u32, allow_unsigned: bool) -> Result<i64> {
+		match self.get(i) {
+			Operand::Literal(value) => check_range(*value, bits, allow_unsigned),
+			_ => bail!("operand {} has to be a literal", i + 1),
+		}
+	}
+
+	fn target(&self, i: usize, bits: u32) -> Result<i32> {
+		let target = match self.get(i) {
+			Operand::Label(label) => self.frame.label(label)?,
+			_ => bail!("operand {} has to be a label", i + 1),
+		};
+		let offset = target as i64 - self.addr as i64;
+		Ok(check_range(offset as i128, bits, false)? as i32)
+	}
+
+	fn reference(&self, i: usize, bits: u32) -> Result<u32> {
+		match self.get(i) {
+			Operand::Reference(idx) if (*idx as u64) < 1 << bits => Ok(*idx),
+			Operand::Reference(idx) => {
+				bail!("reference index {} doesn't fit into {} bits", idx, bits)
+			}
+			_ => bail!("operand {} has to be a reference", i + 1),
+		}
+	}
+
+	/// `{vC, vD, vE, vF, vG}`
+	fn list(&self, i: usize) -> Result<Vec<u8>> {
+		let registers = match self.get(i) {
+			Operand::List(registers) => registers,
+			_ => bail!("operand {} has to be a register list", i + 1),
+		};
+		ensure!(
+			registers.len() <= 5,
+			"at most 5 registers can be passed, use the /range variant"
+		);
+		registers
+			.iter()
+			.map(|&register| {
+				let r = self.frame.register(register)?;
+				ensure!(r < 16, "{} doesn't fit into 4 bits", register);
+				Ok(r as u8)
+			})
+			.collect()
+	}
+
+	/// `{vCCCC .. vNNNN}` as first register and count.
+	fn range(&self, i: usize) -> Result<(u16, u8)> {
+		let (first, count) = match self.get(i) {
+			Operand::Range(first, last) => {
+				let first = self.frame.register(*first)?;
+				let last = self.frame.register(*last)?;
+				ensure!(first <= last, "register range is reversed");
+				(first, last - first + 1)
+			}
+			Operand::List(registers) => {
+				let registers = registers
+					.iter()
+					.map(|&r| self.frame.register(r))
+					.collect::<Result<Vec<_>>>()?;
+				ensure!(
+					registers.windows(2).all(|w| w[1] == w[0] + 1),
+					"registers of a /range instruction have to be consecutive"
+				);
+				(
+					registers.first().copied().unwrap_or(0),
+					registers.len() as u16,
+				)
+			}
+			_ => bail!("operand {} has to be a register range", i + 1),
+		};
+		let count =
+			u8::try_from(count).map_err(|_| eyre!("at most 255 registers can be passed"))?;
+		Ok((first, count))
+	}
+}
+
+/// Number of operands written for each format.
+fn operand_count(format: &Format) -> Option<usize> {
+	use Format::*;
+
+	Some(match format {
+		Format10x => 0,
+		Format10t | Format11x | Format20t | Format30t => 1,
+		Format12x | Format11n | Format22x | Format21t | Format21s | Format21ih | Format21lh
+		| Format21c | Format32x | Format31i | Format31t | Format31c | Format35c | Format3rc
+		| Format51l => 2,
+		Format23x | Format22b | Format22t | Format22s | Format22c | Format45cc | Format4rcc => 3,
+		_ => return None,
+	})
+}
+
+fn encode(op: Opcode, o: &Operands) -> Result<Instruction> {
+	use Format::*;
+	use Instruction::*;
+
+	ensure!(
+		!op.flags().contains(OpcodeFlags::ODEX_ONLY),
+		"{} is only valid in odex files",
+		op.name()
+	);
+	let format = op.format();
+	let expected = operand_count(&format)
+		.ok_or_else(|| eyre!("{} instructions can't be assembled", op.name()))?;
+	ensure!(
+		o.operands.len() == expected,
+		"{} takes {} operands, found {}",
+		op.name(),
+		expected,
+		o.operands.len()
+	);
+
+	Ok(match format {
+		Format10x => Instruction10x(op),
+		Format12x => Instruction12x(op, (o.register(0, 4)? as u8, o.register(1, 4)? as u8)),
+		Format11n => Instruction11n(
+			op,
+			(o.register(0, 4)? as u8, o.literal(1, 4, false)? as u8 & 0xf),
+		),
+		Format11x => Instruction11x(op, (o.register(0, 8)? as u8,)),
+		Format10t => Instruction10t(op, (o.target(0, 8)? as i8,)),
+		Format20t => Instruction20t(op, (o.target(0, 16)? as i16,)),
+		Format22x => Instruction22x(op, (o.register(0, 8)? as u8, o.register(1, 16)?)),
+		Format21t => Instruction21t(op, (o.register(0, 8)? as u8, o.target(1, 16)? as i16)),
+		Format21s => Instruction21s(
+			op,
+			(o.register(0, 8)? as u8, o.literal(1, 16, false)? as u16),
+		),
+		Format21ih => {
+			let value = o.literal(1, 32, true)?;
+			ensure!(
+				value & 0xffff == 0,
+				"the low 16 bits of the literal have to be 0"
+			);
+			Instruction21ih(op, (o.register(0, 8)? as u8, value as i32))
+		}
+		Format21lh => {
+			let value = o.literal(1, 64, true)?;
+			ensure!(
+				value & 0xffff_ffff_ffff == 0,
+				"the low 48 bits of the literal have to be 0"
+			);
+			Instruction21lh(op, (o.register(0, 8)? as u8, value))
+		}
+		Format21c => Instruction21c(op, (o.register(0, 8)? as u8, o.reference(1, 16)? as u16)),
+		Format23x => Instruction23x(
+			op,
+			(
+				o.register(0, 8)? as u8,
+				o.register(1, 8)? as u8,
+				o.register(2, 8)? as u8,
+			),
+		),
+		Format22b => Instruction22b(
+			op,
+			(
+				o.register(0, 8)? as u8,
+				o.register(1, 8)? as u8,
+				o.literal(2, 8, false)? as u8,
+			),
+		),
+		Format22t => Instruction22t(
+			op,
+			(
+				o.register(0, 4)? as u8,
+				o.register(1, 4)? as u8,
+				o.target(2, 16)? as i16,
+			),
+		),
+		Format22s => Instruction22s(
+			op,
+			(
+				o.register(0, 4)? as u8,
+				o.register(1, 4)? as u8,
+				o.literal(2, 16, false)? as u16,
+			),
+		),
+		Format22c => Instruction22c(
+			op,
+			(
+				o.register(0, 4)? as u8,
+				o.register(1, 4)? as u8,
+				o.reference(2, 16)? as u16,
+			),
+		),
+		Format30t => Instruction30t(op, (o.target(0, 32)?,)),
+		Format32x => Instruction32x(op, (o.register(0, 16)?, o.register(1, 16)?)),
+		Format31i => {
+			// bit patterns like 0xffffffff are fine for ints, but not for longs
+			let wide = op.flags().contains(OpcodeFlags::SETS_WIDE_REGISTER);
+			Instruction31i(
+				op,
+				(o.register(0, 8)? as u8, o.literal(1, 32, !wide)? as u32),
+			)
+		}
+		Format31t => Instruction31t(op, (o.register(0, 8)? as u8, o.target(1, 32)?)),
+		Format31c => Instruction31c(op, (o.register(0, 8)? as u8, o.reference(1, 32)?)),
+		Format35c => {
+			let registers = o.list(0)?;
+			let r = |i: usize| registers.get(i).copied().unwrap_or(0);
+			Instruction35c(
+				op,
+				(
+					registers.len() as u8,
+					r(4),
+					o.reference(1, 16)? as u16,
+					r(3),
+					r(2),
+					r(1),
+					r(0),
+				),
+			)
+		}
+		Format3rc => {
+			let (first, count) = o.range(0)?;
+			Instruction3rc(op, (count, o.reference(1, 16)? as u16, first))
+		}
+		Format45cc => {
+			let registers = o.list(0)?;
+			let r = |i: usize| registers.get(i).copied().unwrap_or(0);
+			Instruction45cc(
+				op,
+				(
+					registers.len() as u8,
+					r(4),
+					o.reference(1, 16)? as u16,
+					r(3),
+					r(2),
+					r(1),
+					r(0),
+					o.reference(2, 16)? as u16,
+				),
+			)
+		}
+		Format4rcc => {
+			let (first, count) = o.range(0)?;
+			Instruction4rcc(
+				op,
+				(
+					count,
+					o.reference(1, 16)? as u16,
+					first,
+					o.reference(2, 16)? as u16,
+				),
+			)
+		}
+		Format51l => Instruction51l(
+			op,
+			(o.register(0, 8)? as u8, o.literal(1, 64, true)? as u64),
+		),
+		_ => bail!("{} instructions can't be assembled", op.name()),
+	})
+}
+
+/// Checks that a literal fits into `bits` bits, either as a signed value or,
+/// if `allow_unsigned` is set, as a raw bit pattern like `0xffffffff`.
+fn check_range(value: i128, bits: u32, allow_unsigned: bool) -> Result<i64> {
+	let min = -(1i128 << (bits - 1));
+	let max = if allow_unsigned {
+		(1i128 << bits) - 1
+	} else {
+		(1i128 << (bits - 1)) - 1
+	};
+	ensure!(
+		(min..=max).contains(&value),
+		"literal {} doesn't fit into {} bits",
+		value,
+		bits
+	);
+	// sign extend bit patterns so `as` casts to the field width are lossless
+	let shift = 128 - bits;
+	Ok(((value << shift) >> shift) as i64)
+}
+
+/// Parses an integer literal: `10`, `-0x1f`, `010`, optionally with one of the
+/// `L`, `t` or `s` suffixes baksmali writes for longs, bytes and shorts.
+fn parse_integer(word: &str) -> Option<i128> {
+	let word = word
+		.strip_suffix(['L', 'l', 't', 'T', 's', 'S'])
+		.unwrap_or(word);
+	let (negative, digits) = match word.strip_prefix('-') {
+		Some(digits) => (true, digits),
+		None => (false, word.strip_prefix('+').unwrap_or(word)),
+	};
+	if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+		return None;
+	}
+
+	let magnitude = if let Some(hex) = digits
+		.strip_prefix("0x")
+		.or_else(|| digits.strip_prefix("0X"))
+	{
+		u64::from_str_radix(hex, 16).ok()?
+	} else if digits.len() > 1 && digits.starts_with('0') {
+		u64::from_str_radix(&digits[1..], 8).ok()?
+	} else {
+		digits.parse::<u64>().ok()?
+	} as i128;
+
+	Some(if negative { -magnitude } else { magnitude })
+}
+
+/// Parses a number literal as a value of primitive type `typ`, or of the type
+/// its suffix implies if `typ` isn't a number type.
+fn literal(word: &str, typ: Option<&TypeDescriptor>) -> Result<EncodedValue> {
+	let typ = match typ {
+		Some(typ) if typ.is_primitive() && *typ != TypeDescriptor::Boolean => typ.clone(),
+		_ => literal_type(word),
+	};
+
+	Ok(match typ {
+		TypeDescriptor::Float => EncodedValue::Float(
+			word.strip_suffix(['f', 'F'])
+				.unwrap_or(word)
+				.parse()
+				.map_err(|_| eyre!("expected a float, found `{}`", word))?,
+		),
+		TypeDescriptor::Double => EncodedValue::Double(
+			word.strip_suffix(['d', 'D'])
+				.unwrap_or(word)
+				.parse()
+				.map_err(|_| eyre!("expected a double, found `{}`", word))?,
+		),
+		_ => {
+			let value = parse_integer(word)
+				.ok_or_else(|| eyre!("expected an integer, found `{}`", word))?;
+			match typ {
+				TypeDescriptor::Byte => EncodedValue::Byte(check_range(value, 8, true)? as u8),
+				TypeDescriptor::Short => EncodedValue::Short(check_range(value, 16, true)? as i16),
+				TypeDescriptor::Char => EncodedValue::Char(check_range(value, 16, true)? as u16),
+				TypeDescriptor::Int => EncodedValue::Int(check_range(value, 32, true)? as i32),
+				_ => EncodedValue::Long(check_range(value, 64, true)?),
+			}
+		}
+	})
+}
+
+/// Guesses the type of a number literal from its suffix: `t` for bytes, `s`
+/// for shorts, `L` for longs, `f` for floats and `d` or a fraction for
+/// doubles.
+fn literal_type(word: &str) -> TypeDescriptor {
+	let digits = word.trim_start_matches(['-', '+']);
+	let hex = digits.starts_with("0x") || digits.starts_with("0X");
+	let fraction = !hex && (word.contains(['.', 'e', 'E']) || word.contains("Infinity"));
+	match word.chars().last() {
+		Some('t' | 'T') => TypeDescriptor::Byte,
+		Some('s' | 'S') => TypeDescriptor::Short,
+		Some('l' | 'L') => TypeDescriptor::Long,
+		Some('f' | 'F') if !hex => TypeDescriptor::Float,
+		Some('d' | 'D') if !hex => TypeDescriptor::Double,
+		_ if fraction || word == "NaN" => TypeDescriptor::Double,
+		_ => TypeDescriptor::Int,
+	}
+}
+
+/// Parses a `(...)R` method descriptor.
+fn parse_proto(s: &str) -> Result<Proto> {
+	let (parameters, return_type) = s
+		.strip_prefix('(')
+		.and_then(|s| s.split_once(')'))
+		.ok_or_else(|| eyre!("invalid method descriptor `{}`", s))?;
+
+	let mut rest = parameters;
+	let mut list = Vec::new();
+	while !rest.is_empty() {
+		let (parameter, tail) = TypeDescriptor::parse_prefix(rest)?;
+		ensure!(
+			parameter != TypeDescriptor::Void,
+			"void parameter in method descriptor `{}`",
+			s
+		);
+		list.push(parameter);
+		rest = tail;
+	}
+	let return_type = return_type.parse()?;
+
+	Ok(Proto {
+		shorty_descriptor: Proto::shorty_of(&return_type, &list),
+		return_type,
+		parameters: (!list.is_empty()).then_some(list),
+	})
+}
+
+/// Splits `Lfoo/Bar;->member` into class descriptor and member, the class
+/// is checked by whoever resolves it.
+fn split_member(reference: &str) -> Result<(&str, &str)> {
+	let (class, member) = reference
+		.split_once("->")
+		.ok_or_else(|| eyre!("expected a member reference, found `{}`", reference))?;
+	Ok((class, member))
+}
+
+struct Tokens {
+	tokens: Vec<Spanned>,
+	pos:    usize,
+}
+
+impl Tokens {
+	fn new(tokens: Vec<Spanned>) -> Self {
+		Tokens { tokens, pos: 0 }
+	}
+
+	/// Line of the last consumed token.
+	fn line(&self) -> usize {
+		self.tokens
+			.get(self.pos.saturating_sub(1))
+			.map_or(1, |t| t.line)
+	}
+
+	fn error(&self, message: impl Display) -> Report {
+		eyre!("line {}: {}", self.line(), message)
+	}
+
+	fn unsupported(&self, feature: &'static str, found: String) -> Report {
+		AssembleError::Unsupported {
+			line: self.line(),
+			feature,
+			found,
+		}
+		.into()
+	}
+
+	fn peek(&self) -> Option<&Token> {
+		self.peek_nth(0)
+	}
+
+	fn peek_nth(&self, n: usize) -> Option<&Token> {
+		self.tokens.get(self.pos + n).map(|t| &t.token)
+	}
+
+	fn next_opt(&mut self) -> Option<Token> {
+		let token = self.tokens.get(self.pos)?.token.clone();
+		self.pos += 1;
+		Some(token)
+	}
+
+	fn next(&mut self) -> Result<Token> {
+		self.next_opt()
+			.ok_or_else(|| self.error("unexpected end of file"))
+	}
+
+	fn on_same_line(&self) -> bool {
+		self.tokens
+			.get(self.pos)
+			.is_some_and(|t| t.line == self.line())
+	}
+
+	fn eat(&mut self, token: &Token) -> bool {
+		if self.peek() == Some(token) {
+			self.pos += 1;
+			true
+		} else {
+			false
+		}
+	}
+
+	fn expect(&mut self, expected: Token) -> Result<()> {
+		match self.next()? {
+			token if token == expected => Ok(()),
+			token => Err(self.error(format!("expected `{}`, found `{}`", expected, token))),
+		}
+	}
+
+	fn directive(&mut self, name: &str) -> Result<()> {
+		self.expect(Token::Directive(name.to_string()))
+	}
+
+	/// `.end <kind>`
+	fn end(&mut self, kind: &str) -> Result<()> {
+		self.directive("end")?;
+		self.expect(Token::Word(kind.to_string()))
+	}
+
+	fn word(&mut self) -> Result<String> {
+		match self.next()? {
+			Token::Word(word) => Ok(word),
+			token => Err(self.error(format!("expected a word, found `{}`", token))),
+		}
+	}
+
+	fn string(&mut self) -> Result<String> {
+		match self.next()? {
+			Token::String(string) => Ok(string),
+			token => Err(self.error(format!("expected a string, found `{}`", token))),
+		}
+	}
+
+	fn label(&mut self) -> Result<String> {
+		match self.next()? {
+			Token::Label(label) => Ok(label),
+			token => Err(self.error(format!("expected a label, found `{}`", token))),
+		}
+	}
+
+	fn register(&mut self) -> Result<Register> {
+		match self.next()? {
+			Token::Register(register) => Ok(register),
+			token => Err(self.error(format!("expected a register, found `{}`", token))),
+		}
+	}
+
+	fn type_descriptor(&mut self) -> Result<TypeDescriptor> {
+		let word = self.word()?;
+		word.parse().map_err(|e| self.error(e))
+	}
+
+	fn integer(&mut self, bits: u32, allow_unsigned: bool) -> Result<i64> {
+		let word = self.word()?;
+		let value = parse_integer(&word)
+			.ok_or_else(|| self.error(format!("expected an integer, found `{}`", word)))?;
+		check_range(value, bits, allow_unsigned).map_err(|e| self.error(e))
+	}
+
+	fn unsigned(&mut self) -> Result<u16> {
+		let word = self.word()?;
+		parse_integer(&word)
+			.and_then(|value| u16::try_from(value).ok())
+			.ok_or_else(|| {
+				self.error(format!(
+					"expected an unsigned 16 bit integer, found `{}`",
+					word
+				))
+			})
+	}
+
+	fn access_flags(&mut self, context: AccessContext) -> AccessFlags {
+		let mut flags = AccessFlags::empty();
+		while let Some(Token::Word(word)) = self.peek() {
+			match AccessFlags::from_name(word, context) {
+				Some(flag) => flags |= flag,
+				None => break,
+			}
+			self.pos += 1;
+		}
+		flags
+	}
+
+	fn skip_line(&mut self) {
+		let line = self.line();
+		while self.tokens.get(self.pos).is_some_and(|t| t.line == line) {
+			self.pos += 1;
+		}
+	}
+}
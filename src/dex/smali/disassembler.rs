@@ -0,0 +1,1210 @@
+use std::{
+	collections::{BTreeSet, HashMap},
+	fmt::Write,
+};
+
+use eyre::{bail, eyre, Result};
+
+use crate::dex::{
+	asm::{
+		instruction::Instruction,
+		opcode::{Opcode, ReferenceType},
+	},
+	resolver::{Resolve, ResolveInto},
+	types::{
+		access::{AccessContext, AccessFlags},
+		debug::{DebugInfo, Local},
+		descriptor::TypeDescriptor,
+		id::{
+			AnnotationItem,
+			CodeItem,
+			EncodedAnnotation,
+			EncodedValue,
+			FieldIdItem,
+			MethodHandleItem,
+			MethodIdItem,
+			ProtoIdItem,
+			StringIdItem,
+			TypeIdItem,
+			VISIBILITY_BUILD,
+			VISIBILITY_RUNTIME,
+			VISIBILITY_SYSTEM,
+		},
+		refs::Idx,
+		Annotations,
+		Class,
+		Field,
+		Method,
+		MethodId,
+		Proto,
+	},
+};
+
+const INDENT: &str = "    ";
+
+/// Renders resolved [`Class`]es as `.smali` source, in the same layout baksmali uses.
+pub struct Disassembler<'a, R: Resolve> {
+	resolver: &'a R,
+}
+
+impl<'a, R: Resolve> Disassembler<'a, R> {
+	pub fn new(resolver: &'a R) -> Self {
+		Disassembler { resolver }
+	}
+
+	/// Path of the `.smali` file for a class descriptor, relative to the output
+	/// directory (`Lfoo/Bar;` -> `foo/Bar.smali`).
+	pub fn file_name(descriptor: &str) -> String {
+		let name = descriptor
+			.strip_prefix('L')
+			.and_then(|d| d.strip_suffix(';'))
+			.unwrap_or(descriptor);
+		format!("{}.smali", name)
+	}
+
+	#[cfg_attr(feature = "trace", instrument(skip(self, class), fields(class = %class.name)))]
+	pub fn class(&self, class: &Class) -> Result<String> {
+		let mut out = String::new();
+		self.write_class(&mut out, class)?;
+		Ok(out)
+	}
+
+	pub fn write_class(&self, out: &mut impl Write, class: &Class) -> Result<()> {
+		writeln!(
+			out,
+			".class {}{}",
+			access_flags(class.access_flags, AccessContext::Class),
+			class.name
+		)?;
+		writeln!(out, ".super {}", class.superclass)?;
+		if let Some(source_file) = &class.source_file {
+			writeln!(out, ".source \"{}\"", escape_string(source_file))?;
+		}
+
+		if let Some(interfaces) = class.interfaces.as_ref().filter(|i| !i.is_empty()) {
+			write!(out, "\n# interfaces\n")?;
+			for interface in interfaces {
+				writeln!(out, ".implements {}", interface)?;
+			}
+		}
+
+		let annotations = class.annotations.as_ref();
+		if let Some(annotations) = annotations.filter(|a| !a.class.is_empty()) {
+			write!(out, "\n\n# annotations\n")?;
+			self.write_annotations(out, "", &annotations.class)?;
+		}
+
+		let class_data = match &class.class_data {
+			Some(class_data) => class_data,
+			None => return Ok(()),
+		};
+
+		self.write_fields(out, "static fields", &class_data.static_fields, annotations)?;
+		self.write_fields(
+			out,
+			"instance fields",
+			&class_data.instance_fields,
+			annotations,
+		)?;
+		self.write_methods(
+			out,
+			"direct methods",
+			&class_data.direct_methods,
+			annotations,
+		)?;
+		self.write_methods(
+			out,
+			"virtual methods",
+			&class_data.virtual_methods,
+			annotations,
+		)?;
+
+		Ok(())
+	}
+
+	fn write_fields(
+		&self,
+		out: &mut impl Write,
+		title: &str,
+		fields: &[Field],
+		annotations: Option<&Annotations>,
+	) -> Result<()> {
+		if fields.is_empty() {
+			return Ok(());
+		}
+
+		write!(out, "\n\n# {}\n", title)?;
+		for field in fields {
+			write!(
+				out,
+				".field {}{}:{}",
+				access_flags(field.access_flags, AccessContext::Field),
+				field.id.name,
+				field.id.typ
+			)?;
+			// like baksmali, values that match the field's default are left out
+			match &field.initial_value {
+				Some(value) if !is_default_value(value) => {
+					writeln!(out, " = {}", self.encoded_value(value)?)?
+				}
+				_ => writeln!(out)?,
+			}
+
+			let annotations = annotations.map_or(&[][..], |a| a.field(&field.id));
+			if !annotations.is_empty() {
+				self.write_annotations(out, INDENT, annotations)?;
+				writeln!(out, ".end field")?;
+			}
+		}
+
+		Ok(())
+	}
+
+	fn write_methods(
+		&self,
+		out: &mut impl Write,
+		title: &str,
+		methods: &[Method],
+		annotations: Option<&Annotations>,
+	) -> Result<()> {
+		if methods.is_empty() {
+			return Ok(());
+		}
+
+		write!(out, "\n\n# {}\n", title)?;
+		for (i, method) in methods.iter().enumerate() {
+			if i != 0 {
+				writeln!(out)?;
+			}
+			self.write_method(out, method, annotations)?;
+		}
+
+		Ok(())
+	}
+
+	#[cfg_attr(feature = "trace", instrument(skip(self, out, method), fields(method = %method.id.name)))]
+	pub fn write_method(
+		&self,
+		out: &mut impl Write,
+		method: &Method,
+		annotations: Option<&Annotations>,
+	) -> Result<()> {
+		writeln!(
+			out,
+			".method {}{}{}",
+			access_flags(method.access_flags, AccessContext::Method),
+			method.id.name,
+			proto_string(&method.id.proto)
+		)?;
+		if let Some(code) = &method.code {
+			writeln!(out, "{}.registers {}", INDENT, code.registers_size)?;
+		}
+		self.write_parameters(out, method, annotations)?;
+		if let Some(annotations) = annotations.map(|a| a.method(&method.id)) {
+			self.write_annotations(out, INDENT, annotations)?;
+		}
+		if let Some(code) = &method.code {
+			self.write_code(out, method, code)?;
+		}
+		writeln!(out, ".end method")?;
+
+		Ok(())
+	}
+
+	fn write_code(&self, out: &mut impl Write, method: &Method, code: &CodeItem) -> Result<()> {
+		let debug_info = method.debug_info.as_ref();
+		let method = MethodContext::new(code);
+		let debug = debug_info.map_or_else(Vec::new, |d| debug_items(&method, d));
+		let mut debug = debug.iter().peekable();
+
+		for (insn, &addr) in code.insns.iter().zip(method.addresses.iter()) {
+			writeln!(out)?;
+			while let Some((_, _, item)) = debug.next_if(|(at, ..)| *at <= addr) {
+				writeln!(out, "{}{}", INDENT, item)?;
+			}
+			for kind in LabelKind::ORDER.iter() {
+				if let Some(label) = method.labels.get(*kind, addr) {
+					writeln!(out, "{}:{}", INDENT, label)?;
+				}
+			}
+
+			self.write_instruction(out, &method, addr, insn)?;
+
+			let end = addr + insn.code_units();
+			for (i, try_item) in code.tries.iter().flatten().enumerate() {
+				if try_item.start_addr + try_item.insn_count as u32 != end {
+					continue;
+				}
+				let (start, end) = method.try_range(i);
+				writeln!(out, "{}:{}", INDENT, end)?;
+
+				let handler = code
+					.handlers
+					.as_ref()
+					.and_then(|h| h.handler(try_item.handler_off));
+				let handler = match handler {
+					Some(handler) => handler,
+					None => bail!("no catch handler at offset {}", try_item.handler_off),
+				};
+				for pair in handler.handlers.iter() {
+					writeln!(
+						out,
+						"{}.catch {} {{:{} .. :{}}} :{}",
+						INDENT,
+						self.type_descriptor(*pair.type_idx)?,
+						start,
+						end,
+						method.labels.expect(LabelKind::Catch, *pair.addr)
+					)?;
+				}
+				if let Some(addr) = handler.catch_all_addr {
+					writeln!(
+						out,
+						"{}.catchall {{:{} .. :{}}} :{}",
+						INDENT,
+						start,
+						end,
+						method.labels.expect(LabelKind::CatchAll, *addr)
+					)?;
+				}
+			}
+		}
+
+		// debug info can continue past the last instruction
+		if debug.peek().is_some() {
+			writeln!(out)?;
+			for (_, _, item) in debug {
+				writeln!(out, "{}{}", INDENT, item)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Writes `.param` for each parameter with a name or annotations, which
+	/// needs the parameter types to find their registers.
+	fn write_parameters(
+		&self,
+		out: &mut impl Write,
+		method: &Method,
+		annotations: Option<&Annotations>,
+	) -> Result<()> {
+		let parameters = match &method.id.proto.parameters {
+			Some(parameters) => parameters,
+			None => return Ok(()),
+		};
+		let names = method
+			.debug_info
+			.as_ref()
+			.map_or(&[][..], |d| &d.parameter_names);
+		let annotations = annotations.map_or(&[][..], |a| a.parameters(&method.id));
+
+		let mut register = if method.access_flags.contains(AccessFlags::STATIC) {
+			0
+		} else {
+			1
+		};
+		for (i, parameter) in parameters.iter().enumerate() {
+			let name = names.get(i).and_then(Option::as_ref);
+			let annotations = annotations.get(i).map_or(&[][..], Vec::as_slice);
+			if name.is_some() || !annotations.is_empty() {
+				write!(out, "{}.param p{}", INDENT, register)?;
+				if let Some(name) = name {
+					write!(out, ", \"{}\"", escape_string(name))?;
+				}
+				writeln!(out, "    # {}", parameter)?;
+			}
+			if !annotations.is_empty() {
+				self.write_annotations(out, &INDENT.repeat(2), annotations)?;
+				writeln!(out, "{}.end param", INDENT)?;
+			}
+			register += parameter.register_width();
+		}
+
+		Ok(())
+	}
+
+	/// Writes `.annotation` blocks, with each line starting with `indent`.
+	fn write_annotations(
+		&self,
+		out: &mut impl Write,
+		indent: &str,
+		annotations: &[AnnotationItem],
+	) -> Result<()> {
+		let inner = format!("{}{}", indent, INDENT);
+		for (i, item) in annotations.iter().enumerate() {
+			if i != 0 {
+				writeln!(out)?;
+			}
+			writeln!(
+				out,
+				"{}.annotation {} {}",
+				indent,
+				visibility(item.visibility)?,
+				self.type_descriptor(*item.annotation.type_idx)?
+			)?;
+			for element in &item.annotation.elements {
+				writeln!(
+					out,
+					"{}{} = {}",
+					inner,
+					self.resolver.string(*element.name_idx)?,
+					self.annotation_value(&inner, &element.value)?
+				)?;
+			}
+			writeln!(out, "{}.end annotation", indent)?;
+		}
+
+		Ok(())
+	}
+
+	/// Like [`Self::encoded_value`], but arrays and subannotations span
+	/// several lines like in baksmali. `indent` is the indentation of the
+	/// line the value starts on.
+	fn annotation_value(&self, indent: &str, value: &EncodedValue) -> Result<String> {
+		let inner = format!("{}{}", indent, INDENT);
+		Ok(match value {
+			EncodedValue::Array(array) if !array.values.is_empty() => {
+				let values = array
+					.values
+					.iter()
+					.map(|value| {
+						Ok(format!(
+							"{}{}",
+							inner,
+							self.annotation_value(&inner, value)?
+						))
+					})
+					.collect::<Result<Vec<_>>>()?;
+				format!("{{\n{}\n{}}}", values.join(",\n"), indent)
+			}
+			EncodedValue::Annotation(annotation) => {
+				let mut out = format!(
+					".subannotation {}\n",
+					self.type_descriptor(*annotation.type_idx)?
+				);
+				for element in &annotation.elements {
+					writeln!(
+						out,
+						"{}{} = {}",
+						inner,
+						self.resolver.string(*element.name_idx)?,
+						self.annotation_value(&inner, &element.value)?
+					)?;
+				}
+				write!(out, "{}.end subannotation", indent)?;
+				out
+			}
+			value => self.encoded_value(value)?,
+		})
+	}
+
+	fn write_instruction(
+		&self,
+		out: &mut impl Write,
+		method: &MethodContext,
+		addr: u32,
+		insn: &Instruction,
+	) -> Result<()> {
+		use Instruction::*;
+
+		let op = insn.opcode();
+		let name = op.name();
+		let reg = |r: u16| method.register(r);
+		let target =
+			|kind: LabelKind, offset: i32| method.labels.expect(kind, relative(addr, offset));
+		// only read by the formats that have a literal
+		let literal = insn.literal().unwrap_or_default();
+
+		write!(out, "{}", INDENT)?;
+		match insn {
+			Instruction10x(_) => write!(out, "{}", name)?,
+			Instruction12x(_, (a, b)) => {
+				write!(out, "{} {}, {}", name, reg(*a as u16), reg(*b as u16))?
+			}
+			Instruction11n(_, (a, _)) => {
+				write!(out, "{} {}, {}", name, reg(*a as u16), int_literal(literal))?
+			}
+			Instruction11x(_, (aa,)) => write!(out, "{} {}", name, reg(*aa as u16))?,
+			Instruction10t(_, (aa,)) => {
+				write!(out, "{} :{}", name, target(LabelKind::Goto, *aa as i32))?
+			}
+			Instruction20t(_, (aaaa,)) => {
+				write!(out, "{} :{}", name, target(LabelKind::Goto, *aaaa as i32))?
+			}
+			Instruction20bc(_, (aa, bbbb)) => {
+				write!(out, "{} {}, {:#x}", name, verification_error(*aa), bbbb)?
+			}
+			Instruction22x(_, (aa, bbbb)) => {
+				write!(out, "{} {}, {}", name, reg(*aa as u16), reg(*bbbb))?
+			}
+			Instruction21t(_, (aa, bbbb)) => write!(
+				out,
+				"{} {}, :{}",
+				name,
+				reg(*aa as u16),
+				target(LabelKind::Cond, *bbbb as i32)
+			)?,
+			Instruction21s(_, (aa, _)) | Instruction21ih(_, (aa, _)) => write!(
+				out,
+				"{} {}, {}",
+				name,
+				reg(*aa as u16),
+				int_literal(literal)
+			)?,
+			Instruction21lh(_, (aa, _)) => write!(
+				out,
+				"{} {}, {}",
+				name,
+				reg(*aa as u16),
+				long_literal(literal)
+			)?,
+			Instruction21c(op, (aa, bbbb)) => write!(
+				out,
+				"{} {}, {}",
+				name,
+				reg(*aa as u16),
+				self.reference(op.reference_type(), *bbbb as u32)?
+			)?,
+			Instruction23x(_, (aa, bb, cc)) => write!(
+				out,
+				"{} {}, {}, {}",
+				name,
+				reg(*aa as u16),
+				reg(*bb as u16),
+				reg(*cc as u16)
+			)?,
+			Instruction22b(_, (aa, bb, _)) => write!(
+				out,
+				"{} {}, {}, {}",
+				name,
+				reg(*aa as u16),
+				reg(*bb as u16),
+				int_literal(literal)
+			)?,
+			Instruction22t(_, (a, b, cccc)) => write!(
+				out,
+				"{} {}, {}, :{}",
+				name,
+				reg(*a as u16),
+				reg(*b as u16),
+				target(LabelKind::Cond, *cccc as i32)
+			)?,
+			Instruction22s(_, (a, b, _)) => write!(
+				out,
+				"{} {}, {}, {}",
+				name,
+				reg(*a as u16),
+				reg(*b as u16),
+				int_literal(literal)
+			)?,
+			Instruction22c(op, (a, b, cccc)) => write!(
+				out,
+				"{} {}, {}, {}",
+				name,
+				reg(*a as u16),
+				reg(*b as u16),
+				self.reference(op.reference_type(), *cccc as u32)?
+			)?,
+			Instruction22cs(_, (a, b, cccc)) => write!(
+				out,
+				"{} {}, {}, field@{:#x}",
+				name,
+				reg(*a as u16),
+				reg(*b as u16),
+				cccc
+			)?,
+			Instruction30t(_, (aaaa_aaaa,)) => {
+				write!(out, "{} :{}", name, target(LabelKind::Goto, *aaaa_aaaa))?
+			}
+			Instruction32x(_, (aaaa, bbbb)) => {
+				write!(out, "{} {}, {}", name, reg(*aaaa), reg(*bbbb))?
+			}
+			Instruction31i(_, (aa, _)) => write!(
+				out,
+				"{} {}, {}",
+				name,
+				reg(*aa as u16),
+				int_literal(literal)
+			)?,
+			Instruction31t(op, (aa, bbbb_bbbb)) => write!(
+				out,
+				"{} {}, :{}",
+				name,
+				reg(*aa as u16),
+				target(LabelKind::payload(*op), *bbbb_bbbb)
+			)?,
+			Instruction31c(op, (aa, bbbb_bbbb)) => write!(
+				out,
+				"{} {}, {}",
+				name,
+				reg(*aa as u16),
+				self.reference(op.reference_type(), *bbbb_bbbb)?
+			)?,
+			Instruction35c(op, (a, g, bbbb, f, e, d, c)) => write!(
+				out,
+				"{} {}, {}",
+				name,
+				method.register_list(*a, [*c, *d, *e, *f, *g]),
+				self.reference(op.reference_type(), *bbbb as u32)?
+			)?,
+			Instruction35ms(_, (a, g, bbbb, f, e, d, c)) => write!(
+				out,
+				"{} {}, vtable@{:#x}",
+				name,
+				method.register_list(*a, [*c, *d, *e, *f, *g]),
+				bbbb
+			)?,
+			Instruction35mi(_, (a, g, bbbb, f, e, d, c)) => write!(
+				out,
+				"{} {}, inline@{:#x}",
+				name,
+				method.register_list(*a, [*c, *d, *e, *f, *g]),
+				bbbb
+			)?,
+			Instruction3rc(op, (aa, bbbb, cccc)) => write!(
+				out,
+				"{} {}, {}",
+				name,
+				method.register_range(*aa, *cccc),
+				self.reference(op.reference_type(), *bbbb as u32)?
+			)?,
+			Instruction3rms(_, (aa, bbbb, cccc)) => write!(
+				out,
+				"{} {}, vtable@{:#x}",
+				name,
+				method.register_range(*aa, *cccc),
+				bbbb
+			)?,
+			Instruction3rmi(_, (aa, bbbb, cccc)) => write!(
+				out,
+				"{} {}, inline@{:#x}",
+				name,
+				method.register_range(*aa, *cccc),
+				bbbb
+			)?,
+			Instruction45cc(op, (a, g, bbbb, f, e, d, c, hhhh)) => write!(
+				out,
+				"{} {}, {}, {}",
+				name,
+				method.register_list(*a, [*c, *d, *e, *f, *g]),
+				self.reference(op.reference_type(), *bbbb as u32)?,
+				self.reference(op.reference_type_2(), *hhhh as u32)?
+			)?,
+			Instruction4rcc(op, (aa, bbbb, cccc, hhhh)) => write!(
+				out,
+				"{} {}, {}, {}",
+				name,
+				method.register_range(*aa, *cccc),
+				self.reference(op.reference_type(), *bbbb as u32)?,
+				self.reference(op.reference_type_2(), *hhhh as u32)?
+			)?,
+			Instruction51l(_, (aa, _)) => write!(
+				out,
+				"{} {}, {}",
+				name,
+				reg(*aa as u16),
+				long_literal(literal)
+			)?,
+			PackedSwitchPayload {
+				first_key, targets, ..
+			} => {
+				let base = method.payload_base(addr);
+				writeln!(out, ".packed-switch {}", int_literal(*first_key as i64))?;
+				for target in targets {
+					writeln!(
+						out,
+						"{}{}:{}",
+						INDENT,
+						INDENT,
+						method
+							.labels
+							.expect(LabelKind::PackedSwitch, relative(base, *target))
+					)?;
+				}
+				write!(out, "{}.end packed-switch", INDENT)?;
+			}
+			SparseSwitchPayload { keys, targets, .. } => {
+				let base = method.payload_base(addr);
+				writeln!(out, ".sparse-switch")?;
+				for (key, target) in keys.iter().zip(targets.iter()) {
+					writeln!(
+						out,
+						"{}{}{} -> :{}",
+						INDENT,
+						INDENT,
+						int_literal(*key as i64),
+						method
+							.labels
+							.expect(LabelKind::SparseSwitch, relative(base, *target))
+					)?;
+				}
+				write!(out, "{}.end sparse-switch", INDENT)?;
+			}
+			FillArrayDataPayload { element_width, .. } => {
+				writeln!(out, ".array-data {}", element_width)?;
+				for value in insn.array_elements()?.to_i64s() {
+					let literal = match element_width {
+						1 => format!("{}t", int_literal(value)),
+						2 => format!("{}s", int_literal(value)),
+						8 => format!("{}L", int_literal(value)),
+						_ => int_literal(value),
+					};
+					writeln!(out, "{}{}{}", INDENT, INDENT, literal)?;
+				}
+				write!(out, "{}.end array-data", INDENT)?;
+			}
+		}
+		writeln!(out)?;
+
+		Ok(())
+	}
+
+	/// Renders an encoded value on a single line, number literals get the
+	/// suffix of their type so they can be read back without knowing it.
+	fn encoded_value(&self, value: &EncodedValue) -> Result<String> {
+		Ok(match value {
+			EncodedValue::Byte(v) => format!("{}t", int_literal(*v as i8 as i64)),
+			EncodedValue::Short(v) => format!("{}s", int_literal(*v as i64)),
+			EncodedValue::Char(v) => char_literal(*v),
+			EncodedValue::Int(v) => int_literal(*v as i64),
+			EncodedValue::Long(v) => format!("{}L", int_literal(*v)),
+			EncodedValue::Float(v) => format!("{}f", float_literal(*v)),
+			EncodedValue::Double(v) => float_literal(*v),
+			EncodedValue::MethodType(idx) => self.reference(ReferenceType::MethodProto, *idx)?,
+			EncodedValue::MethodHandle(idx) => self.reference(ReferenceType::MethodHandle, *idx)?,
+			EncodedValue::String(idx) => self.reference(ReferenceType::String, *idx)?,
+			EncodedValue::Type(idx) => self.reference(ReferenceType::Type, *idx)?,
+			EncodedValue::Field(idx) => self.reference(ReferenceType::Field, *idx)?,
+			EncodedValue::Method(idx) => self.reference(ReferenceType::Method, *idx)?,
+			EncodedValue::Enum(idx) => {
+				format!(".enum {}", self.reference(ReferenceType::Field, *idx)?)
+			}
+			EncodedValue::Array(array) => {
+				let values = array
+					.values
+					.iter()
+					.map(|value| self.encoded_value(value))
+					.collect::<Result<Vec<_>>>()?;
+				format!("{{{}}}", values.join(", "))
+			}
+			EncodedValue::Annotation(annotation) => self.subannotation(annotation)?,
+			EncodedValue::Null => "null".to_string(),
+			EncodedValue::Boolean(v) => v.to_string(),
+		})
+	}
+
+	fn subannotation(&self, annotation: &EncodedAnnotation) -> Result<String> {
+		let mut out = format!(
+			".subannotation {}",
+			self.type_descriptor(*annotation.type_idx)?
+		);
+		for element in &annotation.elements {
+			write!(
+				out,
+				" {} = {}",
+				self.resolver.string(*element.name_idx)?,
+				self.encoded_value(&element.value)?
+			)?;
+		}
+		out.push_str(" .end subannotation");
+		Ok(out)
+	}
+
+	fn reference(&self, kind: ReferenceType, idx: u32) -> Result<String> {
+		let res = self.resolver;
+		Ok(match kind {
+			ReferenceType::String => {
+				let string = Idx::<StringIdItem, u32>::new(idx as usize).resolve(res)?;
+				format!("\"{}\"", escape_string(&string.string))
+			}
+			ReferenceType::Type => self.type_descriptor(idx)?.to_string(),
+			ReferenceType::Field => {
+				let field = Idx::<FieldIdItem, u32>::new(idx as usize).resolve(res)?;
+				format!(
+					"{}->{}:{}",
+					field.class_idx.resolve(res)?.descriptor(res)?,
+					res.string(*field.name_idx)?,
+					field.type_idx.resolve(res)?.descriptor(res)?
+				)
+			}
+			ReferenceType::Method => {
+				let method: MethodId = Idx::<MethodIdItem, u32>::new(idx as usize)
+					.resolve(res)?
+					.resolve_into(res)?;
+				method_reference(&method)
+			}
+			ReferenceType::MethodProto => {
+				let proto: Proto = Idx::<ProtoIdItem, u32>::new(idx as usize)
+					.resolve(res)?
+					.resolve_into(res)?;
+				proto_string(&proto)
+			}
+			ReferenceType::MethodHandle => {
+				let handle: MethodHandleItem = res
+					.dex_file()
+					.method_handles
+					.get(idx as usize)
+					.cloned()
+					.ok_or_else(|| eyre!("method handle {} out of bounds", idx))?;
+				let target = if handle.method_handle_type <= 0x03 {
+					self.reference(ReferenceType::Field, handle.field_or_method_id as u32)?
+				} else {
+					self.reference(ReferenceType::Method, handle.field_or_method_id as u32)?
+				};
+				format!(
+					"{}@{}",
+					method_handle_type(handle.method_handle_type),
+					target
+				)
+			}
+			ReferenceType::CallSite => format!("call_site_{}", idx),
+			ReferenceType::None | ReferenceType::_Undef => {
+				bail!("instruction does not reference an item")
+			}
+		})
+	}
+
+	fn type_descriptor(&self, idx: u32) -> Result<TypeDescriptor> {
+		Idx::<TypeIdItem, u32>::new(idx as usize)
+			.resolve(self.resolver)?
+			.descriptor(self.resolver)
+	}
+}
+
+fn proto_string(proto: &Proto) -> String {
+	format!(
+		"({}){}",
+		proto
+			.parameters
+			.iter()
+			.flatten()
+			.map(ToString::to_string)
+			.collect::<String>(),
+		proto.return_type
+	)
+}
+
+fn method_reference(method: &MethodId) -> String {
+	format!(
+		"{}->{}{}",
+		method.class,
+		method.name,
+		proto_string(&method.proto)
+	)
+}
+
+fn relative(addr: u32, offset: i32) -> u32 {
+	(addr as i64 + offset as i64) as u32
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum LabelKind {
+	Array,
+	Catch,
+	CatchAll,
+	Cond,
+	Goto,
+	PackedSwitch,
+	PackedSwitchData,
+	SparseSwitch,
+	SparseSwitchData,
+	TryStart,
+	TryEnd,
+}
+
+impl LabelKind {
+	/// Order in which labels sharing an address are written, alphabetical like
+	/// baksmali. `try_end` is written after the instruction it closes instead.
+	const ORDER: [LabelKind; 10] = [
+		LabelKind::Array,
+		LabelKind::Catch,
+		LabelKind::CatchAll,
+		LabelKind::Cond,
+		LabelKind::Goto,
+		LabelKind::PackedSwitch,
+		LabelKind::PackedSwitchData,
+		LabelKind::SparseSwitch,
+		LabelKind::SparseSwitchData,
+		LabelKind::TryStart,
+	];
+
+	fn prefix(&self) -> &'static str {
+		match self {
+			LabelKind::Array => "array",
+			LabelKind::Catch => "catch",
+			LabelKind::CatchAll => "catchall",
+			LabelKind::Cond => "cond",
+			LabelKind::Goto => "goto",
+			LabelKind::PackedSwitch => "pswitch",
+			LabelKind::PackedSwitchData => "pswitch_data",
+			LabelKind::SparseSwitch => "sswitch",
+			LabelKind::SparseSwitchData => "sswitch_data",
+			LabelKind::TryStart => "try_start",
+			LabelKind::TryEnd => "try_end",
+		}
+	}
+
+	fn payload(op: Opcode) -> LabelKind {
+		match op {
+			Opcode::PackedSwitch => LabelKind::PackedSwitchData,
+			Opcode::SparseSwitch => LabelKind::SparseSwitchData,
+			_ => LabelKind::Array,
+		}
+	}
+}
+
+#[derive(Default)]
+struct Labels {
+	addresses: HashMap<LabelKind, BTreeSet<u32>>,
+}
+
+impl Labels {
+	fn add(&mut self, kind: LabelKind, addr: u32) {
+		self.addresses.entry(kind).or_default().insert(addr);
+	}
+
+	/// Labels are numbered per kind in address order, like baksmali does.
+	fn get(&self, kind: LabelKind, addr: u32) -> Option<String> {
+		let addresses = self.addresses.get(&kind)?;
+		addresses
+			.contains(&addr)
+			.then(|| format!("{}_{}", kind.prefix(), addresses.range(..addr).count()))
+	}
+
+	fn expect(&self, kind: LabelKind, addr: u32) -> String {
+		self.get(kind, addr)
+			.unwrap_or_else(|| format!("{}_{:x}", kind.prefix(), addr))
+	}
+}
+
+struct MethodContext {
+	registers_size: u16,
+	ins_size:       u16,
+	addresses:      Vec<u32>,
+	labels:         Labels,
+	/// payload address -> address of the instruction referencing it
+	payloads:       HashMap<u32, u32>,
+	tries:          Vec<(u32, u32)>,
+}
+
+impl MethodContext {
+	fn new(code: &CodeItem) -> Self {
+		let addresses = code
+			.instructions_with_offsets()
+			.map(|(addr, _)| addr)
+			.collect::<Vec<_>>();
+
+		let mut labels = Labels::default();
+		let mut payloads = HashMap::new();
+
+		for (addr, insn) in code.instructions_with_offsets() {
+			let target = match insn.branch_target(addr) {
+				Some(target) => target,
+				None => continue,
+			};
+			match insn {
+				Instruction::Instruction10t(..)
+				| Instruction::Instruction20t(..)
+				| Instruction::Instruction30t(..) => labels.add(LabelKind::Goto, target),
+				Instruction::Instruction21t(..) | Instruction::Instruction22t(..) => {
+					labels.add(LabelKind::Cond, target)
+				}
+				Instruction::Instruction31t(op, _) => {
+					labels.add(LabelKind::payload(*op), target);
+					payloads.insert(target, addr);
+				}
+				_ => {}
+			}
+		}
+
+		for (insn, &addr) in code.insns.iter().zip(addresses.iter()) {
+			let base = payloads.get(&addr).copied().unwrap_or(addr);
+			match insn {
+				Instruction::PackedSwitchPayload { targets, .. } => {
+					for target in targets {
+						labels.add(LabelKind::PackedSwitch, relative(base, *target));
+					}
+				}
+				Instruction::SparseSwitchPayload { targets, .. } => {
+					for target in targets {
+						labels.add(LabelKind::SparseSwitch, relative(base, *target));
+					}
+				}
+				_ => {}
+			}
+		}
+
+		let mut tries = Vec::new();
+		for try_item in code.tries.iter().flatten() {
+			let start = try_item.start_addr;
+			let end = start + try_item.insn_count as u32;
+			labels.add(LabelKind::TryStart, start);
+			labels.add(LabelKind::TryEnd, end);
+			tries.push((start, end));
+
+			let handler = code
+				.handlers
+				.as_ref()
+				.and_then(|h| h.handler(try_item.handler_off));
+			if let Some(handler) = handler {
+				for pair in handler.handlers.iter() {
+					labels.add(LabelKind::Catch, *pair.addr);
+				}
+				if let Some(addr) = handler.catch_all_addr {
+					labels.add(LabelKind::CatchAll, *addr);
+				}
+			}
+		}
+
+		MethodContext {
+			registers_size: code.registers_size,
+			ins_size: code.ins_size,
+			addresses,
+			labels,
+			payloads,
+			tries,
+		}
+	}
+
+	fn payload_base(&self, addr: u32) -> u32 {
+		self.payloads.get(&addr).copied().unwrap_or(addr)
+	}
+
+	fn try_range(&self, idx: usize) -> (String, String) {
+		let (start, end) = self.tries[idx];
+		(
+			self.labels.expect(LabelKind::TryStart, start),
+			self.labels.expect(LabelKind::TryEnd, end),
+		)
+	}
+
+	/// Registers holding parameters are written as `pN`, the rest as `vN`.
+	fn register(&self, register: u16) -> String {
+		let first_parameter = self.registers_size.saturating_sub(self.ins_size);
+		if register >= first_parameter && register < self.registers_size {
+			format!("p{}", register - first_parameter)
+		} else {
+			format!("v{}", register)
+		}
+	}
+
+	fn register_list(&self, count: u8, registers: [u8; 5]) -> String {
+		let registers = registers
+			.iter()
+			.take(count as usize)
+			.map(|r| self.register(*r as u16))
+			.collect::<Vec<_>>();
+		format!("{{{}}}", registers.join(", "))
+	}
+
+	fn register_range(&self, count: u8, first: u16) -> String {
+		if count == 0 {
+			return "{}".to_string();
+		}
+		format!(
+			"{{{} .. {}}}",
+			self.register(first),
+			self.register(first + count as u16 - 1)
+		)
+	}
+}
+
+/// The directives of the debug info as `(address, order, text)`, sorted by
+/// address. Within an address they come in baksmali's order: `.prologue` and
+/// `.epilogue`, then `.source`, then `.line` and the local directives last.
+/// Those keep the order of the state machine, so a register that is ended
+/// and started again at the same address is ended first.
+fn debug_items(method: &MethodContext, debug_info: &DebugInfo) -> Vec<(u32, u8, String)> {
+	let local = |local: &Local| {
+		let name = match &local.name {
+			Some(name) => format!("\"{}\"", escape_string(name)),
+			None => "null".to_string(),
+		};
+		match &local.typ {
+			Some(typ) => format!("{}:{}", name, typ),
+			None => format!("{}:null", name),
+		}
+	};
+
+	// ending and restarting only name the register, the local is a comment
+	let comment = |l: &Local| match (&l.name, &l.typ) {
+		(None, None) => String::new(),
+		_ => format!("    # {}", local(l)),
+	};
+
+	let mut items = Vec::new();
+	for l in debug_info.locals.iter() {
+		let register = method.register(l.register);
+		if let Some(end) = l.end {
+			items.push((end, 3, format!(".end local {}{}", register, comment(l))));
+		}
+		match l.start {
+			Some(start) if l.restarted => items.push((
+				start,
+				3,
+				format!(".restart local {}{}", register, comment(l)),
+			)),
+			Some(start) => {
+				let mut text = format!(".local {}, {}", register, local(l));
+				if let Some(signature) = &l.signature {
+					let _ = write!(text, ", \"{}\"", escape_string(signature));
+				}
+				items.push((start, 3, text));
+			}
+			None => {}
+		}
+	}
+	for &address in debug_info.prologue_ends.iter() {
+		items.push((address, 0, ".prologue".to_string()));
+	}
+	for &address in debug_info.epilogue_begins.iter() {
+		items.push((address, 0, ".epilogue".to_string()));
+	}
+	for (address, file) in debug_info.source_files.iter() {
+		let text = match file {
+			Some(file) => format!(".source \"{}\"", escape_string(file)),
+			None => ".source".to_string(),
+		};
+		items.push((*address, 1, text));
+	}
+	for position in debug_info.positions.iter() {
+		items.push((position.address, 2, format!(".line {}", position.line)));
+	}
+
+	// stable, so items of the same kind keep the order of the state machine
+	items.sort_by_key(|(address, order, _)| (*address, *order));
+	items
+}
+
+/// Renders access flags with a trailing space, e.g. `public static `.
+///
+/// smali has no names for unknown bits, so those are left out with a warning.
+fn access_flags(flags: AccessFlags, context: AccessContext) -> String {
+	if flags.unknown_bits() != 0 {
+		warn!(
+			"unknown access flags {:#x} of a {:?} can't be written as smali",
+			flags.unknown_bits(),
+			context
+		);
+	}
+	flags
+		.names(context)
+		.map(|name| format!("{} ", name))
+		.collect()
+}
+
+fn visibility(visibility: u8) -> Result<&'static str> {
+	Ok(match visibility {
+		VISIBILITY_BUILD => "build",
+		VISIBILITY_RUNTIME => "runtime",
+		VISIBILITY_SYSTEM => "system",
+		visibility => bail!("unknown annotation visibility {:#x}", visibility),
+	})
+}
+
+fn method_handle_type(kind: u16) -> String {
+	match kind {
+		0x00 => "static-put".to_string(),
+		0x01 => "static-get".to_string(),
+		0x02 => "instance-put".to_string(),
+		0x03 => "instance-get".to_string(),
+		0x04 => "invoke-static".to_string(),
+		0x05 => "invoke-instance".to_string(),
+		0x06 => "invoke-constructor".to_string(),
+		0x07 => "invoke-direct".to_string(),
+		0x08 => "invoke-interface".to_string(),
+		kind => format!("method-handle-{:#x}", kind),
+	}
+}
+
+fn verification_error(kind: u8) -> String {
+	match kind {
+		1 => "generic-error".to_string(),
+		2 => "no-such-class".to_string(),
+		3 => "no-such-field".to_string(),
+		4 => "no-such-method".to_string(),
+		5 => "illegal-class-access".to_string(),
+		6 => "illegal-field-access".to_string(),
+		7 => "illegal-method-access".to_string(),
+		8 => "class-change-error".to_string(),
+		9 => "instantiation-error".to_string(),
+		kind => format!("{:#x}", kind),
+	}
+}
+
+fn int_literal(value: i64) -> String {
+	if value < 0 {
+		format!("-{:#x}", value.unsigned_abs())
+	} else {
+		format!("{:#x}", value)
+	}
+}
+
+/// Longs that don't fit into an int get an `L` suffix.
+fn long_literal(value: i64) -> String {
+	if value < i32::MIN as i64 || value > i32::MAX as i64 {
+		format!("{}L", int_literal(value))
+	} else {
+		int_literal(value)
+	}
+}
+
+/// Floats print in their shortest form that reads back to the same value,
+/// with the names smali uses for infinities.
+fn float_literal<T: Copy + std::fmt::Debug + Into<f64>>(value: T) -> String {
+	let wide: f64 = value.into();
+	if wide.is_infinite() {
+		if wide < 0.0 { "-Infinity" } else { "Infinity" }.to_string()
+	} else {
+		format!("{:?}", value)
+	}
+}
+
+fn char_literal(c: u16) -> String {
+	let mut out = String::from("'");
+	escape_unit(&mut out, c);
+	out.push('\'');
+	out
+}
+
+/// Zero, `false` and `null` are what a field starts out as without an
+/// initial value.
+fn is_default_value(value: &EncodedValue) -> bool {
+	match value {
+		EncodedValue::Byte(v) => *v == 0,
+		EncodedValue::Short(v) => *v == 0,
+		EncodedValue::Char(v) => *v == 0,
+		EncodedValue::Int(v) => *v == 0,
+		EncodedValue::Long(v) => *v == 0,
+		EncodedValue::Float(v) => v.to_bits() == 0,
+		EncodedValue::Double(v) => v.to_bits() == 0,
+		EncodedValue::Boolean(v) => !*v,
+		EncodedValue::Null => true,
+		_ => false,
+	}
+}
+
+/// Escapes a string the way smali expects it inside `"..."`.
+pub fn escape_string(string: &str) -> String {
+	let mut out = String::with_capacity(string.len());
+	for c in string.encode_utf16() {
+		escape_unit(&mut out, c);
+	}
+	out
+}
+
+fn escape_unit(out: &mut String, c: u16) {
+	match c {
+		0x22 => out.push_str("\\\""),
+		0x27 => out.push_str("\\'"),
+		0x5c => out.push_str("\\\\"),
+		0x20..=0x7e => out.push(c as u8 as char),
+		0x0a => out.push_str("\\n"),
+		0x0d => out.push_str("\\r"),
+		0x09 => out.push_str("\\t"),
+		c => {
+			let _ = write!(out, "\\u{:04x}", c);
+		}
+	}
+}
@@ -0,0 +1,177 @@
+use std::{
+	fmt::{Display, Formatter},
+	iter::Peekable,
+	str::Chars,
+};
+
+use eyre::{bail, eyre, Result};
+
+/// A `v` (local) or `p` (parameter) register as written in the source, before
+/// it is mapped onto the method's register frame.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Register {
+	Local(u16),
+	Parameter(u16),
+}
+
+impl Display for Register {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Register::Local(r) => write!(f, "v{}", r),
+			Register::Parameter(r) => write!(f, "p{}", r),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Token {
+	/// `.class`, `.end`, ... without the leading dot.
+	Directive(String),
+	/// `:label` without the leading colon.
+	Label(String),
+	Register(Register),
+	/// Unescaped contents of a `"..."` literal.
+	String(String),
+	/// A `'c'` literal as a UTF-16 code unit.
+	Char(u16),
+	/// Anything else that is separated by whitespace: access flags, opcode
+	/// names, number literals, type descriptors and member references.
+	Word(String),
+	OpenBrace,
+	CloseBrace,
+	Comma,
+}
+
+impl Display for Token {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Token::Directive(d) => write!(f, ".{}", d),
+			Token::Label(l) => write!(f, ":{}", l),
+			Token::Register(r) => write!(f, "{}", r),
+			Token::String(s) => write!(f, "{:?}", s),
+			Token::Char(c) => write!(f, "'\\u{:04x}'", c),
+			Token::Word(w) => write!(f, "{}", w),
+			Token::OpenBrace => write!(f, "{{"),
+			Token::CloseBrace => write!(f, "}}"),
+			Token::Comma => write!(f, ","),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Spanned {
+	pub token: Token,
+	pub line:  usize,
+}
+
+/// Splits smali source into tokens, dropping whitespace and `#` comments.
+pub fn tokenize(source: &str) -> Result<Vec<Spanned>> {
+	let mut chars = source.chars().peekable();
+	let mut tokens = Vec::new();
+	let mut line = 1;
+
+	while let Some(&c) = chars.peek() {
+		let token = match c {
+			'\n' => {
+				line += 1;
+				chars.next();
+				continue;
+			}
+			c if c.is_whitespace() => {
+				chars.next();
+				continue;
+			}
+			'#' => {
+				while chars.next_if(|&c| c != '\n').is_some() {}
+				continue;
+			}
+			'{' => {
+				chars.next();
+				Token::OpenBrace
+			}
+			'}' => {
+				chars.next();
+				Token::CloseBrace
+			}
+			',' => {
+				chars.next();
+				Token::Comma
+			}
+			'"' => {
+				chars.next();
+				let mut units = Vec::new();
+				loop {
+					match chars.next() {
+						Some('"') => break,
+						Some('\\') => units.push(escape(&mut chars, line)?),
+						Some('\n') | None => bail!("line {}: unterminated string literal", line),
+						Some(c) => units.extend(c.encode_utf16(&mut [0; 2]).iter()),
+					}
+				}
+				let string = String::from_utf16(&units)
+					.map_err(|_| eyre!("line {}: string literal is not valid UTF-16", line))?;
+				Token::String(string)
+			}
+			'\'' => {
+				chars.next();
+				let unit = match chars.next() {
+					Some('\\') => escape(&mut chars, line)?,
+					Some(c) if c.len_utf16() == 1 && c != '\'' && c != '\n' => c as u16,
+					_ => bail!("line {}: invalid char literal", line),
+				};
+				if chars.next() != Some('\'') {
+					bail!("line {}: unterminated char literal", line);
+				}
+				Token::Char(unit)
+			}
+			_ => {
+				let mut word = String::new();
+				while let Some(c) = chars
+					.next_if(|&c| !c.is_whitespace() && !matches!(c, '{' | '}' | ',' | '"' | '#'))
+				{
+					word.push(c);
+				}
+				classify(word)
+			}
+		};
+		tokens.push(Spanned { token, line });
+	}
+
+	Ok(tokens)
+}
+
+fn classify(word: String) -> Token {
+	let mut chars = word.chars();
+	match (chars.next(), chars.next()) {
+		(Some('.'), Some(c)) if c.is_ascii_alphabetic() => Token::Directive(word[1..].to_string()),
+		(Some(':'), Some(_)) => Token::Label(word[1..].to_string()),
+		(Some(kind @ ('v' | 'p')), Some(c)) if c.is_ascii_digit() => match word[1..].parse() {
+			Ok(r) if kind == 'v' => Token::Register(Register::Local(r)),
+			Ok(r) => Token::Register(Register::Parameter(r)),
+			Err(_) => Token::Word(word),
+		},
+		_ => Token::Word(word),
+	}
+}
+
+/// Reads the rest of an escape sequence after the backslash.
+fn escape(chars: &mut Peekable<Chars>, line: usize) -> Result<u16> {
+	Ok(match chars.next() {
+		Some('n') => b'\n' as u16,
+		Some('r') => b'\r' as u16,
+		Some('t') => b'\t' as u16,
+		Some('b') => 0x08,
+		Some('f') => 0x0c,
+		Some('0') => 0,
+		Some(c @ ('"' | '\'' | '\\')) => c as u16,
+		Some('u') => {
+			let hex: String = (0..4).filter_map(|_| chars.next()).collect();
+			match u16::from_str_radix(&hex, 16) {
+				Ok(unit) if hex.len() == 4 => unit,
+				_ => bail!("line {}: invalid unicode escape \\u{}", line, hex),
+			}
+		}
+		Some(c) => bail!("line {}: invalid escape sequence \\{}", line, c),
+		None => bail!("line {}: unterminated escape sequence", line),
+	})
+}
@@ -1,19 +1,32 @@
-use eyre::Result;
+use eyre::{ensure, eyre, Result};
 
 use crate::dex::{
 	resolver::{Resolve, ResolveFrom, ResolveInto},
-	types::id::{
-		ClassDataItem,
-		ClassDefItem,
-		CodeItem,
-		EncodedField,
-		EncodedMethod,
-		FieldIdItem,
-		MethodIdItem,
-		ProtoIdItem,
+	types::{
+		access::{AccessFlags, HiddenapiFlags},
+		debug::DebugInfo,
+		descriptor::TypeDescriptor,
+		id::{
+			AnnotationItem,
+			AnnotationSetItem,
+			AnnotationsDirectoryItem,
+			ClassDataItem,
+			ClassDefItem,
+			CodeItem,
+			EncodedField,
+			EncodedMethod,
+			EncodedValue,
+			FieldIdItem,
+			MethodIdItem,
+			ProtoIdItem,
+		},
+		refs::{Idx, Ref},
 	},
 };
 
+pub mod access;
+pub mod debug;
+pub mod descriptor;
 pub mod file;
 pub mod header;
 
@@ -25,7 +38,7 @@ pub mod refs;
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct MethodId {
 	pub name:  String,
-	pub class: String,
+	pub class: TypeDescriptor,
 	pub proto: Proto,
 }
 
@@ -41,88 +54,204 @@ impl ResolveFrom<MethodIdItem> for MethodId {
 
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct Method {
-	pub id:           MethodId,
-	pub access_flags: u32,
-	pub code:         Option<CodeItem>,
-}
-
-impl ResolveFrom<EncodedMethod> for Method {
-	fn resolve_from(item: &EncodedMethod, resolver: &impl Resolve) -> Result<Self> {
-		Ok(Method {
-			id:           item
-				.method_idx_diff
-				.resolve(resolver)?
-				.resolve_into(resolver)?,
-			access_flags: *item.access_flags,
-			code:         None,
-		})
-	}
+	pub id:              MethodId,
+	pub access_flags:    AccessFlags,
+	pub code:            Option<CodeItem>,
+	pub debug_info:      Option<DebugInfo>,
+	/// `None` if the file has no hidden API flags.
+	pub hiddenapi_flags: Option<HiddenapiFlags>,
 }
 
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct FieldId {
-	pub class: usize,
-	pub typ:   String,
+	pub class: TypeDescriptor,
+	pub typ:   TypeDescriptor,
 	pub name:  String,
 }
 
 impl ResolveFrom<FieldIdItem> for FieldId {
 	fn resolve_from(item: &FieldIdItem, resolver: &impl Resolve) -> Result<Self> {
 		Ok(FieldId {
-			class: *item.class_idx,
+			class: item.class_idx.resolve(resolver)?.descriptor(resolver)?,
 			typ:   item.type_idx.resolve(resolver)?.descriptor(resolver)?,
-			name:  resolver.string(*item.name_idx),
+			name:  resolver.string(*item.name_idx)?,
 		})
 	}
 }
 
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct Field {
-	pub id:           FieldId,
-	pub access_flags: u32,
-}
-
-impl ResolveFrom<EncodedField> for Field {
-	fn resolve_from(item: &EncodedField, resolver: &impl Resolve) -> Result<Self> {
-		Ok(Field {
-			id:           FieldId::resolve_from(
-				&resolver.dex_file().field_ids[*item.field_idx_diff as usize],
-				resolver,
-			)?,
-			access_flags: *item.access_flags,
-		})
-	}
+	pub id:              FieldId,
+	pub access_flags:    AccessFlags,
+	/// Initial value of a static field, `None` if it starts out as `0`,
+	/// `false` or `null`.
+	pub initial_value:   Option<EncodedValue>,
+	/// `None` if the file has no hidden API flags.
+	pub hiddenapi_flags: Option<HiddenapiFlags>,
 }
 
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct Class {
-	pub name:         String,
-	pub access_flags: u32,
-	pub superclass:   String,
-	pub interfaces:   Option<Vec<String>>,
+	pub name:         TypeDescriptor,
+	pub access_flags: AccessFlags,
+	pub superclass:   TypeDescriptor,
+	pub interfaces:   Option<Vec<TypeDescriptor>>,
 	pub source_file:  Option<String>,
+	pub annotations:  Option<Annotations>,
 	pub class_data:   Option<ClassData>,
 }
 
+/// Hidden API flags are stored by the index of the class def, which a class
+/// def doesn't know, so only [`Class::resolve`] sets them.
 impl ResolveFrom<ClassDefItem> for Class {
 	fn resolve_from(item: &ClassDefItem, resolver: &impl Resolve) -> Result<Self> {
+		let mut class_data: Option<ClassData> = item
+			.class_data_off
+			.resolve(resolver)?
+			.map(|class_data| class_data.resolve_into(resolver))
+			.transpose()?;
+
+		if let Some(static_values) = item.static_values_off.resolve(resolver)? {
+			let static_fields = class_data
+				.as_mut()
+				.map(|class_data| &mut class_data.static_fields)
+				.ok_or_else(|| eyre!("class has static values but no class data"))?;
+			let values = static_values.value.values;
+			ensure!(
+				values.len() <= static_fields.len(),
+				"class has {} static values but only {} static fields",
+				values.len(),
+				static_fields.len()
+			);
+			for (field, value) in static_fields.iter_mut().zip(values) {
+				field.initial_value = Some(value);
+			}
+		}
+
 		Ok(Class {
-			name:         item.class_type(resolver)?.descriptor(resolver)?,
+			name: item.class_type(resolver)?.descriptor(resolver)?,
 			access_flags: item.access_flags(resolver),
-			superclass:   item.superclass_type(resolver)?.descriptor(resolver)?,
-			interfaces:   None,
-			// interfaces:   item.interfaces(resolver).map(|l| {
-			// 	l.list
-			// 		.iter()
-			// 		.map(|i| resolver.dex_file.type_ids[i.type_idx as usize].descriptor(resolver))
-			// 		.collect()
-			// }),
-			source_file:  item.source_file(resolver)?,
-			class_data:   None,
-			// class_data:   item
-			// 	.class_data
-			// 	.as_ref()
-			// 	.map(|d| ClassData::resolve(d, resolver)),
+			superclass: item.superclass_type(resolver)?.descriptor(resolver)?,
+			interfaces: item.interfaces(resolver)?,
+			source_file: item.source_file(resolver)?,
+			annotations: item
+				.annotations_off
+				.resolve(resolver)?
+				.map(|annotations| annotations.resolve_into(resolver))
+				.transpose()?,
+			class_data,
+		})
+	}
+}
+
+impl Class {
+	/// Resolves the class def at `class_def_idx`, with the hidden API flags
+	/// of its members.
+	pub fn resolve(class_def_idx: usize, resolver: &impl Resolve) -> Result<Self> {
+		let item = resolver
+			.dex_file()
+			.class_defs
+			.get(class_def_idx)
+			.ok_or_else(|| eyre!("class def index {} out of bounds", class_def_idx))?;
+		let mut class = Class::resolve_from(item, resolver)?;
+		if let (Some(class_data), Some(hiddenapi)) = (
+			class.class_data.as_mut(),
+			&resolver.dex_file().hiddenapi_class_data,
+		) {
+			class_data.set_hiddenapi_flags(hiddenapi.class_flags(class_def_idx))?;
+		}
+		Ok(class)
+	}
+}
+
+/// Annotations of a class and its members, the annotations themselves are
+/// kept as parsed so their values still index into the file.
+#[derive(Debug, Clone, PartialOrd, PartialEq)]
+pub struct Annotations {
+	pub class:      Vec<AnnotationItem>,
+	pub fields:     Vec<(FieldId, Vec<AnnotationItem>)>,
+	pub methods:    Vec<(MethodId, Vec<AnnotationItem>)>,
+	/// One annotation set per parameter of the method.
+	pub parameters: Vec<(MethodId, Vec<Vec<AnnotationItem>>)>,
+}
+
+impl Annotations {
+	/// The annotations of a field, empty if it has none.
+	pub fn field(&self, field: &FieldId) -> &[AnnotationItem] {
+		self.fields
+			.iter()
+			.find(|(id, _)| id == field)
+			.map_or(&[], |(_, annotations)| annotations)
+	}
+
+	/// The annotations of a method, empty if it has none.
+	pub fn method(&self, method: &MethodId) -> &[AnnotationItem] {
+		self.methods
+			.iter()
+			.find(|(id, _)| id == method)
+			.map_or(&[], |(_, annotations)| annotations)
+	}
+
+	/// The annotations of each parameter of a method, empty if it has none.
+	pub fn parameters(&self, method: &MethodId) -> &[Vec<AnnotationItem>] {
+		self.parameters
+			.iter()
+			.find(|(id, _)| id == method)
+			.map_or(&[], |(_, parameters)| parameters)
+	}
+}
+
+impl ResolveFrom<AnnotationsDirectoryItem> for Annotations {
+	fn resolve_from(item: &AnnotationsDirectoryItem, resolver: &impl Resolve) -> Result<Self> {
+		// offsets of annotation sets are 0 where there are no annotations
+		let set = |offset: u32| -> Result<Vec<AnnotationItem>> {
+			if offset == 0 {
+				return Ok(Vec::new());
+			}
+			Ref::<AnnotationSetItem, u32>::new(offset)
+				.resolve(resolver)?
+				.entries
+				.iter()
+				.map(|entry| entry.annotations_off.resolve(resolver))
+				.collect()
+		};
+
+		Ok(Annotations {
+			class:      set(*item.class_annotations_off)?,
+			fields:     item
+				.field_annotations
+				.iter()
+				.flatten()
+				.map(|a| {
+					let field = a.field_idx.resolve(resolver)?.resolve_into(resolver)?;
+					Ok((field, set(*a.annotations_off)?))
+				})
+				.collect::<Result<_>>()?,
+			methods:    item
+				.method_annotations
+				.iter()
+				.flatten()
+				.map(|a| {
+					let method = a.method_idx.resolve(resolver)?.resolve_into(resolver)?;
+					Ok((method, set(*a.annotations_off)?))
+				})
+				.collect::<Result<_>>()?,
+			parameters: item
+				.parameter_annotations
+				.iter()
+				.flatten()
+				.map(|a| {
+					let method = a.method_idx.resolve(resolver)?.resolve_into(resolver)?;
+					let sets = a
+						.annotations_off
+						.resolve(resolver)?
+						.list
+						.iter()
+						.map(|entry| set(*entry.annotations_off))
+						.collect::<Result<_>>()?;
+					Ok((method, sets))
+				})
+				.collect::<Result<_>>()?,
 		})
 	}
 }
@@ -135,52 +264,135 @@ pub struct ClassData {
 	pub virtual_methods: Vec<Method>,
 }
 
+impl ClassData {
+	/// Sets the hidden API flags of the members, in the order they are listed
+	/// in. Without any flags all of them are `0`.
+	fn set_hiddenapi_flags(&mut self, flags: &[HiddenapiFlags]) -> Result<()> {
+		let members = self.static_fields.len()
+			+ self.instance_fields.len()
+			+ self.direct_methods.len()
+			+ self.virtual_methods.len();
+		ensure!(
+			flags.is_empty() || flags.len() == members,
+			"class has {} members but {} hidden API flags",
+			members,
+			flags.len()
+		);
+
+		let mut flags = flags.iter().copied();
+		let mut next = || Some(flags.next().unwrap_or_default());
+		for field in self.static_fields.iter_mut() {
+			field.hiddenapi_flags = next();
+		}
+		for field in self.instance_fields.iter_mut() {
+			field.hiddenapi_flags = next();
+		}
+		for method in self.direct_methods.iter_mut() {
+			method.hiddenapi_flags = next();
+		}
+		for method in self.virtual_methods.iter_mut() {
+			method.hiddenapi_flags = next();
+		}
+		Ok(())
+	}
+}
+
 impl ResolveFrom<ClassDataItem> for ClassData {
 	fn resolve_from(item: &ClassDataItem, resolver: &impl Resolve) -> Result<Self> {
 		Ok(ClassData {
-			static_fields:   item
-				.static_fields
-				.iter()
-				.map(|f| Field::resolve_from(f, resolver))
-				.collect::<Result<Vec<_>>>()?,
-			instance_fields: item
-				.instance_fields
-				.iter()
-				.map(|f| Field::resolve_from(f, resolver))
-				.collect::<Result<Vec<_>>>()?,
-			direct_methods:  item
-				.direct_methods
-				.iter()
-				.map(|m| Method::resolve_from(m, resolver))
-				.collect::<Result<Vec<_>>>()?,
-			virtual_methods: item
-				.virtual_methods
-				.iter()
-				.map(|m| Method::resolve_from(m, resolver))
-				.collect::<Result<Vec<_>>>()?,
+			static_fields:   resolve_fields(&item.static_fields, resolver)?,
+			instance_fields: resolve_fields(&item.instance_fields, resolver)?,
+			direct_methods:  resolve_methods(&item.direct_methods, resolver)?,
+			virtual_methods: resolve_methods(&item.virtual_methods, resolver)?,
 		})
 	}
 }
 
+/// Each field in a class data list stores the difference of its index to the
+/// one before it, so the indices are summed up while resolving.
+fn resolve_fields(items: &[EncodedField], resolver: &impl Resolve) -> Result<Vec<Field>> {
+	let mut idx = 0;
+	items
+		.iter()
+		.map(|item| {
+			idx += *item.field_idx_diff as usize;
+			Ok(Field {
+				id:              Idx::<FieldIdItem, u32>::new(idx)
+					.resolve(resolver)?
+					.resolve_into(resolver)?,
+				access_flags:    AccessFlags::from_bits_retain(*item.access_flags),
+				initial_value:   None,
+				hiddenapi_flags: None,
+			})
+		})
+		.collect()
+}
+
+/// Same as [`resolve_fields`], with the method indices being deltas as well.
+fn resolve_methods(items: &[EncodedMethod], resolver: &impl Resolve) -> Result<Vec<Method>> {
+	let mut idx = 0;
+	items
+		.iter()
+		.map(|item| {
+			idx += *item.method_idx_diff as usize;
+			let code = item.code_off.resolve(resolver)?;
+			let debug_info = match &code {
+				Some(code) => code
+					.debug_info_off
+					.resolve(resolver)?
+					.map(|debug_info| debug_info.resolve_into(resolver))
+					.transpose()?,
+				None => None,
+			};
+			Ok(Method {
+				id: Idx::<MethodIdItem, u32>::new(idx)
+					.resolve(resolver)?
+					.resolve_into(resolver)?,
+				access_flags: AccessFlags::from_bits_retain(*item.access_flags),
+				code,
+				debug_info,
+				hiddenapi_flags: None,
+			})
+		})
+		.collect()
+}
+
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct Proto {
 	pub shorty_descriptor: String,
-	pub return_type:       String,
-	pub parameters:        Option<Vec<String>>,
+	pub return_type:       TypeDescriptor,
+	pub parameters:        Option<Vec<TypeDescriptor>>,
+}
+
+impl Proto {
+	/// The shorty descriptor of a return and parameter types, which has one
+	/// character per type with all reference types shortened to `L`.
+	pub fn shorty_of(return_type: &TypeDescriptor, parameters: &[TypeDescriptor]) -> String {
+		std::iter::once(return_type)
+			.chain(parameters.iter())
+			.map(TypeDescriptor::shorty)
+			.collect()
+	}
 }
 
 impl ResolveFrom<ProtoIdItem> for Proto {
 	fn resolve_from(item: &ProtoIdItem, resolver: &impl Resolve) -> Result<Self> {
+		let shorty_descriptor = item.shorty(resolver)?;
+		let return_type = item.return_type(resolver).descriptor(resolver)?;
+		let parameters = item.parameters(resolver)?;
+
+		let expected = Proto::shorty_of(&return_type, parameters.as_deref().unwrap_or_default());
+		ensure!(
+			shorty_descriptor == expected,
+			"shorty descriptor `{}` doesn't match the proto's types, expected `{}`",
+			shorty_descriptor,
+			expected
+		);
+
 		Ok(Proto {
-			shorty_descriptor: item.shorty(resolver),
-			return_type:       item.return_type(resolver).descriptor(resolver)?,
-			parameters:        None,
-			// parameters:        item.parameters(resolver).map(|l| {
-			// 	l.list
-			// 		.iter()
-			// 		.map(|i| resolver.dex_file.type_ids[i.type_idx as usize].descriptor(resolver))
-			// 		.collect()
-			// }),
+			shorty_descriptor,
+			return_type,
+			parameters,
 		})
 	}
 }
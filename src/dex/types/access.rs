@@ -0,0 +1,235 @@
+use bitflags::bitflags;
+
+bitflags! {
+	/// https://source.android.com/devices/tech/dalvik/dex-format#access-flags
+	///
+	/// Some bits mean different things depending on whether they are set on a
+	/// class, a field or a method, those have one constant per meaning. Bits
+	/// without a constant are allowed and kept, see
+	/// [`AccessFlags::from_bits_retain`].
+	#[derive(Default)]
+	pub struct AccessFlags: u32 {
+		const PUBLIC = 0x1;
+		const PRIVATE = 0x2;
+		const PROTECTED = 0x4;
+		const STATIC = 0x8;
+		const FINAL = 0x10;
+		/// Methods only, only used for native methods.
+		const SYNCHRONIZED = 0x20;
+		/// Classes only, not used by the runtime.
+		const SUPER = 0x20;
+		/// Fields only.
+		const VOLATILE = 0x40;
+		/// Methods only, added by the compiler.
+		const BRIDGE = 0x40;
+		/// Fields only.
+		const TRANSIENT = 0x80;
+		/// Methods only.
+		const VARARGS = 0x80;
+		const NATIVE = 0x100;
+		const INTERFACE = 0x200;
+		const ABSTRACT = 0x400;
+		const STRICT = 0x800;
+		const SYNTHETIC = 0x1000;
+		const ANNOTATION = 0x2000;
+		const ENUM = 0x4000;
+		const CONSTRUCTOR = 0x10000;
+		/// The `synchronized` keyword on a method that isn't native.
+		const DECLARED_SYNCHRONIZED = 0x20000;
+	}
+}
+
+/// What access flags are set on, which decides what the bits mean.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AccessContext {
+	Class,
+	Field,
+	Method,
+}
+
+/// `(flag, name, valid for classes, fields, methods)`, in the order smali
+/// writes them.
+const SMALI_NAMES: [(AccessFlags, &str, [bool; 3]); 19] = [
+	(AccessFlags::PUBLIC, "public", [true, true, true]),
+	(AccessFlags::PRIVATE, "private", [true, true, true]),
+	(AccessFlags::PROTECTED, "protected", [true, true, true]),
+	(AccessFlags::STATIC, "static", [true, true, true]),
+	(AccessFlags::FINAL, "final", [true, true, true]),
+	(
+		AccessFlags::SYNCHRONIZED,
+		"synchronized",
+		[false, false, true],
+	),
+	(AccessFlags::VOLATILE, "volatile", [false, true, false]),
+	(AccessFlags::BRIDGE, "bridge", [false, false, true]),
+	(AccessFlags::TRANSIENT, "transient", [false, true, false]),
+	(AccessFlags::VARARGS, "varargs", [false, false, true]),
+	(AccessFlags::NATIVE, "native", [false, false, true]),
+	(AccessFlags::INTERFACE, "interface", [true, false, false]),
+	(AccessFlags::ABSTRACT, "abstract", [true, false, true]),
+	(AccessFlags::STRICT, "strictfp", [true, false, true]),
+	(AccessFlags::SYNTHETIC, "synthetic", [true, true, true]),
+	(AccessFlags::ANNOTATION, "annotation", [true, false, false]),
+	(AccessFlags::ENUM, "enum", [true, true, false]),
+	(
+		AccessFlags::CONSTRUCTOR,
+		"constructor",
+		[false, false, true],
+	),
+	(
+		AccessFlags::DECLARED_SYNCHRONIZED,
+		"declared-synchronized",
+		[false, false, true],
+	),
+];
+
+/// `(flag, keyword, valid for classes, fields, methods)`, in the order the
+/// JLS recommends for modifiers.
+const JAVA_MODIFIERS: [(AccessFlags, &str, [bool; 3]); 12] = [
+	(AccessFlags::PUBLIC, "public", [true, true, true]),
+	(AccessFlags::PROTECTED, "protected", [true, true, true]),
+	(AccessFlags::PRIVATE, "private", [true, true, true]),
+	(AccessFlags::ABSTRACT, "abstract", [true, false, true]),
+	(AccessFlags::STATIC, "static", [true, true, true]),
+	(AccessFlags::FINAL, "final", [true, true, true]),
+	(AccessFlags::TRANSIENT, "transient", [false, true, false]),
+	(AccessFlags::VOLATILE, "volatile", [false, true, false]),
+	(
+		AccessFlags::SYNCHRONIZED,
+		"synchronized",
+		[false, false, true],
+	),
+	(
+		AccessFlags::DECLARED_SYNCHRONIZED,
+		"synchronized",
+		[false, false, true],
+	),
+	(AccessFlags::NATIVE, "native", [false, false, true]),
+	(AccessFlags::STRICT, "strictfp", [true, false, true]),
+];
+
+impl AccessFlags {
+	/// Keeps all bits, including the ones no flag is defined for, so files
+	/// that set them, like obfuscated ones do, are written back unchanged.
+	pub fn from_bits_retain(bits: u32) -> Self {
+		// SAFETY: the type allows unknown bits, nothing relies on them being
+		// unset
+		unsafe { Self::from_bits_unchecked(bits) }
+	}
+
+	/// Bits no flag is defined for.
+	pub fn unknown_bits(self) -> u32 {
+		self.bits() & !Self::all().bits()
+	}
+
+	/// Looks up a flag by its smali name, e.g. `declared-synchronized`.
+	pub fn from_name(name: &str, context: AccessContext) -> Option<Self> {
+		SMALI_NAMES
+			.iter()
+			.find(|(_, n, valid)| *n == name && valid[context as usize])
+			.map(|(flag, ..)| *flag)
+	}
+
+	/// Names of the set flags as smali writes them.
+	pub fn names(self, context: AccessContext) -> impl Iterator<Item = &'static str> {
+		SMALI_NAMES
+			.iter()
+			.filter(move |(flag, _, valid)| self.intersects(*flag) && valid[context as usize])
+			.map(|(_, name, _)| *name)
+	}
+
+	/// Renders the flags as smali does, e.g. `public static final`.
+	pub fn smali(self, context: AccessContext) -> String {
+		self.names(context).collect::<Vec<_>>().join(" ")
+	}
+
+	/// Renders the flags as Java modifiers, leaving out the ones that are
+	/// implied by the declaration, like `abstract` on interfaces, or that have
+	/// no keyword at all, like `synthetic`.
+	pub fn java(self, context: AccessContext) -> String {
+		let mut flags = self;
+		if context == AccessContext::Class && flags.contains(AccessFlags::INTERFACE) {
+			flags.remove(AccessFlags::ABSTRACT);
+		}
+		if flags.contains(AccessFlags::SYNCHRONIZED | AccessFlags::DECLARED_SYNCHRONIZED) {
+			flags.remove(AccessFlags::DECLARED_SYNCHRONIZED);
+		}
+
+		JAVA_MODIFIERS
+			.iter()
+			.filter(|(flag, _, valid)| flags.intersects(*flag) && valid[context as usize])
+			.map(|(_, keyword, _)| *keyword)
+			.collect::<Vec<_>>()
+			.join(" ")
+	}
+}
+
+/// Restrictions on using a field or method that isn't part of the public
+/// SDK, as stored in the hidden API class data.
+///
+/// https://source.android.com/devices/tech/dalvik/dex-format#hiddenapi-class-data-item
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct HiddenapiFlags(pub u32);
+
+impl HiddenapiFlags {
+	/// The restriction is stored in the low three bits.
+	const RESTRICTION_MASK: u32 = 0x7;
+	const CORE_PLATFORM_API: u32 = 0x8;
+	const TEST_API: u32 = 0x10;
+
+	pub fn restriction(self) -> HiddenapiRestriction {
+		match self.0 & Self::RESTRICTION_MASK {
+			0 => HiddenapiRestriction::Whitelist,
+			1 => HiddenapiRestriction::Greylist,
+			2 => HiddenapiRestriction::Blacklist,
+			3 => HiddenapiRestriction::GreylistMaxO,
+			4 => HiddenapiRestriction::GreylistMaxP,
+			5 => HiddenapiRestriction::GreylistMaxQ,
+			6 => HiddenapiRestriction::GreylistMaxR,
+			_ => HiddenapiRestriction::GreylistMaxS,
+		}
+	}
+
+	/// Part of the API the platform itself may use across modules.
+	pub fn is_core_platform_api(self) -> bool {
+		self.0 & Self::CORE_PLATFORM_API != 0
+	}
+
+	pub fn is_test_api(self) -> bool {
+		self.0 & Self::TEST_API != 0
+	}
+}
+
+/// Which apps may use a member, the `max` variants allow apps targeting at
+/// most that Android version.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum HiddenapiRestriction {
+	/// Part of the public SDK.
+	Whitelist,
+	/// Not part of the SDK but usable by every app.
+	Greylist,
+	/// Not usable by apps at all.
+	Blacklist,
+	GreylistMaxO,
+	GreylistMaxP,
+	GreylistMaxQ,
+	GreylistMaxR,
+	/// Not in the format documentation yet, but written by newer tools.
+	GreylistMaxS,
+}
+
+impl HiddenapiRestriction {
+	/// The name the `hiddenapi` tool and smali use.
+	pub fn name(self) -> &'static str {
+		match self {
+			HiddenapiRestriction::Whitelist => "whitelist",
+			HiddenapiRestriction::Greylist => "greylist",
+			HiddenapiRestriction::Blacklist => "blacklist",
+			HiddenapiRestriction::GreylistMaxO => "greylist-max-o",
+			HiddenapiRestriction::GreylistMaxP => "greylist-max-p",
+			HiddenapiRestriction::GreylistMaxQ => "greylist-max-q",
+			HiddenapiRestriction::GreylistMaxR => "greylist-max-r",
+			HiddenapiRestriction::GreylistMaxS => "greylist-max-s",
+		}
+	}
+}
@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+
+use eyre::{eyre, Result};
+
+use crate::dex::{
+	parser::{parse::Uleb128, ReadThings},
+	resolver::{Resolve, ResolveFrom},
+	types::{
+		descriptor::TypeDescriptor,
+		id::{DebugInfoItem, TypeIdItem},
+		refs::Idx,
+	},
+	writer::WriteThings,
+};
+
+pub(crate) const DBG_END_SEQUENCE: u8 = 0x00;
+pub(crate) const DBG_ADVANCE_PC: u8 = 0x01;
+pub(crate) const DBG_ADVANCE_LINE: u8 = 0x02;
+pub(crate) const DBG_START_LOCAL: u8 = 0x03;
+pub(crate) const DBG_START_LOCAL_EXTENDED: u8 = 0x04;
+pub(crate) const DBG_END_LOCAL: u8 = 0x05;
+pub(crate) const DBG_RESTART_LOCAL: u8 = 0x06;
+pub(crate) const DBG_SET_PROLOGUE_END: u8 = 0x07;
+pub(crate) const DBG_SET_EPILOGUE_BEGIN: u8 = 0x08;
+pub(crate) const DBG_SET_FILE: u8 = 0x09;
+const DBG_FIRST_SPECIAL: u8 = 0x0a;
+const DBG_LINE_BASE: i32 = -4;
+const DBG_LINE_RANGE: u8 = 15;
+
+impl DebugInfo {
+	/// Encodes the state changes back into the state machine bytecode, with
+	/// `string` and `typ` giving the indices strings and types end up at.
+	pub(crate) fn encode(
+		&self,
+		string: impl Fn(&str) -> Result<u32>,
+		typ: impl Fn(&TypeDescriptor) -> Result<u32>,
+	) -> Result<DebugInfoItem> {
+		// indices are stored plus one, with `0` meaning none
+		let string = |s: &Option<String>| -> Result<u32> {
+			s.as_deref().map_or(Ok(0), |s| Ok(string(s)? + 1))
+		};
+		let typ = |t: &Option<TypeDescriptor>| -> Result<u32> {
+			t.as_ref().map_or(Ok(0), |t| Ok(typ(t)? + 1))
+		};
+
+		// the same order the state machine produced them in within an
+		// address, positions come last as a special opcode emits them
+		let mut events = Vec::new();
+		for local in self.locals.iter() {
+			if let Some(start) = local.start {
+				let event = if local.restarted {
+					DebugEvent::RestartLocal(local.register)
+				} else {
+					DebugEvent::StartLocal {
+						register:  local.register,
+						name:      local.name.clone(),
+						typ:       local.typ.clone(),
+						signature: local.signature.clone(),
+					}
+				};
+				events.push((start, 1, event));
+			}
+			if let Some(end) = local.end {
+				events.push((end, 0, DebugEvent::EndLocal(local.register)));
+			}
+		}
+		events.extend(
+			self.prologue_ends
+				.iter()
+				.map(|&address| (address, 2, DebugEvent::PrologueEnd)),
+		);
+		events.extend(
+			self.epilogue_begins
+				.iter()
+				.map(|&address| (address, 2, DebugEvent::EpilogueBegin)),
+		);
+		events.extend(
+			self.source_files
+				.iter()
+				.map(|(address, file)| (*address, 3, DebugEvent::SetFile(file.clone()))),
+		);
+		events.extend(
+			self.positions
+				.iter()
+				.map(|position| (position.address, 4, DebugEvent::Line(position.line))),
+		);
+		// stable, so locals in the same register keep their order
+		events.sort_by_key(|(address, order, _)| (*address, *order));
+
+		let mut bytecode = Vec::new();
+		let mut address = 0u32;
+		let mut line = self.line_start;
+		for (target, _, event) in events {
+			let address_diff = target - address;
+			if let DebugEvent::Line(target_line) = event {
+				let mut line_diff = target_line.wrapping_sub(line) as i32;
+				let in_range = |diff: i32| {
+					(DBG_LINE_BASE..DBG_LINE_BASE + DBG_LINE_RANGE as i32).contains(&diff)
+				};
+				if !in_range(line_diff) {
+					bytecode.u8(DBG_ADVANCE_LINE)?;
+					bytecode.sleb128(line_diff)?;
+					line_diff = 0;
+				}
+				let mut adjusted = (line_diff - DBG_LINE_BASE) as u32
+					+ address_diff * DBG_LINE_RANGE as u32
+					+ DBG_FIRST_SPECIAL as u32;
+				if adjusted > u8::MAX as u32 {
+					bytecode.u8(DBG_ADVANCE_PC)?;
+					bytecode.uleb128(address_diff)?;
+					adjusted = (line_diff - DBG_LINE_BASE) as u32 + DBG_FIRST_SPECIAL as u32;
+				}
+				bytecode.u8(adjusted as u8)?;
+				address = target;
+				line = target_line;
+				continue;
+			}
+
+			if address_diff != 0 {
+				bytecode.u8(DBG_ADVANCE_PC)?;
+				bytecode.uleb128(address_diff)?;
+				address = target;
+			}
+			match event {
+				DebugEvent::StartLocal {
+					register,
+					name,
+					typ: local_type,
+					signature,
+				} => {
+					let extended = signature.is_some();
+					bytecode.u8(if extended {
+						DBG_START_LOCAL_EXTENDED
+					} else {
+						DBG_START_LOCAL
+					})?;
+					bytecode.uleb128(register as u32)?;
+					bytecode.uleb128(string(&name)?)?;
+					bytecode.uleb128(typ(&local_type)?)?;
+					if extended {
+						bytecode.uleb128(string(&signature)?)?;
+					}
+				}
+				DebugEvent::EndLocal(register) => {
+					bytecode.u8(DBG_END_LOCAL)?;
+					bytecode.uleb128(register as u32)?;
+				}
+				DebugEvent::RestartLocal(register) => {
+					bytecode.u8(DBG_RESTART_LOCAL)?;
+					bytecode.uleb128(register as u32)?;
+				}
+				DebugEvent::PrologueEnd => bytecode.u8(DBG_SET_PROLOGUE_END)?,
+				DebugEvent::EpilogueBegin => bytecode.u8(DBG_SET_EPILOGUE_BEGIN)?,
+				DebugEvent::SetFile(file) => {
+					bytecode.u8(DBG_SET_FILE)?;
+					bytecode.uleb128(string(&file)?)?;
+				}
+				DebugEvent::Line(_) => unreachable!(),
+			}
+		}
+		bytecode.u8(DBG_END_SEQUENCE)?;
+
+		Ok(DebugInfoItem {
+			line_start: self.line_start.into(),
+			parameters_size: (self.parameter_names.len() as u32).into(),
+			parameter_names: self
+				.parameter_names
+				.iter()
+				.map(|name| string(name).map(Uleb128::from))
+				.collect::<Result<_>>()?,
+			bytecode,
+		})
+	}
+}
+
+/// The debug info of a method with the state machine run, addresses are in
+/// code units from the start of the method.
+#[derive(Debug, Clone, Default, PartialOrd, PartialEq)]
+pub struct DebugInfo {
+	pub line_start:      u32,
+	/// Names of the parameters, not including `this`.
+	pub parameter_names: Vec<Option<String>>,
+	/// Source line of each address a new position entry starts at.
+	pub positions:       Vec<Position>,
+	pub locals:          Vec<Local>,
+	pub prologue_ends:   Vec<u32>,
+	pub epilogue_begins: Vec<u32>,
+	/// Source file changes, `None` switches back to the one of the class.
+	pub source_files:    Vec<(u32, Option<String>)>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Position {
+	pub address: u32,
+	pub line:    u32,
+}
+
+/// A local variable living in `register` from `start` until `end`.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Local {
+	pub register:  u16,
+	pub name:      Option<String>,
+	pub typ:       Option<TypeDescriptor>,
+	pub signature: Option<String>,
+	/// `None` for parameters, which are live from the start of the method
+	/// without being started explicitly.
+	pub start:     Option<u32>,
+	/// `None` if the local lives until the end of the method.
+	pub end:       Option<u32>,
+	/// If the local was brought back by `DBG_RESTART_LOCAL` after the one
+	/// before it in the same register ended.
+	pub restarted: bool,
+}
+
+/// The state changes of the debug info state machine that aren't just
+/// advancing the address.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DebugEvent {
+	Line(u32),
+	StartLocal {
+		register:  u16,
+		name:      Option<String>,
+		typ:       Option<TypeDescriptor>,
+		signature: Option<String>,
+	},
+	EndLocal(u16),
+	RestartLocal(u16),
+	PrologueEnd,
+	EpilogueBegin,
+	SetFile(Option<String>),
+}
+
+/// Collects [`DebugEvent`]s into a [`DebugInfo`], tracking which local is
+/// live in which register.
+pub(crate) struct DebugInfoBuilder {
+	info: DebugInfo,
+	/// register -> index of the last local started in it
+	last: HashMap<u16, usize>,
+}
+
+impl DebugInfoBuilder {
+	pub(crate) fn new(line_start: u32, parameter_names: Vec<Option<String>>) -> Self {
+		DebugInfoBuilder {
+			info: DebugInfo {
+				line_start,
+				parameter_names,
+				..DebugInfo::default()
+			},
+			last: HashMap::new(),
+		}
+	}
+
+	pub(crate) fn event(&mut self, address: u32, event: DebugEvent) {
+		match event {
+			DebugEvent::Line(line) => self.info.positions.push(Position { address, line }),
+			DebugEvent::StartLocal {
+				register,
+				name,
+				typ,
+				signature,
+			} => {
+				self.end(register, address);
+				self.start(Local {
+					register,
+					name,
+					typ,
+					signature,
+					start: Some(address),
+					end: None,
+					restarted: false,
+				});
+			}
+			DebugEvent::EndLocal(register) => {
+				if !self.end(register, address) {
+					// a parameter that was never started, it ends here
+					self.start(Local {
+						register,
+						name: None,
+						typ: None,
+						signature: None,
+						start: None,
+						end: Some(address),
+						restarted: false,
+					});
+				}
+			}
+			DebugEvent::RestartLocal(register) => {
+				self.end(register, address);
+				let local = match self.last.get(&register) {
+					Some(&idx) => Local {
+						start: Some(address),
+						end: None,
+						restarted: true,
+						..self.info.locals[idx].clone()
+					},
+					None => Local {
+						register,
+						name: None,
+						typ: None,
+						signature: None,
+						start: Some(address),
+						end: None,
+						restarted: true,
+					},
+				};
+				self.start(local);
+			}
+			DebugEvent::PrologueEnd => self.info.prologue_ends.push(address),
+			DebugEvent::EpilogueBegin => self.info.epilogue_begins.push(address),
+			DebugEvent::SetFile(file) => self.info.source_files.push((address, file)),
+		}
+	}
+
+	pub(crate) fn finish(self) -> DebugInfo {
+		self.info
+	}
+
+	fn start(&mut self, local: Local) {
+		self.last.insert(local.register, self.info.locals.len());
+		self.info.locals.push(local);
+	}
+
+	/// Ends the local live in `register`, returns `false` if there is none.
+	fn end(&mut self, register: u16, address: u32) -> bool {
+		match self.last.get(&register) {
+			Some(&idx) if self.info.locals[idx].end.is_none() => {
+				self.info.locals[idx].end = Some(address);
+				true
+			}
+			_ => false,
+		}
+	}
+}
+
+impl ResolveFrom<DebugInfoItem> for DebugInfo {
+	fn resolve_from(item: &DebugInfoItem, resolver: &impl Resolve) -> Result<Self> {
+		// string and type indices are stored plus one, with `0` meaning none
+		let string = |idx: u32| -> Result<Option<String>> {
+			idx.checked_sub(1)
+				.map(|idx| resolver.string(idx as usize))
+				.transpose()
+		};
+		let typ = |idx: u32| -> Result<Option<TypeDescriptor>> {
+			idx.checked_sub(1)
+				.map(|idx| {
+					Idx::<TypeIdItem, u32>::new(idx as usize)
+						.resolve(resolver)?
+						.descriptor(resolver)
+				})
+				.transpose()
+		};
+
+		let parameter_names = item
+			.parameter_names
+			.iter()
+			.map(|idx| string(**idx))
+			.collect::<Result<_>>()?;
+		let mut builder = DebugInfoBuilder::new(*item.line_start, parameter_names);
+
+		let mut bytecode = item.bytecode.as_slice();
+		let mut address = 0u32;
+		let mut line = *item.line_start;
+		let advance = |address: u32, diff: u32| {
+			address
+				.checked_add(diff)
+				.ok_or_else(|| eyre!("address {:#x} + {:#x} overflows", address, diff))
+		};
+		loop {
+			let opcode = bytecode.u8()?;
+			let event = match opcode {
+				DBG_END_SEQUENCE => break,
+				DBG_ADVANCE_PC => {
+					address = advance(address, *bytecode.uleb128()?)?;
+					continue;
+				}
+				DBG_ADVANCE_LINE => {
+					line = line.wrapping_add(*bytecode.sleb128()? as u32);
+					continue;
+				}
+				DBG_START_LOCAL | DBG_START_LOCAL_EXTENDED => DebugEvent::StartLocal {
+					register:  *bytecode.uleb128()? as u16,
+					name:      string(*bytecode.uleb128()?)?,
+					typ:       typ(*bytecode.uleb128()?)?,
+					signature: if opcode == DBG_START_LOCAL_EXTENDED {
+						string(*bytecode.uleb128()?)?
+					} else {
+						None
+					},
+				},
+				DBG_END_LOCAL => DebugEvent::EndLocal(*bytecode.uleb128()? as u16),
+				DBG_RESTART_LOCAL => DebugEvent::RestartLocal(*bytecode.uleb128()? as u16),
+				DBG_SET_PROLOGUE_END => DebugEvent::PrologueEnd,
+				DBG_SET_EPILOGUE_BEGIN => DebugEvent::EpilogueBegin,
+				DBG_SET_FILE => DebugEvent::SetFile(string(*bytecode.uleb128()?)?),
+				special => {
+					let adjusted = special - DBG_FIRST_SPECIAL;
+					line = line
+						.wrapping_add((DBG_LINE_BASE + (adjusted % DBG_LINE_RANGE) as i32) as u32);
+					address = advance(address, (adjusted / DBG_LINE_RANGE) as u32)?;
+					DebugEvent::Line(line)
+				}
+			};
+			builder.event(address, event);
+		}
+
+		Ok(builder.finish())
+	}
+}
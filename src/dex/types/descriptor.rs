@@ -0,0 +1,248 @@
+use std::{
+	convert::TryFrom,
+	fmt::{Display, Formatter},
+	str::FromStr,
+};
+
+use eyre::{bail, ensure, eyre, Report, Result};
+
+/// https://source.android.com/devices/tech/dalvik/dex-format#typedescriptor
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum TypeDescriptor {
+	Void,
+	Boolean,
+	Byte,
+	Short,
+	Char,
+	Int,
+	Long,
+	Float,
+	Double,
+	/// A class by its name with `/` separated packages, e.g. `java/lang/String`.
+	Object(String),
+	/// `dimensions` levels of arrays, the element type is never an array itself.
+	Array(u8, Box<TypeDescriptor>),
+}
+
+impl TypeDescriptor {
+	/// Parses a single descriptor off the front of `s` and returns the rest,
+	/// for reading the parameters of a method descriptor.
+	pub fn parse_prefix(s: &str) -> Result<(Self, &str)> {
+		let dimensions = s.bytes().take_while(|&b| b == b'[').count();
+		let dimensions = u8::try_from(dimensions)
+			.map_err(|_| eyre!("array type `{}` has too many dimensions", s))?;
+
+		let rest = &s[dimensions as usize..];
+		let (element, rest) = match rest.chars().next() {
+			Some('L') => match rest.find(';') {
+				Some(end) if end > 1 => (
+					TypeDescriptor::Object(rest[1..end].to_string()),
+					&rest[end + 1..],
+				),
+				_ => bail!("unterminated class descriptor `{}`", s),
+			},
+			Some(c) => match Self::primitive(c) {
+				Some(primitive) => (primitive, &rest[1..]),
+				None => bail!("invalid type descriptor `{}`", s),
+			},
+			None => bail!("invalid type descriptor `{}`", s),
+		};
+
+		if dimensions == 0 {
+			return Ok((element, rest));
+		}
+		ensure!(element != TypeDescriptor::Void, "array of void `{}`", s);
+		Ok((TypeDescriptor::Array(dimensions, Box::new(element)), rest))
+	}
+
+	fn primitive(c: char) -> Option<Self> {
+		Some(match c {
+			'V' => TypeDescriptor::Void,
+			'Z' => TypeDescriptor::Boolean,
+			'B' => TypeDescriptor::Byte,
+			'S' => TypeDescriptor::Short,
+			'C' => TypeDescriptor::Char,
+			'I' => TypeDescriptor::Int,
+			'J' => TypeDescriptor::Long,
+			'F' => TypeDescriptor::Float,
+			'D' => TypeDescriptor::Double,
+			_ => return None,
+		})
+	}
+
+	fn keyword(&self) -> Option<&'static str> {
+		Some(match self {
+			TypeDescriptor::Void => "void",
+			TypeDescriptor::Boolean => "boolean",
+			TypeDescriptor::Byte => "byte",
+			TypeDescriptor::Short => "short",
+			TypeDescriptor::Char => "char",
+			TypeDescriptor::Int => "int",
+			TypeDescriptor::Long => "long",
+			TypeDescriptor::Float => "float",
+			TypeDescriptor::Double => "double",
+			TypeDescriptor::Object(_) | TypeDescriptor::Array(..) => return None,
+		})
+	}
+
+	fn from_keyword(keyword: &str) -> Option<Self> {
+		Some(match keyword {
+			"void" => TypeDescriptor::Void,
+			"boolean" => TypeDescriptor::Boolean,
+			"byte" => TypeDescriptor::Byte,
+			"short" => TypeDescriptor::Short,
+			"char" => TypeDescriptor::Char,
+			"int" => TypeDescriptor::Int,
+			"long" => TypeDescriptor::Long,
+			"float" => TypeDescriptor::Float,
+			"double" => TypeDescriptor::Double,
+			_ => return None,
+		})
+	}
+
+	/// The name as written in Java source, e.g. `java.lang.String[]`.
+	///
+	/// Nested classes keep their `$`, as the descriptor doesn't say which
+	/// part of the name is the outer class.
+	pub fn java_name(&self) -> String {
+		match self {
+			TypeDescriptor::Object(name) => name.replace('/', "."),
+			TypeDescriptor::Array(dimensions, element) => {
+				format!(
+					"{}{}",
+					element.java_name(),
+					"[]".repeat(*dimensions as usize)
+				)
+			}
+			primitive => primitive.keyword().unwrap_or_default().to_string(),
+		}
+	}
+
+	/// Parses a name as written in Java source, with nested classes written
+	/// with `$` like [`java_name`](Self::java_name) does.
+	pub fn from_java_name(name: &str) -> Result<Self> {
+		let mut element = name;
+		let mut dimensions = 0u8;
+		while let Some(rest) = element.strip_suffix("[]") {
+			element = rest;
+			dimensions = dimensions
+				.checked_add(1)
+				.ok_or_else(|| eyre!("array type `{}` has too many dimensions", name))?;
+		}
+
+		let element = match Self::from_keyword(element) {
+			Some(primitive) => primitive,
+			None => Self::object_from_dotted(element)?,
+		};
+		if dimensions == 0 {
+			return Ok(element);
+		}
+		ensure!(element != TypeDescriptor::Void, "array of void `{}`", name);
+		Ok(TypeDescriptor::Array(dimensions, Box::new(element)))
+	}
+
+	/// The name `Class.getName()` returns, e.g. `java.lang.String`, `int` or
+	/// `[Ljava.lang.String;`.
+	pub fn binary_name(&self) -> String {
+		match self {
+			TypeDescriptor::Object(name) => name.replace('/', "."),
+			TypeDescriptor::Array(..) => self.to_string().replace('/', "."),
+			primitive => primitive.keyword().unwrap_or_default().to_string(),
+		}
+	}
+
+	/// Parses a name in the format of [`binary_name`](Self::binary_name).
+	pub fn from_binary_name(name: &str) -> Result<Self> {
+		if name.starts_with('[') {
+			return name.replace('.', "/").parse();
+		}
+		match Self::from_keyword(name) {
+			Some(primitive) => Ok(primitive),
+			None => Self::object_from_dotted(name),
+		}
+	}
+
+	fn object_from_dotted(name: &str) -> Result<Self> {
+		ensure!(
+			!name.is_empty() && !name.contains(['/', ';', '[']),
+			"invalid class name `{}`",
+			name
+		);
+		Ok(TypeDescriptor::Object(name.replace('.', "/")))
+	}
+
+	/// The character used for the type in shorty descriptors, all reference
+	/// types are shortened to `L`.
+	pub fn shorty(&self) -> char {
+		match self {
+			TypeDescriptor::Void => 'V',
+			TypeDescriptor::Boolean => 'Z',
+			TypeDescriptor::Byte => 'B',
+			TypeDescriptor::Short => 'S',
+			TypeDescriptor::Char => 'C',
+			TypeDescriptor::Int => 'I',
+			TypeDescriptor::Long => 'J',
+			TypeDescriptor::Float => 'F',
+			TypeDescriptor::Double => 'D',
+			TypeDescriptor::Object(_) | TypeDescriptor::Array(..) => 'L',
+		}
+	}
+
+	pub fn is_primitive(&self) -> bool {
+		!matches!(
+			self,
+			TypeDescriptor::Void | TypeDescriptor::Object(_) | TypeDescriptor::Array(..)
+		)
+	}
+
+	pub fn is_reference(&self) -> bool {
+		matches!(self, TypeDescriptor::Object(_) | TypeDescriptor::Array(..))
+	}
+
+	/// The type of the elements of an array type, with one dimension less.
+	pub fn element_type(&self) -> Option<TypeDescriptor> {
+		match self {
+			TypeDescriptor::Array(1, element) => Some(*element.clone()),
+			TypeDescriptor::Array(dimensions, element) => {
+				Some(TypeDescriptor::Array(dimensions - 1, element.clone()))
+			}
+			_ => None,
+		}
+	}
+
+	/// `long` and `double` take up a register pair.
+	pub fn is_wide(&self) -> bool {
+		matches!(self, TypeDescriptor::Long | TypeDescriptor::Double)
+	}
+
+	/// Number of registers a value of the type takes up.
+	pub fn register_width(&self) -> u16 {
+		match self {
+			TypeDescriptor::Void => 0,
+			_ if self.is_wide() => 2,
+			_ => 1,
+		}
+	}
+}
+
+impl FromStr for TypeDescriptor {
+	type Err = Report;
+
+	fn from_str(s: &str) -> Result<Self> {
+		let (descriptor, rest) = Self::parse_prefix(s)?;
+		ensure!(rest.is_empty(), "invalid type descriptor `{}`", s);
+		Ok(descriptor)
+	}
+}
+
+impl Display for TypeDescriptor {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			TypeDescriptor::Object(name) => write!(f, "L{};", name),
+			TypeDescriptor::Array(dimensions, element) => {
+				write!(f, "{}{}", "[".repeat(*dimensions as usize), element)
+			}
+			primitive => write!(f, "{}", primitive.shorty()),
+		}
+	}
+}
@@ -1,10 +1,48 @@
-use eyre::{Result, WrapErr};
+use std::collections::{BTreeMap, HashMap};
+
+use eyre::{bail, ensure, Result, WrapErr};
 
 use crate::dex::{
 	parser::{Parse, Parser},
-	types::{header::Header, id::*, map::MapList},
+	types::{
+		header::{checksum, signature, EndianConstant, Header, HEADER_SIZE},
+		id::*,
+		map::{MapItem, MapList, TypeCode},
+		refs::DataItem,
+	},
+	writer::{FileWriter, Write, WriteThings, Writer},
 };
 
+/// Order sections are laid out in when the parsed map list doesn't say
+/// otherwise, the same order d8 uses.
+const SECTION_ORDER: [TypeCode; 21] = [
+	TypeCode::HeaderItem,
+	TypeCode::StringIdItem,
+	TypeCode::TypeIdItem,
+	TypeCode::ProtoIdItem,
+	TypeCode::FieldIdItem,
+	TypeCode::MethodIdItem,
+	TypeCode::ClassDefItem,
+	TypeCode::CallSiteIdItem,
+	TypeCode::MethodHandleItem,
+	TypeCode::CodeItem,
+	TypeCode::DebugInfoItem,
+	TypeCode::TypeList,
+	TypeCode::StringDataItem,
+	TypeCode::AnnotationItem,
+	TypeCode::ClassDataItem,
+	TypeCode::EncodedArrayItem,
+	TypeCode::AnnotationSetItem,
+	TypeCode::AnnotationSetRefList,
+	TypeCode::AnnotationsDirectoryItem,
+	TypeCode::HiddenapiClassDataItem,
+	TypeCode::MapList,
+];
+
+/// Upper bound for the layout passes in [`DexFile::to_bytes`], uleb128
+/// offsets only ever grow so this is reached after a handful of passes.
+const MAX_LAYOUT_PASSES: usize = 16;
+
 #[derive(Debug)]
 pub struct DexFile {
 	pub header:   Header,
@@ -28,75 +66,123 @@ pub struct DexFile {
 	pub annotation_directories:   Vec<AnnotationsDirectoryItem>, // in map, not in header
 	pub call_site_ids:            Vec<CallSiteIdItem>,
 	pub method_handles:           Vec<MethodHandleItem>,
+	pub hiddenapi_class_data:     Option<HiddenapiClassDataItem>,
 
 	pub data:      Vec<u8>,
 	pub link_data: Vec<u8>,
+
+	/// Offsets every item of a section was parsed from, in the same order as
+	/// the section. [`DexFile::data_item`] looks items up by these and
+	/// [`DexFile::to_bytes`] relocates offsets that reference them, so items
+	/// added after parsing need a made up offset past the end of the file here
+	/// that references to them use as well.
+	pub item_offsets: BTreeMap<TypeCode, Vec<u32>>,
+
+	/// Set by [`DexFile::parse_ids`] until the data sections are parsed, the
+	/// empty sections of such a file would be written as if they had no items.
+	pub(crate) ids_only: bool,
+}
+
+/// Parses all items of a section, recording the offsets they were parsed
+/// from in `item_offsets`. Sections missing from the map list are empty.
+macro_rules! parse_section {
+	($parser:ident, $item_offsets:expr, $item:expr, $ty:ty) => {{
+		let mut items: Vec<$ty> = Vec::new();
+		if let Some(item) = $item {
+			tracing::debug!("parsing {:?}", item.item_type);
+			let mut offsets = Vec::with_capacity(item.size as usize);
+			items.reserve(item.size as usize);
+			$parser.set_offset(item.offset)?;
+			for _ in 0..item.size {
+				$parser.align(<$ty as Write>::ALIGNMENT)?;
+				offsets.push($parser.get_offset());
+				items.push(
+					$parser
+						.parse()
+						.wrap_err_with(|| format!("parsing {:?}", item.item_type))?,
+				);
+			}
+			$item_offsets.insert(item.item_type, offsets);
+		}
+		items
+	}};
 }
 
 impl Parse for DexFile {
 	#[cfg_attr(feature = "trace", instrument(skip(parser)))]
 	fn parse<P: Parser>(parser: &mut P) -> Result<Self> {
+		let mut dex_file = Self::parse_ids(parser)?;
+		dex_file.parse_data(parser)?;
+		Ok(dex_file)
+	}
+}
+
+impl DexFile {
+	/// Parses the header, the map list, the id sections, the strings they
+	/// refer to and the hidden API flags, but none of the other data
+	/// sections, which are left empty.
+	///
+	/// Data items can then be parsed individually when they are needed, like
+	/// [`Resolver::lazy`](crate::dex::resolver::Resolver::lazy) does. A file
+	/// parsed this way can't be written back.
+	#[cfg_attr(feature = "trace", instrument(skip(parser)))]
+	pub fn parse_ids<P: Parser>(parser: &mut P) -> Result<Self> {
 		let header: Header = parser.offset(0)?.parse()?;
 		debug!("Header: {:#?}", header);
+		ensure!(
+			header.endian_tag == parser.endian(),
+			"file is tagged {:?} but is read as {:?}",
+			header.endian_tag,
+			parser.endian()
+		);
 		let map_list: MapList = parser.offset(header.map_off)?.parse()?;
 
 		let map = map_list.map()?;
 		debug!("Map: {:#?}", map);
 
-		macro_rules! parse_section {
-			($item_name:ident, $ty:ty) => {{
-				tracing::debug!(concat!("parsing ", stringify!($item_name)));
-				let $item_name: Vec<$ty> = parser
-					.offset(map.$item_name.offset)?
-					.parse_list(map.$item_name.size)
-					.wrap_err(concat!("parsing ", stringify!($item_name)))?;
-				$item_name
-			}};
-			($item_name:ident, $item:expr, $ty:ty) => {{
-				tracing::debug!(concat!("parsing ", stringify!($item_name)));
-				let $item_name: Vec<$ty> = parser
-					.offset($item.offset)?
-					.parse_list($item.size)
-					.wrap_err(concat!("parsing ", stringify!($item_name)))?;
-				$item_name
-			}};
-		}
+		let mut item_offsets = BTreeMap::new();
+		item_offsets.insert(TypeCode::HeaderItem, vec![0]);
+		item_offsets.insert(TypeCode::MapList, vec![header.map_off]);
 
-		let string_ids = parse_section!(string_id_item, StringIdItem);
+		let string_ids: Vec<StringIdItem> =
+			parse_section!(parser, item_offsets, map.string_id_item, StringIdItem);
 		let string_data = string_ids
 			.iter()
 			.map(|id| parser.offset(*id.string_data_off).and_then(|p| p.parse()))
 			.collect::<Result<_>>()?;
+		item_offsets.insert(
+			TypeCode::StringDataItem,
+			string_ids.iter().map(|id| *id.string_data_off).collect(),
+		);
 
-		let type_ids = parse_section!(type_id_item, TypeIdItem);
-		let proto_ids = parse_section!(proto_id_item, ProtoIdItem);
-		let field_ids = parse_section!(field_id_item, FieldIdItem);
-		let method_ids = parse_section!(method_id_item, MethodIdItem);
-		let class_defs = parse_section!(class_def_item, ClassDefItem);
-		let code = parse_section!(code_item, CodeItem);
-		// let debug_info = parse_section!(debug_info_item, DebugInfoItem);
-		let debug_info = vec![];
-		let type_lists = parse_section!(type_list, TypeList);
-		let annotations = parse_section!(annotation_item, AnnotationItem);
-		let class_data = parse_section!(class_data_item, ClassDataItem);
-		let encoded_arrays = parse_section!(encoded_array_item, EncodedArrayItem);
-		let annotation_sets = parse_section!(annotation_set_item, AnnotationSetItem);
-		let annotation_set_ref_lists =
-			parse_section!(annotation_set_ref_list, AnnotationSetRefList);
-		let annotation_directories =
-			parse_section!(annotations_directory_item, AnnotationsDirectoryItem);
-
-		let call_site_ids = if let Some(item) = map.call_site_id_item {
-			parse_section!(call_site_id_item, item, CallSiteIdItem)
-		} else {
-			vec![]
-		};
+		let type_ids = parse_section!(parser, item_offsets, map.type_id_item, TypeIdItem);
+		let proto_ids = parse_section!(parser, item_offsets, map.proto_id_item, ProtoIdItem);
+		let field_ids = parse_section!(parser, item_offsets, map.field_id_item, FieldIdItem);
+		let method_ids = parse_section!(parser, item_offsets, map.method_id_item, MethodIdItem);
+		let class_defs = parse_section!(parser, item_offsets, map.class_def_item, ClassDefItem);
 
-		let method_handles = if let Some(item) = map.method_handle_item {
-			parse_section!(method_handle_item, item, MethodHandleItem)
-		} else {
-			vec![]
-		};
+		let call_site_ids =
+			parse_section!(parser, item_offsets, map.call_site_id_item, CallSiteIdItem);
+		let method_handles = parse_section!(
+			parser,
+			item_offsets,
+			map.method_handle_item,
+			MethodHandleItem
+		);
+		let mut hiddenapi_class_data = parse_section!(
+			parser,
+			item_offsets,
+			map.hiddenapi_class_data_item,
+			HiddenapiClassDataItem
+		);
+		ensure!(
+			hiddenapi_class_data.len() <= 1,
+			"there are {} hidden API sections",
+			hiddenapi_class_data.len()
+		);
+
+		let mut link_data = vec![0; header.link_size as usize];
+		parser.offset(header.link_off)?.read_exact(&mut link_data)?;
 
 		Ok(DexFile {
 			header,
@@ -107,20 +193,297 @@ impl Parse for DexFile {
 			field_ids,
 			method_ids,
 			class_defs,
-			code,
-			debug_info,
-			type_lists,
+			code: vec![],
+			debug_info: vec![],
+			type_lists: vec![],
 			string_data,
-			annotations,
-			class_data,
-			encoded_arrays,
-			annotation_sets,
-			annotation_set_ref_lists,
-			annotation_directories,
+			annotations: vec![],
+			class_data: vec![],
+			encoded_arrays: vec![],
+			annotation_sets: vec![],
+			annotation_set_ref_lists: vec![],
+			annotation_directories: vec![],
 			call_site_ids,
 			method_handles,
+			hiddenapi_class_data: hiddenapi_class_data.pop(),
 			data: vec![],
-			link_data: vec![],
+			link_data,
+			item_offsets,
+			ids_only: true,
 		})
 	}
+
+	/// Parses the data sections [`DexFile::parse_ids`] left out.
+	fn parse_data<P: Parser>(&mut self, parser: &mut P) -> Result<()> {
+		let map = self.map_list.map()?;
+		let offsets = &mut self.item_offsets;
+
+		self.code = parse_section!(parser, offsets, map.code_item, CodeItem);
+		self.debug_info = parse_section!(parser, offsets, map.debug_info_item, DebugInfoItem);
+		self.type_lists = parse_section!(parser, offsets, map.type_list, TypeList);
+		self.annotations = parse_section!(parser, offsets, map.annotation_item, AnnotationItem);
+		self.class_data = parse_section!(parser, offsets, map.class_data_item, ClassDataItem);
+		self.encoded_arrays =
+			parse_section!(parser, offsets, map.encoded_array_item, EncodedArrayItem);
+		self.annotation_sets =
+			parse_section!(parser, offsets, map.annotation_set_item, AnnotationSetItem);
+		self.annotation_set_ref_lists = parse_section!(
+			parser,
+			offsets,
+			map.annotation_set_ref_list,
+			AnnotationSetRefList
+		);
+		self.annotation_directories = parse_section!(
+			parser,
+			offsets,
+			map.annotations_directory_item,
+			AnnotationsDirectoryItem
+		);
+		self.ids_only = false;
+		Ok(())
+	}
+}
+
+/// Where the sections ended up in one layout pass of [`DexFile::to_bytes`].
+#[derive(Debug, Eq, PartialEq)]
+struct Layout {
+	sections:     Vec<MapItem>,
+	item_offsets: BTreeMap<TypeCode, Vec<u32>>,
+}
+
+impl DexFile {
+	/// Looks up the item of a data section that was parsed from `offset`.
+	pub fn data_item<T: DataItem>(&self, offset: u32) -> Option<&T> {
+		let idx = self
+			.item_offsets
+			.get(&T::TYPE_CODE)?
+			.binary_search(&offset)
+			.ok()?;
+		T::dex_section(self).get(idx)
+	}
+
+	/// Serializes the file, regenerating the map list, the header and the
+	/// checksums from the items.
+	///
+	/// Offsets between items are relocated to where the referenced items end
+	/// up, an unmodified file is written back byte for byte. Files are always
+	/// written little endian, whatever byte order they were read in.
+	pub fn to_bytes(&self) -> Result<Vec<u8>> {
+		ensure!(
+			!self.ids_only,
+			"only the ids of this file were parsed, it can't be written back"
+		);
+		let order = self.section_order();
+
+		// Offsets depend on the layout and uleb128 encoded offsets change the
+		// layout, so lay the file out until nothing moves anymore.
+		let mut relocations = self.relocations(&self.item_offsets)?;
+		let mut map = self.map_list.list.clone();
+		let mut previous = None;
+		for _ in 0..MAX_LAYOUT_PASSES {
+			let (bytes, layout) = self.write_pass(&order, relocations, &map)?;
+			if previous.as_ref() == Some(&layout) {
+				return Ok(bytes);
+			}
+
+			relocations = self.relocations(&layout.item_offsets)?;
+			map = layout.sections.clone();
+			previous = Some(layout);
+		}
+
+		bail!("layout didn't settle after {} passes", MAX_LAYOUT_PASSES)
+	}
+
+	/// Checks the header against `bytes`, the file this was parsed from, see
+	/// [`Header::verify_integrity`].
+	pub fn verify_integrity(&self, bytes: &[u8]) -> Result<()> {
+		self.header.verify_integrity(bytes)
+	}
+
+	/// Sections in the order of the parsed map list, sections that weren't
+	/// in it are placed after the section that precedes them in
+	/// [`SECTION_ORDER`].
+	fn section_order(&self) -> Vec<TypeCode> {
+		let mut order: Vec<TypeCode> = self.map_list.list.iter().map(|i| i.item_type).collect();
+		for (i, typ) in SECTION_ORDER.iter().enumerate() {
+			if order.contains(typ) {
+				continue;
+			}
+			let position = SECTION_ORDER[..i]
+				.iter()
+				.rev()
+				.find_map(|previous| order.iter().position(|t| t == previous))
+				.map_or(0, |p| p + 1);
+			order.insert(position, *typ);
+		}
+		order
+	}
+
+	/// Maps the offsets items were parsed from to the ones they are written to.
+	fn relocations(&self, new: &BTreeMap<TypeCode, Vec<u32>>) -> Result<HashMap<u32, u32>> {
+		let mut relocations = HashMap::new();
+		for (typ, old) in self.item_offsets.iter() {
+			let new = new.get(typ).map_or(&[][..], |o| &o[..]);
+			if old.len() != new.len() {
+				bail!(
+					"{:?} has {} items but {} recorded offsets",
+					typ,
+					new.len(),
+					old.len()
+				);
+			}
+			relocations.extend(old.iter().copied().zip(new.iter().copied()));
+		}
+		Ok(relocations)
+	}
+
+	fn write_pass(
+		&self,
+		order: &[TypeCode],
+		relocations: HashMap<u32, u32>,
+		map: &[MapItem],
+	) -> Result<(Vec<u8>, Layout)> {
+		let mut writer = FileWriter::new(relocations);
+		let mut sections = Vec::new();
+		let mut item_offsets = BTreeMap::new();
+		let mut data_off = None;
+
+		for &typ in order {
+			if u16::from(typ) >= u16::from(TypeCode::MapList) && data_off.is_none() {
+				data_off = Some(writer.get_offset());
+			}
+
+			let offsets = self
+				.write_section(&mut writer, typ, map)
+				.wrap_err_with(|| format!("writing {:?}", typ))?;
+			// the map list only has the sections with items
+			if let Some(&offset) = offsets.first() {
+				sections.push(MapItem {
+					item_type: typ,
+					size: offsets.len() as u32,
+					offset,
+				});
+			}
+			item_offsets.insert(typ, offsets);
+		}
+
+		let file_end = writer.get_offset();
+		let data_off = data_off.unwrap_or(file_end);
+		let (link_size, link_off) = if self.link_data.is_empty() {
+			(0, 0)
+		} else {
+			(self.link_data.len() as u32, writer.get_offset())
+		};
+		writer.bytes(&self.link_data)?;
+
+		let mut bytes = writer.into_inner();
+		let section = |typ| {
+			sections
+				.iter()
+				.find(|i: &&MapItem| i.item_type == typ)
+				.map_or((0, 0), |i| (i.size, i.offset))
+		};
+		let (string_ids_size, string_ids_off) = section(TypeCode::StringIdItem);
+		let (type_ids_size, type_ids_off) = section(TypeCode::TypeIdItem);
+		let (proto_ids_size, proto_ids_off) = section(TypeCode::ProtoIdItem);
+		let (field_ids_size, field_ids_off) = section(TypeCode::FieldIdItem);
+		let (method_ids_size, method_ids_off) = section(TypeCode::MethodIdItem);
+		let (class_defs_size, class_defs_off) = section(TypeCode::ClassDefItem);
+		let header = Header {
+			format_version: self.header.format_version,
+			checksum: 0,
+			signature: [0; 20],
+			file_size: bytes.len() as u32,
+			header_size: HEADER_SIZE,
+			endian_tag: EndianConstant::EndianConstant,
+			link_size,
+			link_off,
+			map_off: section(TypeCode::MapList).1,
+			string_ids_size,
+			string_ids_off,
+			type_ids_size,
+			type_ids_off,
+			proto_ids_size,
+			proto_ids_off,
+			field_ids_size,
+			field_ids_off,
+			method_ids_size,
+			method_ids_off,
+			class_defs_size,
+			class_defs_off,
+			data_size: file_end - data_off,
+			data_off,
+		};
+		let mut header_writer = FileWriter::default();
+		header_writer.write_item(&header)?;
+		bytes[..HEADER_SIZE as usize].copy_from_slice(&header_writer.into_inner());
+
+		let signature = signature(&bytes);
+		bytes[12..32].copy_from_slice(&signature);
+		let checksum = checksum(&bytes);
+		bytes[8..12].copy_from_slice(&checksum.to_le_bytes());
+
+		Ok((
+			bytes,
+			Layout {
+				sections,
+				item_offsets,
+			},
+		))
+	}
+
+	/// Writes all items of a section and returns the offsets they were
+	/// written to.
+	fn write_section(
+		&self,
+		writer: &mut FileWriter,
+		typ: TypeCode,
+		map: &[MapItem],
+	) -> Result<Vec<u32>> {
+		fn items<T: Write>(writer: &mut FileWriter, items: &[T]) -> Result<Vec<u32>> {
+			items
+				.iter()
+				.map(|item| {
+					writer.align(T::ALIGNMENT)?;
+					let offset = writer.get_offset();
+					writer.write_item(item)?;
+					Ok(offset)
+				})
+				.collect()
+		}
+
+		match typ {
+			// the header depends on the layout, so it is filled in at the end
+			TypeCode::HeaderItem => {
+				writer.bytes(&[0; HEADER_SIZE as usize])?;
+				Ok(vec![0])
+			}
+			TypeCode::StringIdItem => items(writer, &self.string_ids),
+			TypeCode::TypeIdItem => items(writer, &self.type_ids),
+			TypeCode::ProtoIdItem => items(writer, &self.proto_ids),
+			TypeCode::FieldIdItem => items(writer, &self.field_ids),
+			TypeCode::MethodIdItem => items(writer, &self.method_ids),
+			TypeCode::ClassDefItem => items(writer, &self.class_defs),
+			TypeCode::CallSiteIdItem => items(writer, &self.call_site_ids),
+			TypeCode::MethodHandleItem => items(writer, &self.method_handles),
+			TypeCode::MapList => items(
+				writer,
+				&[MapList {
+					size: map.len() as u32,
+					list: map.to_vec(),
+				}],
+			),
+			TypeCode::TypeList => items(writer, &self.type_lists),
+			TypeCode::AnnotationSetRefList => items(writer, &self.annotation_set_ref_lists),
+			TypeCode::AnnotationSetItem => items(writer, &self.annotation_sets),
+			TypeCode::ClassDataItem => items(writer, &self.class_data),
+			TypeCode::CodeItem => items(writer, &self.code),
+			TypeCode::StringDataItem => items(writer, &self.string_data),
+			TypeCode::DebugInfoItem => items(writer, &self.debug_info),
+			TypeCode::AnnotationItem => items(writer, &self.annotations),
+			TypeCode::EncodedArrayItem => items(writer, &self.encoded_arrays),
+			TypeCode::AnnotationsDirectoryItem => items(writer, &self.annotation_directories),
+			TypeCode::HiddenapiClassDataItem => items(writer, self.hiddenapi_class_data.as_slice()),
+		}
+	}
 }
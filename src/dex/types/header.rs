@@ -1,21 +1,52 @@
-use std::convert::{TryFrom, TryInto};
+use std::{
+	convert::{TryFrom, TryInto},
+	io::{Read, Seek, SeekFrom},
+};
 
+use adler::adler32_slice;
 use color_eyre::{
-	eyre::{bail, WrapErr},
+	eyre::{bail, ensure, WrapErr},
 	Result,
 };
+use sha1::{Digest, Sha1};
 
-use crate::dex::parser::{Parse, ParseError, Parser};
+use crate::dex::{
+	parser::{Parse, ParseError, Parser},
+	writer::{Write, Writer},
+};
 
 const ENDIAN_CONSTANT: u32 = 0x12345678;
 const REVERSE_ENDIAN_CONSTANT: u32 = 0x78563412;
 
-#[derive(Debug, Copy, Clone)]
+/// Offset of [`Header::endian_tag`] in the file.
+const ENDIAN_TAG_OFFSET: u64 = 0x28;
+
+/// Byte order of a file, the standard little endian one or the big endian one
+/// the spec permits as well.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum EndianConstant {
 	EndianConstant,
 	ReverseEndianConstant,
 }
 
+impl EndianConstant {
+	/// Reads the endian tag of the header without knowing the byte order yet
+	/// and rewinds `reader` to the start of the file.
+	pub fn read_tag<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+		let mut tag = [0; 4];
+		reader.seek(SeekFrom::Start(ENDIAN_TAG_OFFSET))?;
+		reader.read_exact(&mut tag)?;
+		reader.seek(SeekFrom::Start(0))?;
+		Ok(Self::from_tag_bytes(tag)?)
+	}
+
+	/// The tag is always read as little endian, a big endian file stores
+	/// `ENDIAN_CONSTANT` byte swapped and reads as `REVERSE_ENDIAN_CONSTANT`.
+	fn from_tag_bytes(tag: [u8; 4]) -> std::result::Result<Self, ParseError> {
+		u32::from_le_bytes(tag).try_into()
+	}
+}
+
 impl TryFrom<u32> for EndianConstant {
 	type Error = ParseError;
 
@@ -23,7 +54,16 @@ impl TryFrom<u32> for EndianConstant {
 		match value {
 			ENDIAN_CONSTANT => Ok(EndianConstant::EndianConstant),
 			REVERSE_ENDIAN_CONSTANT => Ok(EndianConstant::ReverseEndianConstant),
-			_ => Err(ParseError::GenericError("Not a valid endian constant".to_string()).into()),
+			_ => Err(ParseError::generic("Not a valid endian constant")),
+		}
+	}
+}
+
+impl From<EndianConstant> for u32 {
+	fn from(value: EndianConstant) -> u32 {
+		match value {
+			EndianConstant::EndianConstant => ENDIAN_CONSTANT,
+			EndianConstant::ReverseEndianConstant => REVERSE_ENDIAN_CONSTANT,
 		}
 	}
 }
@@ -58,6 +98,9 @@ pub struct Header {
 const DEX_FILE_MAGIC: [u8; 8] = [0x64, 0x65, 0x78, 0x0a, 0x00, 0x00, 0x00, 0x00];
 //                                                       ^^^^^version^^^^^
 
+/// Size of the header, which is also where the id sections start.
+pub const HEADER_SIZE: u32 = 0x70;
+
 impl Header {
 	fn verify_header<P: Parser>(parser: &mut P) -> Result<u32> {
 		let mut magic = [0; 8];
@@ -74,6 +117,64 @@ impl Header {
 		Ok(version)
 	}
 
+	/// Checks the header against `bytes`, the whole file: the Adler-32
+	/// checksum, the SHA-1 signature and the sizes and offsets that have to
+	/// fit the file. All mismatches are reported at once.
+	pub fn verify_integrity(&self, bytes: &[u8]) -> Result<()> {
+		let mut problems = Vec::new();
+
+		if bytes.len() < HEADER_SIZE as usize {
+			bail!(
+				"file is only {} bytes, smaller than the header",
+				bytes.len()
+			);
+		}
+		if self.file_size as usize != bytes.len() {
+			problems.push(format!(
+				"file size is {} but the header says {}",
+				bytes.len(),
+				self.file_size
+			));
+		}
+		if self.header_size != HEADER_SIZE {
+			problems.push(format!(
+				"header size is {:#x} instead of {:#x}",
+				self.header_size, HEADER_SIZE
+			));
+		}
+		let data_end = self.data_off.checked_add(self.data_size);
+		if self.data_size != 0
+			&& (self.data_off < HEADER_SIZE
+				|| data_end.is_none_or(|end| end as usize > bytes.len()))
+		{
+			problems.push(format!(
+				"data section of {} bytes at {:#x} is outside of the file",
+				self.data_size, self.data_off
+			));
+		}
+
+		let checksum = checksum(bytes);
+		if checksum != self.checksum {
+			problems.push(format!(
+				"checksum is {:#010x} but the header says {:#010x}",
+				checksum, self.checksum
+			));
+		}
+		let signature = signature(bytes);
+		if signature[..] != self.signature {
+			problems.push(format!(
+				"signature is {} but the header says {}",
+				hex(&signature),
+				hex(&self.signature)
+			));
+		}
+
+		if !problems.is_empty() {
+			bail!("integrity check failed: {}", problems.join(", "));
+		}
+		Ok(())
+	}
+
 	#[cfg_attr(feature = "trace", instrument(skip(parser)))]
 	pub fn parse<P: Parser>(parser: &mut P) -> Result<Self> {
 		parser.align(4)?;
@@ -90,7 +191,11 @@ impl Header {
 			},
 			file_size:       parser.u32()?,
 			header_size:     parser.u32()?,
-			endian_tag:      parser.u32()?.try_into()?,
+			endian_tag:      {
+				let mut tag = [0; 4];
+				parser.read_exact(&mut tag)?;
+				EndianConstant::from_tag_bytes(tag)?
+			},
 			link_size:       parser.u32()?,
 			link_off:        parser.u32()?,
 			map_off:         parser.u32()?,
@@ -117,3 +222,57 @@ impl Parse for Header {
 		Header::parse(parser)
 	}
 }
+
+impl Write for Header {
+	const ALIGNMENT: u32 = 4;
+
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.align(4)?;
+
+		ensure!(
+			self.format_version < 1000,
+			"format version {} doesn't fit into the magic",
+			self.format_version
+		);
+		let mut magic = DEX_FILE_MAGIC;
+		magic[4..7].copy_from_slice(format!("{:03}", self.format_version).as_bytes());
+		writer.bytes(&magic)?;
+
+		writer.u32(self.checksum)?;
+		writer.bytes(&self.signature)?;
+		writer.u32(self.file_size)?;
+		writer.u32(self.header_size)?;
+		writer.u32(self.endian_tag.into())?;
+		writer.u32(self.link_size)?;
+		writer.u32(self.link_off)?;
+		writer.u32(self.map_off)?;
+		writer.u32(self.string_ids_size)?;
+		writer.u32(self.string_ids_off)?;
+		writer.u32(self.type_ids_size)?;
+		writer.u32(self.type_ids_off)?;
+		writer.u32(self.proto_ids_size)?;
+		writer.u32(self.proto_ids_off)?;
+		writer.u32(self.field_ids_size)?;
+		writer.u32(self.field_ids_off)?;
+		writer.u32(self.method_ids_size)?;
+		writer.u32(self.method_ids_off)?;
+		writer.u32(self.class_defs_size)?;
+		writer.u32(self.class_defs_off)?;
+		writer.u32(self.data_size)?;
+		writer.u32(self.data_off)
+	}
+}
+
+/// Adler-32 checksum of everything after the magic and the checksum itself.
+pub(crate) fn checksum(bytes: &[u8]) -> u32 {
+	adler32_slice(&bytes[12..])
+}
+
+/// SHA-1 of everything after the magic, the checksum and the signature itself.
+pub(crate) fn signature(bytes: &[u8]) -> [u8; 20] {
+	Sha1::digest(&bytes[32..]).into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
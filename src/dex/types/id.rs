@@ -1,24 +1,49 @@
-use std::{clone::Clone, marker::Copy, ops::Not};
+use std::{
+	borrow::Cow,
+	clone::Clone,
+	io,
+	io::{Cursor, Read, Seek, SeekFrom},
+	marker::Copy,
+};
 
-use eyre::{bail, ensure, Result, WrapErr};
+use eyre::{bail, ensure, eyre, Result, WrapErr};
 
 use crate::dex::{
-	asm::instruction::Instruction,
+	asm::{
+		instruction::{ArrayElements, Instruction},
+		opcode::Opcode,
+	},
 	parser::{
+		decode_mutf8,
 		parse::{Sleb128, Uleb128},
 		Parse,
 		ParseError,
 		Parser,
+		ReadThings,
+		SliceParser,
 	},
 	resolver::{Resolve, ResolveInto},
 	types::{
+		access::{AccessFlags, HiddenapiFlags},
+		debug::{
+			DBG_ADVANCE_LINE,
+			DBG_ADVANCE_PC,
+			DBG_END_LOCAL,
+			DBG_END_SEQUENCE,
+			DBG_RESTART_LOCAL,
+			DBG_SET_FILE,
+			DBG_START_LOCAL,
+			DBG_START_LOCAL_EXTENDED,
+		},
+		descriptor::TypeDescriptor,
 		file::DexFile,
-		refs::{IdItem, Idx, Ref},
+		header::EndianConstant,
+		map::TypeCode,
+		refs::{DataItem, IdItem, Idx, Ref},
 	},
+	writer::{FileWriter, Write, Writer},
 };
 
-const NO_INDEX: usize = 0xffffffff;
-
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct StringIdItem {
 	pub string_data_off: Ref<StringDataItem, u32>,
@@ -53,6 +78,88 @@ impl Parse for StringDataItem {
 	}
 }
 
+impl Write for StringDataItem {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.write_item(&self.size)?;
+		writer.bytes(&self.data)?;
+		writer.u8(0)
+	}
+}
+
+impl StringDataItem {
+	/// Encodes `string` as MUTF-8, the way strings are stored in the file.
+	pub fn new(string: &str) -> Self {
+		let mut size = 0;
+		let mut data = Vec::with_capacity(string.len());
+		for unit in string.encode_utf16() {
+			size += 1;
+			match unit {
+				0x01..=0x7f => data.push(unit as u8),
+				0x00 | 0x80..=0x7ff => {
+					data.push(0xc0 | (unit >> 6) as u8);
+					data.push(0x80 | (unit & 0x3f) as u8);
+				}
+				_ => {
+					data.push(0xe0 | (unit >> 12) as u8);
+					data.push(0x80 | ((unit >> 6) & 0x3f) as u8);
+					data.push(0x80 | (unit & 0x3f) as u8);
+				}
+			}
+		}
+
+		StringDataItem {
+			size: size.into(),
+			data,
+			string: string.to_string(),
+		}
+	}
+}
+
+/// A [`StringDataItem`] borrowed from the file, which is only decoded when
+/// the string is asked for.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+pub struct StringDataRef<'a> {
+	/// Length of the string in UTF-16 code units.
+	pub size: Uleb128,
+	/// MUTF-8 encoded string, without the terminating zero.
+	pub data: &'a [u8],
+}
+
+impl<'a> StringDataRef<'a> {
+	pub fn parse(parser: &mut SliceParser<'a>) -> Result<Self> {
+		let size = parser.uleb128()?;
+		let data = parser.take_until_nul()?;
+		Ok(StringDataRef { size, data })
+	}
+
+	/// The decoded string, borrowed if it is plain ASCII as MUTF-8 and UTF-8
+	/// agree on that.
+	pub fn decode(&self) -> Result<Cow<'a, str>> {
+		if self.data.is_ascii() && self.data.len() == *self.size as usize {
+			// ASCII is valid UTF-8
+			return Ok(Cow::Borrowed(
+				std::str::from_utf8(self.data).expect("ASCII is UTF-8"),
+			));
+		}
+		let mut bytes = self.data.iter();
+		let string = decode_mutf8(*self.size, || {
+			bytes
+				.next()
+				.copied()
+				.ok_or_else(|| eyre!("string is shorter than its size"))
+		})?;
+		Ok(Cow::Owned(string))
+	}
+
+	pub fn to_item(&self) -> Result<StringDataItem> {
+		Ok(StringDataItem {
+			size:   self.size,
+			data:   self.data.to_vec(),
+			string: self.decode()?.into_owned(),
+		})
+	}
+}
+
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct TypeIdItem {
 	pub descriptor_idx: Idx<StringIdItem, u32>,
@@ -61,14 +168,9 @@ pub struct TypeIdItem {
 parse_struct_default!(TypeIdItem 4 { descriptor_idx });
 
 impl TypeIdItem {
-	pub fn descriptor<R: Resolve>(&self, res: &R) -> Result<String> {
+	pub fn descriptor<R: Resolve>(&self, res: &R) -> Result<TypeDescriptor> {
 		let string_data: StringDataItem = self.descriptor_idx.resolve_into(res)?;
-		Ok(string_data.string.clone())
-		// self.descriptor_idx.resolve_into(res)?.string.clone()
-		// let string_id: StringIdItem = res.resolve(&self.descriptor_idx)?;
-		// res.dex_file().string_data[*self.descriptor_idx]
-		// 	.string
-		// 	.clone()
+		string_data.string.parse()
 	}
 }
 
@@ -98,7 +200,7 @@ impl IdItem for ProtoIdItem {
 }
 
 impl ProtoIdItem {
-	pub fn shorty<R: Resolve>(&self, res: &R) -> String {
+	pub fn shorty<R: Resolve>(&self, res: &R) -> Result<String> {
 		res.string(*self.shorty_idx)
 	}
 
@@ -106,9 +208,12 @@ impl ProtoIdItem {
 		res.dex_file().type_ids[*self.return_type_idx].clone()
 	}
 
-	// pub fn parameters<R: Read + Seek>(&self, __res: &Resolver<R>) -> Option<TypeList> {
-	// 	self.parameters.clone()
-	// }
+	pub fn parameters(&self, res: &impl Resolve) -> Result<Option<Vec<TypeDescriptor>>> {
+		self.parameters
+			.resolve(res)?
+			.map(|list| list.descriptors(res))
+			.transpose()
+	}
 }
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
@@ -159,7 +264,7 @@ impl MethodIdItem {
 	}
 
 	pub fn name(&self, res: &impl Resolve) -> Result<String> {
-		Ok(self.name_idx.resolve_into(res)?.string.clone())
+		Ok(self.name_idx.resolve(res)?.string)
 	}
 }
 
@@ -182,24 +287,23 @@ impl ClassDefItem {
 		// res.dex_file().type_ids[*self.class_idx].clone()
 	}
 
-	pub fn access_flags(&self, _res: &impl Resolve) -> u32 {
-		self.access_flags
+	pub fn access_flags(&self, _res: &impl Resolve) -> AccessFlags {
+		AccessFlags::from_bits_retain(self.access_flags)
 	}
 
 	pub fn superclass_type(&self, res: &impl Resolve) -> Result<TypeIdItem> {
 		self.superclass_idx.resolve_into(res)
 	}
 
-	// pub fn interfaces<R: Read + Seek>(&self, _res: &Resolver<R>) -> Option<TypeList> {
-	// 	self.interfaces.clone()
-	// }
+	pub fn interfaces(&self, res: &impl Resolve) -> Result<Option<Vec<TypeDescriptor>>> {
+		self.interfaces_off
+			.resolve(res)?
+			.map(|list| list.descriptors(res))
+			.transpose()
+	}
 
 	pub fn source_file(&self, res: &impl Resolve) -> Result<Option<String>> {
-		Ok(self.source_file_idx.resolve(res)?.map(|i| i.string.clone()))
-		// if *self.source_file_idx == NO_INDEX {
-		// 	return None;
-		// }
-		// Some(res.string(*self.source_file_idx))
+		Ok(self.source_file_idx.resolve(res)?.map(|i| i.string))
 	}
 }
 
@@ -296,10 +400,24 @@ impl Parse for MethodHandleItem {
 	}
 }
 
+impl Write for MethodHandleItem {
+	const ALIGNMENT: u32 = 4;
+
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.align(4)?;
+
+		writer.u16(self.method_handle_type)?;
+		writer.u16(0)?; // unused
+		writer.u16(self.field_or_method_id)?;
+		writer.u16(0) // unused
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#method-handle-type-codes
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
-enum MethodHandleType {
-	// Method handle is a static field setter (accessor)
+#[allow(clippy::enum_variant_names)]
+pub enum MethodHandleType {
+	/// Method handle is a static field setter (accessor)
 	MethodHandleTypeStaticPut = 0x00,
 	/// Method handle is a static field getter (accessor)
 	MethodHandleTypeStaticGet = 0x01,
@@ -344,6 +462,12 @@ impl Parse for MethodHandleType {
 	}
 }
 
+impl Write for MethodHandleType {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.u16(self.clone() as u16)
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#class-data-item
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct ClassDataItem {
@@ -381,6 +505,28 @@ impl Parse for ClassDataItem {
 	}
 }
 
+impl DataItem for ClassDataItem {
+	const TYPE_CODE: TypeCode = TypeCode::ClassDataItem;
+
+	fn dex_section(dex_file: &DexFile) -> &[Self] {
+		&dex_file.class_data
+	}
+}
+
+impl Write for ClassDataItem {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.uleb128(self.static_fields.len() as u32)?;
+		writer.uleb128(self.instance_fields.len() as u32)?;
+		writer.uleb128(self.direct_methods.len() as u32)?;
+		writer.uleb128(self.virtual_methods.len() as u32)?;
+
+		writer.write_list(&self.static_fields)?;
+		writer.write_list(&self.instance_fields)?;
+		writer.write_list(&self.direct_methods)?;
+		writer.write_list(&self.virtual_methods)
+	}
+}
+
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct EncodedField {
 	pub field_idx_diff: Uleb128,
@@ -394,9 +540,10 @@ parse_struct_default!(EncodedField {
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct EncodedMethod {
-	pub method_idx_diff: Idx<MethodIdItem, Uleb128>,
+	pub method_idx_diff: Uleb128,
 	pub access_flags:    Uleb128,
-	pub code_off:        Ref<CodeItem, Uleb128>,
+	/// `0` for abstract and native methods.
+	pub code_off:        Ref<Option<CodeItem>, Uleb128>,
 }
 
 parse_struct_default!(EncodedMethod {
@@ -425,6 +572,34 @@ impl Parse for TypeList {
 	}
 }
 
+impl DataItem for TypeList {
+	const TYPE_CODE: TypeCode = TypeCode::TypeList;
+
+	fn dex_section(dex_file: &DexFile) -> &[Self] {
+		&dex_file.type_lists
+	}
+}
+
+impl TypeList {
+	pub fn descriptors(&self, res: &impl Resolve) -> Result<Vec<TypeDescriptor>> {
+		self.list
+			.iter()
+			.map(|item| item.type_idx.resolve(res)?.descriptor(res))
+			.collect()
+	}
+}
+
+impl Write for TypeList {
+	const ALIGNMENT: u32 = 4;
+
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.align(4)?;
+
+		writer.u32(self.list.len() as u32)?;
+		writer.write_list(&self.list)
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#type-item-format
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct TypeItem {
@@ -439,13 +614,76 @@ pub struct CodeItem {
 	pub ins_size:       u16,
 	pub outs_size:      u16,
 	pub tries_size:     u16,
-	pub debug_info_off: Ref<DebugInfoItem, u32>,
+	pub debug_info_off: Ref<Option<DebugInfoItem>, u32>,
 	pub insns:          Vec<Instruction>,
 	pub padding:        Option<u16>,
 	pub tries:          Option<Vec<TryItem>>,
 	pub handlers:       Option<EncodedCatchHandlerList>,
 }
 
+impl CodeItem {
+	/// The instructions with their address, the number of code units before
+	/// them, which is what branch offsets, try ranges and debug info refer
+	/// to.
+	pub fn instructions_with_offsets(&self) -> impl Iterator<Item = (u32, &Instruction)> + '_ {
+		self.insns.iter().scan(0, |address, insn| {
+			let current = *address;
+			*address += insn.code_units();
+			Some((current, insn))
+		})
+	}
+
+	pub fn instruction_at(&self, address: u32) -> Option<&Instruction> {
+		self.instructions_with_offsets()
+			.take_while(|(at, _)| *at <= address)
+			.find(|(at, _)| *at == address)
+			.map(|(_, insn)| insn)
+	}
+
+	/// The payload of the `packed-switch`, `sparse-switch` or
+	/// `fill-array-data` instruction at `address`.
+	pub fn payload(&self, address: u32) -> Result<&Instruction> {
+		let insn = self
+			.instruction_at(address)
+			.ok_or_else(|| eyre!("no instruction at {:#x}", address))?;
+		let expected = match insn.opcode() {
+			Opcode::PackedSwitch => Opcode::PackedSwitchPayload,
+			Opcode::SparseSwitch => Opcode::SparseSwitchPayload,
+			Opcode::FillArrayData => Opcode::ArrayPayload,
+			op => bail!("{} at {:#x} doesn't have a payload", op, address),
+		};
+		let target = insn
+			.branch_target(address)
+			.ok_or_else(|| eyre!("{} at {:#x} has no offset", insn.opcode(), address))?;
+		let payload = self
+			.instruction_at(target)
+			.ok_or_else(|| eyre!("no payload at {:#x}", target))?;
+		ensure!(
+			payload.opcode() == expected,
+			"{} at {:#x} points to {} instead of its payload",
+			insn.opcode(),
+			address,
+			payload.opcode()
+		);
+		Ok(payload)
+	}
+
+	/// Keys of the switch at `address` with the addresses they jump to.
+	pub fn switch_targets(&self, address: u32) -> Result<Vec<(i32, u32)>> {
+		Ok(self
+			.payload(address)?
+			.switch_cases()?
+			.into_iter()
+			.map(|(key, offset)| (key, address.wrapping_add_signed(offset)))
+			.collect())
+	}
+
+	/// The elements the `fill-array-data` instruction at `address` fills in.
+	pub fn array_data(&self, address: u32) -> Result<ArrayElements> {
+		self.payload(address)?.array_elements()
+	}
+}
+
 impl Parse for CodeItem {
 	#[cfg_attr(
 		feature = "trace",
@@ -474,22 +712,31 @@ impl Parse for CodeItem {
 		let insns = {
 			let start_offset = parser.get_offset();
 			let mut vec = vec![0u8; (insns_size * 2) as usize];
-			parser.read(&mut vec)?;
+			parser.read_exact(&mut vec)?;
 			// trace!(offset = start_pos, "raw instructions: {:#04x?}", vec);
 
+			// instructions are decoded from little endian code units
+			if parser.endian() == EndianConstant::ReverseEndianConstant {
+				vec.chunks_exact_mut(2).for_each(|unit| unit.swap(0, 1));
+			}
+
 			let mut instructions = Vec::new();
-			parser.set_offset(start_offset)?;
-			while parser.get_offset() < start_offset + insns_size * 2 {
-				let i = match Instruction::parse(parser).wrap_err("parsing instruction") {
+			let mut code = CodeUnits {
+				units:    Cursor::new(vec),
+				start:    start_offset,
+				lenient:  parser.is_lenient(),
+				warnings: Vec::new(),
+			};
+			while code.get_offset() < start_offset + insns_size * 2 {
+				let i = match Instruction::parse(&mut code).wrap_err("parsing instruction") {
 					Ok(i) => i,
 					Err(e) => {
 						// std::io::stdout();
 						error!(
 							// instructions = format!("{:?}", instructions).as_str(),
 							// raw_instructions = format!("{:x?}", vec).as_str(),
-							offset = parser.get_offset(),
-							"failed to parse instruction: {:#}",
-							e
+							offset = code.get_offset(),
+							"failed to parse instruction: {:#}", e
 						);
 						return Err(e);
 					}
@@ -497,12 +744,15 @@ impl Parse for CodeItem {
 				// trace!(offset = parser.get_pos(), "parsed: {:x?}", i);
 				instructions.push(i);
 			}
+			for warning in code.warnings {
+				parser.warning(warning);
+			}
 
 			instructions
 		};
 
 		let (padding, tries, handlers) = if tries_size != 0 {
-			let padding = if insns_size % 2 != 0 {
+			let padding = if !insns_size.is_multiple_of(2) {
 				Some(parser.u16()?)
 			} else {
 				None
@@ -523,7 +773,7 @@ impl Parse for CodeItem {
 			}
 		}
 
-		let ret = Ok(CodeItem {
+		Ok(CodeItem {
 			registers_size,
 			ins_size,
 			outs_size,
@@ -533,9 +783,96 @@ impl Parse for CodeItem {
 			padding,
 			tries,
 			handlers,
-		});
+		})
+	}
+}
+
+/// The instructions of a code item with their code units in little endian
+/// order, at the offsets they have in the file.
+struct CodeUnits {
+	units:    Cursor<Vec<u8>>,
+	start:    u32,
+	lenient:  bool,
+	warnings: Vec<ParseError>,
+}
+
+impl Read for CodeUnits {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.units.read(buf)
+	}
+}
+
+impl Seek for CodeUnits {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		let pos = match pos {
+			SeekFrom::Start(offset) => {
+				SeekFrom::Start(offset.checked_sub(self.start as u64).ok_or_else(|| {
+					io::Error::new(
+						io::ErrorKind::InvalidInput,
+						"seeking before the instructions",
+					)
+				})?)
+			}
+			pos => pos,
+		};
+		Ok(self.units.seek(pos)? + self.start as u64)
+	}
+}
+
+impl ReadThings for CodeUnits {
+	fn is_lenient(&self) -> bool {
+		self.lenient
+	}
 
-		ret
+	fn warning(&mut self, warning: ParseError) {
+		self.warnings.push(warning);
+	}
+}
+
+impl DataItem for CodeItem {
+	const TYPE_CODE: TypeCode = TypeCode::CodeItem;
+
+	fn dex_section(dex_file: &DexFile) -> &[Self] {
+		&dex_file.code
+	}
+}
+
+impl Write for CodeItem {
+	const ALIGNMENT: u32 = 4;
+
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.align(4)?;
+
+		let insns = {
+			let mut insns = FileWriter::default();
+			insns.write_list(&self.insns)?;
+			insns.into_inner()
+		};
+		let insns_size = insns.len() as u32 / 2;
+		let tries = self.tries.as_deref().unwrap_or_default();
+
+		writer.u16(self.registers_size)?;
+		writer.u16(self.ins_size)?;
+		writer.u16(self.outs_size)?;
+		writer.u16(tries.len() as u16)?;
+		writer.write_item(&self.debug_info_off)?;
+		writer.u32(insns_size)?;
+		writer.bytes(&insns)?;
+
+		if !tries.is_empty() {
+			if !insns_size.is_multiple_of(2) {
+				writer.u16(self.padding.unwrap_or(0))?;
+			}
+
+			writer.write_list(tries)?;
+			writer.write_item(
+				self.handlers
+					.as_ref()
+					.ok_or_else(|| eyre!("code item with tries is missing its handlers"))?,
+			)?;
+		}
+
+		Ok(())
 	}
 }
 
@@ -572,6 +909,28 @@ impl Parse for EncodedCatchHandlerList {
 	}
 }
 
+impl Write for EncodedCatchHandlerList {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.uleb128(self.list.len() as u32)?;
+		writer.write_list(&self.list)
+	}
+}
+
+impl EncodedCatchHandlerList {
+	/// Looks up a handler by its byte offset from the start of the list, as
+	/// referenced by [`TryItem::handler_off`].
+	pub fn handler(&self, handler_off: u16) -> Option<&EncodedCatchHandler> {
+		let mut offset = self.size.encoded_size();
+		for handler in self.list.iter() {
+			if offset == handler_off as u32 {
+				return Some(handler);
+			}
+			offset += handler.encoded_size();
+		}
+		None
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#encoded-catch-handler
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct EncodedCatchHandler {
@@ -587,7 +946,7 @@ impl Parse for EncodedCatchHandler {
 	)]
 	fn parse<P: Parser>(parser: &mut P) -> Result<Self> {
 		let size = parser.sleb128()?;
-		let handlers = parser.parse_list(size.abs() as u32)?;
+		let handlers = parser.parse_list(size.unsigned_abs())?;
 		let catch_all_addr = if !size.is_positive() {
 			Some(parser.uleb128()?)
 		} else {
@@ -602,6 +961,36 @@ impl Parse for EncodedCatchHandler {
 	}
 }
 
+impl Write for EncodedCatchHandler {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		let size = self.handlers.len() as i32;
+		match self.catch_all_addr {
+			Some(catch_all_addr) => {
+				writer.sleb128(-size)?;
+				writer.write_list(&self.handlers)?;
+				writer.write_item(&catch_all_addr)
+			}
+			None => {
+				writer.sleb128(size)?;
+				writer.write_list(&self.handlers)
+			}
+		}
+	}
+}
+
+impl EncodedCatchHandler {
+	/// Number of bytes the handler takes up in the handler list.
+	pub fn encoded_size(&self) -> u32 {
+		self.size.encoded_size()
+			+ self
+				.handlers
+				.iter()
+				.map(|h| h.type_idx.encoded_size() + h.addr.encoded_size())
+				.sum::<u32>()
+			+ self.catch_all_addr.map_or(0, |a| a.encoded_size())
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#encoded-type-addr-pair
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct EncodedTypeAddrPair {
@@ -617,6 +1006,8 @@ pub struct DebugInfoItem {
 	pub line_start:      Uleb128,
 	pub parameters_size: Uleb128,
 	pub parameter_names: Vec<Uleb128>,
+	/// The raw state machine bytecode, up to and including `DBG_END_SEQUENCE`.
+	pub bytecode:        Vec<u8>,
 }
 
 impl Parse for DebugInfoItem {
@@ -629,18 +1020,64 @@ impl Parse for DebugInfoItem {
 		let parameters_size = parser.uleb128()?;
 		let parameter_names = parser.parse_list(*parameters_size)?;
 
+		let start = parser.get_offset();
+		loop {
+			match parser.u8()? {
+				DBG_END_SEQUENCE => break,
+				DBG_ADVANCE_PC | DBG_END_LOCAL | DBG_RESTART_LOCAL | DBG_SET_FILE => {
+					parser.uleb128()?;
+				}
+				DBG_ADVANCE_LINE => {
+					parser.sleb128()?;
+				}
+				DBG_START_LOCAL => {
+					for _ in 0..3 {
+						parser.uleb128()?;
+					}
+				}
+				DBG_START_LOCAL_EXTENDED => {
+					for _ in 0..4 {
+						parser.uleb128()?;
+					}
+				}
+				// the prologue/epilogue markers and special opcodes have no arguments
+				_ => {}
+			}
+		}
+		let mut bytecode = vec![0; (parser.get_offset() - start) as usize];
+		parser.set_offset(start)?;
+		parser.read_exact(&mut bytecode)?;
+
 		Ok(DebugInfoItem {
 			line_start,
 			parameters_size,
 			parameter_names,
+			bytecode,
 		})
 	}
 }
 
+impl DataItem for DebugInfoItem {
+	const TYPE_CODE: TypeCode = TypeCode::DebugInfoItem;
+
+	fn dex_section(dex_file: &DexFile) -> &[Self] {
+		&dex_file.debug_info
+	}
+}
+
+impl Write for DebugInfoItem {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.write_item(&self.line_start)?;
+		writer.uleb128(self.parameter_names.len() as u32)?;
+		writer.write_list(&self.parameter_names)?;
+		writer.bytes(&self.bytecode)
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#annotations-directory
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct AnnotationsDirectoryItem {
-	pub class_annotations_off:     Ref<AnnotationSetItem, u32>,
+	pub class_annotations_off:     Ref<Option<AnnotationSetItem>, u32>,
 	pub fields_size:               u32,
 	pub annotated_methods_size:    u32,
 	pub annotated_parameters_size: u32,
@@ -662,15 +1099,12 @@ impl Parse for AnnotationsDirectoryItem {
 		let annotated_methods_size = parser.u32()?;
 		let annotated_parameters_size = parser.u32()?;
 		let field_annotations = (fields_size != 0)
-			.not()
 			.then(|| parser.parse_list(fields_size))
 			.transpose()?;
 		let method_annotations = (annotated_methods_size != 0)
-			.not()
 			.then(|| parser.parse_list(annotated_methods_size))
 			.transpose()?;
 		let parameter_annotations = (annotated_parameters_size != 0)
-			.not()
 			.then(|| parser.parse_list(annotated_parameters_size))
 			.transpose()?;
 
@@ -686,6 +1120,34 @@ impl Parse for AnnotationsDirectoryItem {
 	}
 }
 
+impl DataItem for AnnotationsDirectoryItem {
+	const TYPE_CODE: TypeCode = TypeCode::AnnotationsDirectoryItem;
+
+	fn dex_section(dex_file: &DexFile) -> &[Self] {
+		&dex_file.annotation_directories
+	}
+}
+
+impl Write for AnnotationsDirectoryItem {
+	const ALIGNMENT: u32 = 4;
+
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.align(4)?;
+
+		let field_annotations = self.field_annotations.as_deref().unwrap_or_default();
+		let method_annotations = self.method_annotations.as_deref().unwrap_or_default();
+		let parameter_annotations = self.parameter_annotations.as_deref().unwrap_or_default();
+
+		writer.write_item(&self.class_annotations_off)?;
+		writer.u32(field_annotations.len() as u32)?;
+		writer.u32(method_annotations.len() as u32)?;
+		writer.u32(parameter_annotations.len() as u32)?;
+		writer.write_list(field_annotations)?;
+		writer.write_list(method_annotations)?;
+		writer.write_list(parameter_annotations)
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#field-annotation
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct FieldAnnotation {
@@ -713,7 +1175,7 @@ parse_struct_default!(MethodAnnotation {
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct ParameterAnnotation {
 	pub method_idx:      Idx<MethodIdItem, u32>,
-	pub annotations_off: Ref<AnnotationSetItem, u32>,
+	pub annotations_off: Ref<AnnotationSetRefList, u32>,
 }
 
 parse_struct_default!(ParameterAnnotation {
@@ -742,10 +1204,29 @@ impl Parse for AnnotationSetRefList {
 	}
 }
 
+impl DataItem for AnnotationSetRefList {
+	const TYPE_CODE: TypeCode = TypeCode::AnnotationSetRefList;
+
+	fn dex_section(dex_file: &DexFile) -> &[Self] {
+		&dex_file.annotation_set_ref_lists
+	}
+}
+
+impl Write for AnnotationSetRefList {
+	const ALIGNMENT: u32 = 4;
+
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.align(4)?;
+
+		writer.u32(self.list.len() as u32)?;
+		writer.write_list(&self.list)
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#set-ref-item
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct AnnotationSetRefItem {
-	pub annotations_off: Ref<AnnotationSetItem, u32>,
+	pub annotations_off: Ref<Option<AnnotationSetItem>, u32>,
 }
 
 parse_struct_default!(AnnotationSetRefItem { annotations_off });
@@ -771,14 +1252,38 @@ impl Parse for AnnotationSetItem {
 	}
 }
 
+impl DataItem for AnnotationSetItem {
+	const TYPE_CODE: TypeCode = TypeCode::AnnotationSetItem;
+
+	fn dex_section(dex_file: &DexFile) -> &[Self] {
+		&dex_file.annotation_sets
+	}
+}
+
+impl Write for AnnotationSetItem {
+	const ALIGNMENT: u32 = 4;
+
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.align(4)?;
+
+		writer.u32(self.entries.len() as u32)?;
+		writer.write_list(&self.entries)
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#off-item
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct AnnotationOffItem {
-	pub annotations_off: Ref<AnnotationSetItem, u32>,
+	pub annotations_off: Ref<AnnotationItem, u32>,
 }
 
 parse_struct_default!(AnnotationOffItem { annotations_off });
 
+/// https://source.android.com/devices/tech/dalvik/dex-format#visibility
+pub const VISIBILITY_BUILD: u8 = 0x00;
+pub const VISIBILITY_RUNTIME: u8 = 0x01;
+pub const VISIBILITY_SYSTEM: u8 = 0x02;
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#annotation-item
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct AnnotationItem {
@@ -791,6 +1296,14 @@ parse_struct_default!(AnnotationItem {
 	annotation,
 });
 
+impl DataItem for AnnotationItem {
+	const TYPE_CODE: TypeCode = TypeCode::AnnotationItem;
+
+	fn dex_section(dex_file: &DexFile) -> &[Self] {
+		&dex_file.annotations
+	}
+}
+
 // macro_rules! parsed_struct {
 //     (
 //         [$($attrs_pub:tt)*]
@@ -854,12 +1367,30 @@ pub struct EncodedArrayItem {
 
 parse_struct_default!(EncodedArrayItem { value });
 
+impl DataItem for EncodedArrayItem {
+	const TYPE_CODE: TypeCode = TypeCode::EncodedArrayItem;
+
+	fn dex_section(dex_file: &DexFile) -> &[Self] {
+		&dex_file.encoded_arrays
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#hiddenapi-class-data-item
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct HiddenapiClassDataItem {
-	pub size:    u32,
-	pub offsets: Vec<u8>,
-	pub flags:   Vec<Uleb128>,
+	/// The flags of the members of each class def, in the order of its class
+	/// data: static fields, instance fields, direct methods, virtual methods.
+	/// Empty for classes whose flags are all `0`.
+	pub flags: Vec<Vec<HiddenapiFlags>>,
+}
+
+impl HiddenapiClassDataItem {
+	/// Flags of the members of the class def at `class_def_idx`.
+	pub fn class_flags(&self, class_def_idx: usize) -> &[HiddenapiFlags] {
+		self.flags
+			.get(class_def_idx)
+			.map_or(&[], |flags| flags.as_slice())
+	}
 }
 
 impl Parse for HiddenapiClassDataItem {
@@ -868,16 +1399,82 @@ impl Parse for HiddenapiClassDataItem {
 		instrument(skip(parser), name = "<HiddenapiClassDataItem as Parse>::parse")
 	)]
 	fn parse<P: Parser>(parser: &mut P) -> Result<Self> {
+		let start = parser.get_offset();
 		let size = parser.u32()?;
-		// TODO
-		let offsets = vec![];
-		let flags = vec![];
 
-		Ok(HiddenapiClassDataItem {
-			size,
-			offsets,
-			flags,
-		})
+		// There is one offset per class def, but the number of class defs isn't
+		// stored here. The flags follow the offsets, so the first flags mark
+		// the end of them. Offsets are relative to the start of the item.
+		let mut offsets = Vec::new();
+		let mut end = size;
+		while 4 + 4 * (offsets.len() as u64) < u64::from(end) {
+			let offset = parser.u32()?;
+			if offset != 0 {
+				ensure!(
+					offset < size,
+					"hidden API flags at {:#x} are past the end of the section",
+					offset
+				);
+				end = end.min(offset);
+			}
+			offsets.push(offset);
+		}
+
+		// flags of a class run up to the ones of the next class
+		let mut bounds: Vec<u32> = offsets.iter().copied().filter(|&o| o != 0).collect();
+		bounds.push(size);
+		bounds.sort_unstable();
+		bounds.dedup();
+
+		let flags = offsets
+			.iter()
+			.map(|&offset| {
+				let mut flags = Vec::new();
+				if offset == 0 {
+					return Ok(flags);
+				}
+				let end = start + bounds[bounds.partition_point(|&b| b <= offset)];
+				parser.set_offset(start + offset)?;
+				while parser.get_offset() < end {
+					flags.push(HiddenapiFlags(*parser.uleb128()?));
+				}
+				Ok(flags)
+			})
+			.collect::<Result<_>>()?;
+		parser.set_offset(start + size)?;
+
+		Ok(HiddenapiClassDataItem { flags })
+	}
+}
+
+impl Write for HiddenapiClassDataItem {
+	const ALIGNMENT: u32 = 4;
+
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.align(4)?;
+
+		let mut offsets = Vec::with_capacity(self.flags.len());
+		let mut offset = 4 + 4 * self.flags.len() as u32;
+		for flags in self.flags.iter() {
+			if flags.is_empty() {
+				offsets.push(0);
+				continue;
+			}
+			offsets.push(offset);
+			offset += flags
+				.iter()
+				.map(|flag| Uleb128::from(flag.0).encoded_size())
+				.sum::<u32>();
+		}
+
+		writer.u32(offset)?;
+		for offset in offsets {
+			writer.u32(offset)?;
+		}
+		for flag in self.flags.iter().flatten() {
+			writer.uleb128(flag.0)?;
+		}
+		Ok(())
 	}
 }
 
@@ -961,9 +1558,7 @@ impl Parse for EncodedValue {
 					value_arg
 				);
 
-				let mut bytes = [0; 2];
-				parser.read_exact(&mut bytes[0..value_arg + 1])?;
-				EncodedValue::Short(i16::from_le_bytes(bytes))
+				EncodedValue::Short(read_signed(parser, value_arg + 1)? as i16)
 			}
 			// char
 			0x03 => {
@@ -985,9 +1580,7 @@ impl Parse for EncodedValue {
 					value_arg
 				);
 
-				let mut bytes = [0; 4];
-				parser.read_exact(&mut bytes[0..value_arg + 1])?;
-				EncodedValue::Int(i32::from_le_bytes(bytes))
+				EncodedValue::Int(read_signed(parser, value_arg + 1)? as i32)
 			}
 			// long
 			0x06 => {
@@ -997,9 +1590,7 @@ impl Parse for EncodedValue {
 					value_arg
 				);
 
-				let mut bytes = [0; 8];
-				parser.read_exact(&mut bytes[0..value_arg + 1])?;
-				EncodedValue::Long(i64::from_le_bytes(bytes))
+				EncodedValue::Long(read_signed(parser, value_arg + 1)?)
 			}
 			// float
 			0x10 => {
@@ -1009,9 +1600,8 @@ impl Parse for EncodedValue {
 					value_arg
 				);
 
-				let mut bytes = [0; 4];
-				parser.read_exact(&mut bytes[0..value_arg + 1])?;
-				EncodedValue::Float(f32::from_le_bytes(bytes))
+				let bits = read_right_zero_extended(parser, value_arg + 1)? >> 32;
+				EncodedValue::Float(f32::from_bits(bits as u32))
 			}
 			// double
 			0x11 => {
@@ -1021,9 +1611,10 @@ impl Parse for EncodedValue {
 					value_arg
 				);
 
-				let mut bytes = [0; 8];
-				parser.read_exact(&mut bytes[0..value_arg + 1])?;
-				EncodedValue::Double(f64::from_le_bytes(bytes))
+				EncodedValue::Double(f64::from_bits(read_right_zero_extended(
+					parser,
+					value_arg + 1,
+				)?))
 			}
 			// method type
 			0x15 => {
@@ -1150,6 +1741,87 @@ impl Parse for EncodedValue {
 	}
 }
 
+impl Write for EncodedValue {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		match self {
+			EncodedValue::Byte(v) => write_value(writer, 0x00, &[*v]),
+			EncodedValue::Short(v) => write_signed(writer, 0x02, *v as i64),
+			EncodedValue::Char(v) => write_unsigned(writer, 0x03, *v as u64),
+			EncodedValue::Int(v) => write_signed(writer, 0x04, *v as i64),
+			EncodedValue::Long(v) => write_signed(writer, 0x06, *v),
+			EncodedValue::Float(v) => {
+				write_right_zero_extended(writer, 0x10, (v.to_bits() as u64) << 32)
+			}
+			EncodedValue::Double(v) => write_right_zero_extended(writer, 0x11, v.to_bits()),
+			EncodedValue::MethodType(v) => write_unsigned(writer, 0x15, *v as u64),
+			EncodedValue::MethodHandle(v) => write_unsigned(writer, 0x16, *v as u64),
+			EncodedValue::String(v) => write_unsigned(writer, 0x17, *v as u64),
+			EncodedValue::Type(v) => write_unsigned(writer, 0x18, *v as u64),
+			EncodedValue::Field(v) => write_unsigned(writer, 0x19, *v as u64),
+			EncodedValue::Method(v) => write_unsigned(writer, 0x1a, *v as u64),
+			EncodedValue::Enum(v) => write_unsigned(writer, 0x1b, *v as u64),
+			EncodedValue::Array(v) => {
+				writer.u8(0x1c)?;
+				writer.write_item(v)
+			}
+			EncodedValue::Annotation(v) => {
+				writer.u8(0x1d)?;
+				writer.write_item(v)
+			}
+			EncodedValue::Null => writer.u8(0x1e),
+			EncodedValue::Boolean(v) => writer.u8(0x1f | (*v as u8) << 5),
+		}
+	}
+}
+
+/// Reads a `size` byte value and sign extends it to 64 bits.
+fn read_signed<P: Parser>(parser: &mut P, size: usize) -> Result<i64> {
+	let mut bytes = [0; 8];
+	parser.read_exact(&mut bytes[..size])?;
+	let shift = 64 - 8 * size as u32;
+	Ok(i64::from_le_bytes(bytes) << shift >> shift)
+}
+
+/// Reads a `size` byte value into the most significant bytes of a 64 bit
+/// value, floating point values are zero extended to the right.
+fn read_right_zero_extended<P: Parser>(parser: &mut P, size: usize) -> Result<u64> {
+	let mut bytes = [0; 8];
+	parser.read_exact(&mut bytes[8 - size..])?;
+	Ok(u64::from_le_bytes(bytes))
+}
+
+/// Writes the value header followed by `value`, which has to be 1 to 8 bytes
+/// long.
+fn write_value<W: Writer>(writer: &mut W, value_type: u8, value: &[u8]) -> Result<()> {
+	writer.u8(value_type | ((value.len() - 1) as u8) << 5)?;
+	writer.bytes(value)
+}
+
+/// Writes the fewest low bytes of `value` that sign extend back to it.
+fn write_signed<W: Writer>(writer: &mut W, value_type: u8, value: i64) -> Result<()> {
+	let bits = 65
+		- if value < 0 {
+			value.leading_ones()
+		} else {
+			value.leading_zeros()
+		};
+	let size = bits.div_ceil(8) as usize;
+	write_value(writer, value_type, &value.to_le_bytes()[..size])
+}
+
+/// Writes the fewest low bytes of `value` that zero extend back to it.
+fn write_unsigned<W: Writer>(writer: &mut W, value_type: u8, value: u64) -> Result<()> {
+	let size = (64 - value.leading_zeros()).div_ceil(8).max(1) as usize;
+	write_value(writer, value_type, &value.to_le_bytes()[..size])
+}
+
+/// Writes the fewest high bytes of `value` that zero extend back to it to
+/// the right.
+fn write_right_zero_extended<W: Writer>(writer: &mut W, value_type: u8, value: u64) -> Result<()> {
+	let size = (64 - value.trailing_zeros()).div_ceil(8).max(1) as usize;
+	write_value(writer, value_type, &value.to_le_bytes()[8 - size..])
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#encoded-array
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct EncodedArray {
@@ -1170,6 +1842,13 @@ impl Parse for EncodedArray {
 	}
 }
 
+impl Write for EncodedArray {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.uleb128(self.values.len() as u32)?;
+		writer.write_list(&self.values)
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#encoded-annotation
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct EncodedAnnotation {
@@ -1196,6 +1875,14 @@ impl Parse for EncodedAnnotation {
 	}
 }
 
+impl Write for EncodedAnnotation {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.write_item(&self.type_idx)?;
+		writer.uleb128(self.elements.len() as u32)?;
+		writer.write_list(&self.elements)
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#annotation-element
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
 pub struct AnnotationElement {
@@ -15,27 +15,34 @@ use std::convert::{TryFrom, TryInto};
 
 use eyre::{bail, eyre, Report, Result};
 
-use crate::dex::parser::{Parse, Parser};
+use crate::dex::{
+	parser::{Parse, Parser},
+	writer::{Write, Writer},
+};
 
+/// The sections of a file by their type.
+///
+/// Only the header and the map list are always there, sections without any
+/// items are usually left out of the map list.
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Map {
 	pub header_item:                MapItem,
-	pub string_id_item:             MapItem,
-	pub type_id_item:               MapItem,
-	pub proto_id_item:              MapItem,
-	pub field_id_item:              MapItem,
-	pub method_id_item:             MapItem,
-	pub class_def_item:             MapItem,
-	pub code_item:                  MapItem,
-	pub debug_info_item:            MapItem,
-	pub type_list:                  MapItem,
-	pub string_data_item:           MapItem,
-	pub annotation_item:            MapItem,
-	pub class_data_item:            MapItem,
-	pub encoded_array_item:         MapItem,
-	pub annotation_set_item:        MapItem,
-	pub annotation_set_ref_list:    MapItem,
-	pub annotations_directory_item: MapItem,
+	pub string_id_item:             Option<MapItem>,
+	pub type_id_item:               Option<MapItem>,
+	pub proto_id_item:              Option<MapItem>,
+	pub field_id_item:              Option<MapItem>,
+	pub method_id_item:             Option<MapItem>,
+	pub class_def_item:             Option<MapItem>,
+	pub code_item:                  Option<MapItem>,
+	pub debug_info_item:            Option<MapItem>,
+	pub type_list:                  Option<MapItem>,
+	pub string_data_item:           Option<MapItem>,
+	pub annotation_item:            Option<MapItem>,
+	pub class_data_item:            Option<MapItem>,
+	pub encoded_array_item:         Option<MapItem>,
+	pub annotation_set_item:        Option<MapItem>,
+	pub annotation_set_ref_list:    Option<MapItem>,
+	pub annotations_directory_item: Option<MapItem>,
 	pub map_list:                   MapItem,
 	pub call_site_id_item:          Option<MapItem>,
 	pub method_handle_item:         Option<MapItem>,
@@ -53,36 +60,37 @@ impl MapList {
 	pub fn map(&self) -> Result<Map> {
 		Ok(Map {
 			header_item:                self.item(TypeCode::HeaderItem)?,
-			string_id_item:             self.item(TypeCode::StringIdItem)?,
-			type_id_item:               self.item(TypeCode::TypeIdItem)?,
-			proto_id_item:              self.item(TypeCode::ProtoIdItem)?,
-			field_id_item:              self.item(TypeCode::FieldIdItem)?,
-			method_id_item:             self.item(TypeCode::MethodIdItem)?,
-			class_def_item:             self.item(TypeCode::ClassDefItem)?,
-			code_item:                  self.item(TypeCode::CodeItem)?,
-			debug_info_item:            self.item(TypeCode::DebugInfoItem)?,
-			type_list:                  self.item(TypeCode::TypeList)?,
-			string_data_item:           self.item(TypeCode::StringDataItem)?,
-			annotation_item:            self.item(TypeCode::AnnotationItem)?,
-			class_data_item:            self.item(TypeCode::ClassDataItem)?,
-			encoded_array_item:         self.item(TypeCode::EncodedArrayItem)?,
-			annotation_set_item:        self.item(TypeCode::AnnotationSetItem)?,
-			annotation_set_ref_list:    self.item(TypeCode::AnnotationSetRefList)?,
-			annotations_directory_item: self.item(TypeCode::AnnotationsDirectoryItem)?,
+			string_id_item:             self.find(TypeCode::StringIdItem),
+			type_id_item:               self.find(TypeCode::TypeIdItem),
+			proto_id_item:              self.find(TypeCode::ProtoIdItem),
+			field_id_item:              self.find(TypeCode::FieldIdItem),
+			method_id_item:             self.find(TypeCode::MethodIdItem),
+			class_def_item:             self.find(TypeCode::ClassDefItem),
+			code_item:                  self.find(TypeCode::CodeItem),
+			debug_info_item:            self.find(TypeCode::DebugInfoItem),
+			type_list:                  self.find(TypeCode::TypeList),
+			string_data_item:           self.find(TypeCode::StringDataItem),
+			annotation_item:            self.find(TypeCode::AnnotationItem),
+			class_data_item:            self.find(TypeCode::ClassDataItem),
+			encoded_array_item:         self.find(TypeCode::EncodedArrayItem),
+			annotation_set_item:        self.find(TypeCode::AnnotationSetItem),
+			annotation_set_ref_list:    self.find(TypeCode::AnnotationSetRefList),
+			annotations_directory_item: self.find(TypeCode::AnnotationsDirectoryItem),
 			map_list:                   self.item(TypeCode::MapList)?,
-			call_site_id_item:          self.item(TypeCode::CallSiteIdItem).ok(),
-			method_handle_item:         self.item(TypeCode::MethodHandleItem).ok(),
-			hiddenapi_class_data_item:  self.item(TypeCode::HiddenapiClassDataItem).ok(),
+			call_site_id_item:          self.find(TypeCode::CallSiteIdItem),
+			method_handle_item:         self.find(TypeCode::MethodHandleItem),
+			hiddenapi_class_data_item:  self.find(TypeCode::HiddenapiClassDataItem),
 		})
 	}
 
 	pub fn item(&self, typ: TypeCode) -> Result<MapItem> {
-		self.list
-			.iter()
-			.find(|i| i.item_type == typ)
-			.map(|i| *i)
+		self.find(typ)
 			.ok_or_else(|| eyre!("could not find item with code: {:?}", typ))
 	}
+
+	pub fn find(&self, typ: TypeCode) -> Option<MapItem> {
+		self.list.iter().find(|i| i.item_type == typ).copied()
+	}
 }
 
 impl Parse for MapList {
@@ -97,6 +105,17 @@ impl Parse for MapList {
 	}
 }
 
+impl Write for MapList {
+	const ALIGNMENT: u32 = 4;
+
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.align(4)?;
+
+		writer.u32(self.list.len() as u32)?;
+		writer.write_list(&self.list)
+	}
+}
+
 /// https://source.android.com/devices/tech/dalvik/dex-format#map-item
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct MapItem {
@@ -121,6 +140,15 @@ impl Parse for MapItem {
 	}
 }
 
+impl Write for MapItem {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.u16(self.item_type.into())?;
+		writer.u16(0)?; // unused
+		writer.u32(self.size)?;
+		writer.u32(self.offset)
+	}
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum TypeCode {
 	HeaderItem,
@@ -177,6 +205,34 @@ impl TryFrom<u16> for TypeCode {
 	}
 }
 
+impl From<TypeCode> for u16 {
+	fn from(value: TypeCode) -> u16 {
+		match value {
+			TypeCode::HeaderItem => 0x0000,
+			TypeCode::StringIdItem => 0x0001,
+			TypeCode::TypeIdItem => 0x0002,
+			TypeCode::ProtoIdItem => 0x0003,
+			TypeCode::FieldIdItem => 0x0004,
+			TypeCode::MethodIdItem => 0x0005,
+			TypeCode::ClassDefItem => 0x0006,
+			TypeCode::CallSiteIdItem => 0x0007,
+			TypeCode::MethodHandleItem => 0x0008,
+			TypeCode::MapList => 0x1000,
+			TypeCode::TypeList => 0x1001,
+			TypeCode::AnnotationSetRefList => 0x1002,
+			TypeCode::AnnotationSetItem => 0x1003,
+			TypeCode::ClassDataItem => 0x2000,
+			TypeCode::CodeItem => 0x2001,
+			TypeCode::StringDataItem => 0x2002,
+			TypeCode::DebugInfoItem => 0x2003,
+			TypeCode::AnnotationItem => 0x2004,
+			TypeCode::EncodedArrayItem => 0x2005,
+			TypeCode::AnnotationsDirectoryItem => 0x2006,
+			TypeCode::HiddenapiClassDataItem => 0xF000,
+		}
+	}
+}
+
 // #[derive(Debug)]
 // pub struct MapData {
 // 	pub item_type: u16,
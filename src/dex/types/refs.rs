@@ -1,11 +1,17 @@
-use std::{cmp::Ordering, convert::TryInto, fmt::Debug, ops::Deref};
+use std::{
+	cmp::Ordering,
+	convert::{TryFrom, TryInto},
+	fmt::Debug,
+	ops::Deref,
+};
 
 use eyre::{eyre, Result, WrapErr};
 
 use crate::dex::{
 	parser::{Parse, Parser},
 	resolver::{Resolve, ResolveFrom},
-	types::file::DexFile,
+	types::{file::DexFile, map::TypeCode},
+	writer::{Write, Writer},
 };
 
 pub struct Ref<T, N> {
@@ -41,7 +47,7 @@ impl<T, N> Eq for Ref<T, N> {}
 
 impl<T, N> PartialOrd for Ref<T, N> {
 	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-		self.offset.partial_cmp(&other.offset)
+		Some(self.cmp(other))
 	}
 }
 
@@ -87,11 +93,49 @@ where
 	}
 }
 
-// impl<T, N> ResolveFrom<Ref<T, N>> for T {
-// 	fn resolve<R: Read + Seek>(item: &Ref<T, N>, resolver: &Resolver<R>) -> Self {
-// 		resolver.dex_file.
-// 	}
-// }
+impl<T, N> Write for Ref<T, N>
+where
+	N: Write + From<u32>,
+{
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		let offset = writer
+			.relocate(self.offset)
+			.wrap_err("relocating offset for Ref")?;
+		N::from(offset).write(writer)
+	}
+}
+
+/// Items of the data sections, which are referenced by their offset instead
+/// of an index.
+pub trait DataItem
+where
+	Self: Sized,
+{
+	const TYPE_CODE: TypeCode;
+
+	fn dex_section(dex_file: &DexFile) -> &[Self];
+}
+
+impl<T: DataItem + Parse + Clone + 'static, N> ResolveFrom<Ref<T, N>> for T {
+	fn resolve_from(item: &Ref<T, N>, resolver: &impl Resolve) -> Result<Self> {
+		resolver.data_item(item.offset)
+	}
+}
+
+impl<T: DataItem + Parse + Clone + 'static, N> Ref<T, N> {
+	pub fn resolve(&self, resolver: &impl Resolve) -> Result<T> {
+		T::resolve_from(self, resolver)
+	}
+}
+
+impl<T: DataItem + Parse + Clone + 'static, N> Ref<Option<T>, N> {
+	pub fn resolve(&self, resolver: &impl Resolve) -> Result<Option<T>> {
+		if self.offset == 0 {
+			return Ok(None);
+		}
+		Ref::<T, N>::new(self.offset).resolve(resolver).map(Some)
+	}
+}
 
 pub struct Idx<T, N> {
 	idx:     usize,
@@ -126,7 +170,7 @@ impl<T, N> Eq for Idx<T, N> {}
 
 impl<T, N> PartialOrd for Idx<T, N> {
 	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-		self.idx.partial_cmp(&other.idx)
+		Some(self.cmp(other))
 	}
 }
 
@@ -172,6 +216,18 @@ where
 	}
 }
 
+impl<T, E, N> Write for Idx<T, N>
+where
+	E: std::error::Error + Send + Sync + 'static,
+	N: Write + TryFrom<usize, Error = E>,
+{
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		N::try_from(self.idx)
+			.wrap_err("converting idx for Idx")?
+			.write(writer)
+	}
+}
+
 pub trait IdItem
 where
 	Self: Sized,
@@ -185,9 +241,9 @@ impl<O: Clone, T: IdItem<Output = O>, N> ResolveFrom<Idx<T, N>> for O {
 	// type From = Idx<T, N>;
 
 	fn resolve_from(item: &Idx<T, N>, resolver: &impl Resolve) -> Result<Self> {
-		T::dex_section(&resolver.dex_file())
+		T::dex_section(resolver.dex_file())
 			.get(**item)
-			.map(|t| t.clone())
+			.cloned()
 			.ok_or_else(|| eyre!("index {:?} out of bounds", item))
 	}
 }
@@ -197,28 +253,18 @@ impl<O: Clone, T: IdItem<Output = O>, N> Idx<T, N> {
 		O::resolve_from(self, resolver)
 	}
 }
-//
-// const NO_INDEX: usize = 0xffffffff;
-
-// impl<O, T: IdItem<Output = O>> IdItem for Option<T> {
-// 	type Output = O;
-//
-// 	fn dex_section(dex_file: &DexFile) -> &[Self::Output] {
-// 		T::dex_section(dex_file)
-// 	}
-// }
-
-// impl<O: Clone, T: IdItem<Output = O>, N> ResolveFrom<Idx<Option<T>, N>> for Option<O> {
-// 	fn resolve_from<R: Resolve>(item: &Idx<Option<T>, N>, resolver: &R) -> Result<Self> {
-// 		item.resolve(resolver)
-// 	}
-// }
-//
-// impl<O: Clone, T: IdItem<Output = O>, N> Idx<Option<T>, N> {
-// 	pub(crate) fn resolve<R: Resolve>(&self, resolver: &R) -> Result<Option<O>> {
-// 		if self.idx == NO_INDEX {
-// 			return Ok(None);
-// 		}
-// 		Ok(Some(O::resolve_from(self, resolver)?))
-// 	}
-// }
+
+pub const NO_INDEX: usize = 0xffffffff;
+
+impl<O: Clone, T: IdItem<Output = O>, N> Idx<Option<T>, N> {
+	pub fn resolve(&self, resolver: &impl Resolve) -> Result<Option<O>> {
+		if self.idx == NO_INDEX {
+			return Ok(None);
+		}
+		T::dex_section(resolver.dex_file())
+			.get(self.idx)
+			.cloned()
+			.map(Some)
+			.ok_or_else(|| eyre!("index {:?} out of bounds", self))
+	}
+}
@@ -0,0 +1,249 @@
+use std::{fs::File, path::Path};
+
+use eyre::{ensure, Result, WrapErr};
+use memmap2::Mmap;
+
+use crate::dex::{
+	container::DEX_MAGIC,
+	parser::{Parse, Parser, SliceParser},
+	types::{
+		header::Header,
+		id::{
+			ClassDefItem,
+			FieldIdItem,
+			MethodIdItem,
+			ProtoIdItem,
+			StringDataRef,
+			StringIdItem,
+			TypeIdItem,
+		},
+	},
+};
+
+/// Sizes of the fixed size items of the id sections.
+const STRING_ID_SIZE: u32 = 4;
+const TYPE_ID_SIZE: u32 = 4;
+const PROTO_ID_SIZE: u32 = 12;
+const FIELD_ID_SIZE: u32 = 8;
+const METHOD_ID_SIZE: u32 = 8;
+const CLASS_DEF_SIZE: u32 = 32;
+
+/// A dex file whose id sections and strings are read in place instead of
+/// parsed up front.
+///
+/// Only the header is parsed when the view is created. Ids are decoded from
+/// the borrowed bytes when they are asked for and strings are borrowed as
+/// [`StringDataRef`]s that decode their MUTF-8 lazily, without allocating.
+/// Other items, like class data or code, are only reachable through
+/// [`DexView::parse_at`], which parses them into owned items like
+/// [`Resolver`] does. Nothing is cached, so this suits a single pass over the
+/// ids and strings of a large file better than [`Resolver`].
+///
+/// [`Resolver`]: crate::dex::resolver::Resolver
+#[derive(Debug, Clone)]
+pub struct DexView<'a> {
+	parser: SliceParser<'a>,
+	header: Header,
+}
+
+impl<'a> DexView<'a> {
+	pub fn new(data: &'a [u8]) -> Result<Self> {
+		let mut parser = SliceParser::new(data)?;
+		let header: Header = parser.parse().wrap_err("parsing header")?;
+
+		for (name, size, offset, item_size) in [
+			(
+				"string ids",
+				header.string_ids_size,
+				header.string_ids_off,
+				STRING_ID_SIZE,
+			),
+			(
+				"type ids",
+				header.type_ids_size,
+				header.type_ids_off,
+				TYPE_ID_SIZE,
+			),
+			(
+				"proto ids",
+				header.proto_ids_size,
+				header.proto_ids_off,
+				PROTO_ID_SIZE,
+			),
+			(
+				"field ids",
+				header.field_ids_size,
+				header.field_ids_off,
+				FIELD_ID_SIZE,
+			),
+			(
+				"method ids",
+				header.method_ids_size,
+				header.method_ids_off,
+				METHOD_ID_SIZE,
+			),
+			(
+				"class defs",
+				header.class_defs_size,
+				header.class_defs_off,
+				CLASS_DEF_SIZE,
+			),
+		] {
+			let end = offset as u64 + size as u64 * item_size as u64;
+			ensure!(
+				end <= data.len() as u64,
+				"{} {} at {:#x} are past the end of the file",
+				size,
+				name,
+				offset
+			);
+		}
+
+		Ok(DexView { parser, header })
+	}
+
+	pub fn header(&self) -> &Header {
+		&self.header
+	}
+
+	/// The whole file.
+	pub fn data(&self) -> &'a [u8] {
+		self.parser.data()
+	}
+
+	/// Parses an item at `offset`, for following the offsets in other items.
+	pub fn parse_at<T: Parse>(&self, offset: u32) -> Result<T> {
+		let mut parser = self.parser;
+		parser.set_offset(offset)?;
+		parser.parse()
+	}
+
+	fn id<T: Parse>(
+		&self,
+		name: &str,
+		size: u32,
+		offset: u32,
+		item_size: u32,
+		idx: usize,
+	) -> Result<T> {
+		ensure!(
+			idx < size as usize,
+			"{} index {} is out of bounds, there are {}",
+			name,
+			idx,
+			size
+		);
+		self.parse_at(offset + idx as u32 * item_size)
+			.wrap_err_with(|| format!("parsing {} {}", name, idx))
+	}
+
+	pub fn string(&self, idx: usize) -> Result<StringDataRef<'a>> {
+		let id: StringIdItem = self.id(
+			"string id",
+			self.header.string_ids_size,
+			self.header.string_ids_off,
+			STRING_ID_SIZE,
+			idx,
+		)?;
+		let mut parser = self.parser;
+		parser.set_offset(*id.string_data_off)?;
+		StringDataRef::parse(&mut parser).wrap_err_with(|| format!("parsing string {}", idx))
+	}
+
+	/// All strings in the order of their ids.
+	pub fn strings(&self) -> impl Iterator<Item = Result<StringDataRef<'a>>> + '_ {
+		(0..self.header.string_ids_size as usize).map(move |idx| self.string(idx))
+	}
+
+	pub fn type_id(&self, idx: usize) -> Result<TypeIdItem> {
+		self.id(
+			"type id",
+			self.header.type_ids_size,
+			self.header.type_ids_off,
+			TYPE_ID_SIZE,
+			idx,
+		)
+	}
+
+	/// The descriptor of a type, like `Lfoo/Bar;`.
+	pub fn type_descriptor(&self, idx: usize) -> Result<StringDataRef<'a>> {
+		self.string(*self.type_id(idx)?.descriptor_idx)
+	}
+
+	pub fn proto_id(&self, idx: usize) -> Result<ProtoIdItem> {
+		self.id(
+			"proto id",
+			self.header.proto_ids_size,
+			self.header.proto_ids_off,
+			PROTO_ID_SIZE,
+			idx,
+		)
+	}
+
+	pub fn field_id(&self, idx: usize) -> Result<FieldIdItem> {
+		self.id(
+			"field id",
+			self.header.field_ids_size,
+			self.header.field_ids_off,
+			FIELD_ID_SIZE,
+			idx,
+		)
+	}
+
+	pub fn method_id(&self, idx: usize) -> Result<MethodIdItem> {
+		self.id(
+			"method id",
+			self.header.method_ids_size,
+			self.header.method_ids_off,
+			METHOD_ID_SIZE,
+			idx,
+		)
+	}
+
+	pub fn class_def(&self, idx: usize) -> Result<ClassDefItem> {
+		self.id(
+			"class def",
+			self.header.class_defs_size,
+			self.header.class_defs_off,
+			CLASS_DEF_SIZE,
+			idx,
+		)
+	}
+
+	pub fn class_defs(&self) -> impl Iterator<Item = Result<ClassDefItem>> + '_ {
+		(0..self.header.class_defs_size as usize).map(move |idx| self.class_def(idx))
+	}
+}
+
+/// A dex file mapped into memory, so the operating system only reads the
+/// parts of it a [`DexView`] touches.
+#[derive(Debug)]
+pub struct MappedDex {
+	mmap: Mmap,
+}
+
+impl MappedDex {
+	pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		let file = File::open(path).wrap_err_with(|| format!("opening {}", path.display()))?;
+		// SAFETY: the mapping is only ever read. Like with any mapped file,
+		// another process truncating or changing the file while it is mapped
+		// isn't something that can be guarded against here.
+		let mmap =
+			unsafe { Mmap::map(&file) }.wrap_err_with(|| format!("mapping {}", path.display()))?;
+		Ok(MappedDex { mmap })
+	}
+
+	pub fn data(&self) -> &[u8] {
+		&self.mmap
+	}
+
+	/// If the file starts like a dex file, as opposed to an archive.
+	pub fn is_dex(&self) -> bool {
+		self.mmap.starts_with(DEX_MAGIC)
+	}
+
+	pub fn view(&self) -> Result<DexView<'_>> {
+		DexView::new(&self.mmap)
+	}
+}
@@ -0,0 +1,81 @@
+pub mod write;
+
+use std::{collections::HashMap, io};
+
+use eyre::{eyre, Result};
+pub use write::{Write, WriteThings};
+
+pub trait Writer: WriteThings + Sized {
+	fn get_offset(&self) -> u32;
+
+	/// Maps the offset an item was parsed from to the offset it is written
+	/// to, `0` always stays `0` as it marks a missing item.
+	fn relocate(&self, offset: u32) -> Result<u32>;
+
+	#[inline(always)]
+	fn align(&mut self, alignment: u32) -> Result<()> {
+		while !self.get_offset().is_multiple_of(alignment) {
+			self.u8(0)?;
+		}
+		Ok(())
+	}
+
+	#[inline(always)]
+	fn write_item<T: Write>(&mut self, item: &T) -> Result<()> {
+		item.write(self)
+	}
+
+	fn write_list<T: Write>(&mut self, items: &[T]) -> Result<()> {
+		for item in items {
+			self.write_item(item)?;
+		}
+		Ok(())
+	}
+}
+
+/// Writes items into a buffer that starts at offset `0` of the file.
+#[derive(Debug, Default)]
+pub struct FileWriter {
+	buffer:      Vec<u8>,
+	relocations: HashMap<u32, u32>,
+}
+
+impl FileWriter {
+	pub fn new(relocations: HashMap<u32, u32>) -> Self {
+		FileWriter {
+			buffer: Vec::new(),
+			relocations,
+		}
+	}
+
+	pub fn into_inner(self) -> Vec<u8> {
+		self.buffer
+	}
+}
+
+impl Writer for FileWriter {
+	fn get_offset(&self) -> u32 {
+		self.buffer.len() as u32
+	}
+
+	fn relocate(&self, offset: u32) -> Result<u32> {
+		if offset == 0 {
+			return Ok(0);
+		}
+		self.relocations
+			.get(&offset)
+			.copied()
+			.ok_or_else(|| eyre!("no item was parsed from offset {:#x}", offset))
+	}
+}
+
+impl io::Write for FileWriter {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.buffer.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
@@ -0,0 +1,111 @@
+use std::convert::TryFrom;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use eyre::{Result, WrapErr};
+
+use crate::dex::{
+	parser::parse::{Sleb128, Uleb128},
+	writer::Writer,
+};
+
+pub trait Write {
+	/// Alignment every instance of the item starts at, sections of the item
+	/// are aligned the same way.
+	const ALIGNMENT: u32 = 1;
+
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()>;
+}
+
+macro_rules! write_simple {
+	($($ty:tt),*) => {
+		$(
+			impl Write for $ty {
+				fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+					writer.$ty(*self)
+				}
+			}
+		)*
+	};
+}
+
+write_simple!(u8, u16, i16, u32, i32);
+
+impl Write for Uleb128 {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.uleb128(**self)
+	}
+}
+
+impl Write for Sleb128 {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<()> {
+		writer.sleb128(**self)
+	}
+}
+
+impl TryFrom<usize> for Uleb128 {
+	type Error = std::num::TryFromIntError;
+
+	fn try_from(value: usize) -> core::result::Result<Self, Self::Error> {
+		u32::try_from(value).map(Uleb128::from)
+	}
+}
+
+pub trait WriteThings: WriteBytesExt {
+	fn u8(&mut self, val: u8) -> Result<()> {
+		self.write_u8(val).wrap_err("writing u8")
+	}
+
+	/// Packs two nibbles into one byte, the reverse of
+	/// [`ReadThings::split_u8`](crate::dex::parser::ReadThings::split_u8).
+	fn join_u8(&mut self, low: u8, high: u8) -> Result<()> {
+		self.u8((low & 0xf) | (high << 4))
+	}
+
+	fn u16(&mut self, val: u16) -> Result<()> {
+		self.write_u16::<LittleEndian>(val).wrap_err("writing u16")
+	}
+
+	fn i16(&mut self, val: i16) -> Result<()> {
+		self.write_i16::<LittleEndian>(val).wrap_err("writing i16")
+	}
+
+	fn u32(&mut self, val: u32) -> Result<()> {
+		self.write_u32::<LittleEndian>(val).wrap_err("writing u32")
+	}
+
+	fn i32(&mut self, val: i32) -> Result<()> {
+		self.write_i32::<LittleEndian>(val).wrap_err("writing i32")
+	}
+
+	fn u64(&mut self, val: u64) -> Result<()> {
+		self.write_u64::<LittleEndian>(val).wrap_err("writing u64")
+	}
+
+	fn i64(&mut self, val: i64) -> Result<()> {
+		self.write_i64::<LittleEndian>(val).wrap_err("writing i64")
+	}
+
+	fn f32(&mut self, val: f32) -> Result<()> {
+		self.write_f32::<LittleEndian>(val).wrap_err("writing f32")
+	}
+
+	fn f64(&mut self, val: f64) -> Result<()> {
+		self.write_f64::<LittleEndian>(val).wrap_err("writing f64")
+	}
+
+	fn uleb128(&mut self, val: u32) -> Result<()> {
+		leb128::write::unsigned(self, val as u64).wrap_err("writing uleb128")?;
+		Ok(())
+	}
+
+	fn sleb128(&mut self, val: i32) -> Result<()> {
+		leb128::write::signed(self, val as i64).wrap_err("writing sleb128")?;
+		Ok(())
+	}
+
+	fn bytes(&mut self, val: &[u8]) -> Result<()> {
+		self.write_all(val).wrap_err("writing bytes")
+	}
+}
+
+impl<T: WriteBytesExt> WriteThings for T {}
@@ -0,0 +1,47 @@
+//! Parsing and resolution of Dalvik executable (`.dex`) files.
+//!
+//! The raw on-disk structures live in [`dex::types`], they are read through
+//! the [`Parser`]/[`Parse`] traits and written back through the
+//! [`Writer`]/[`Write`] traits. [`Resolver`] turns them into the higher level
+//! [`Class`]/[`Method`]/[`Field`] model and [`dex::asm`] contains the bytecode
+//! definitions. [`Disassembler`] writes resolved classes out as smali and
+//! [`Assembler`] reads smali back in. [`DexContainer`] opens the dex files
+//! of an APK together and [`DexBuilder`] lays assembled classes out as a new
+//! file. [`DexView`] reads the ids and strings of large files in place, e.g.
+//! through a [`MappedDex`].
+#![feature(associated_type_defaults)]
+
+#[macro_use]
+extern crate tracing;
+
+#[macro_use]
+pub mod dex;
+
+pub use dex::{
+	asm::{format::Format, instruction::Instruction, opcode::Opcode},
+	builder::DexBuilder,
+	container::{DexContainer, DexResolver},
+	parser::{FileParser, Parse, ParseError, Parser, SliceParser},
+	resolver::{Resolve, ResolveFrom, ResolveInto, Resolver},
+	smali::{
+		assembler::{AssembleError, Assembler, Pool},
+		disassembler::Disassembler,
+	},
+	types::{
+		access::{AccessContext, AccessFlags, HiddenapiFlags, HiddenapiRestriction},
+		debug::DebugInfo,
+		descriptor::TypeDescriptor,
+		file::DexFile,
+		header::Header,
+		id::StringDataRef,
+		Class,
+		ClassData,
+		Field,
+		FieldId,
+		Method,
+		MethodId,
+		Proto,
+	},
+	view::{DexView, MappedDex},
+	writer::{FileWriter, Write, Writer},
+};
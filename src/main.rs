@@ -1,50 +1,271 @@
-// [`crate::dex::types::refs::Parse`]
-#![feature(associated_type_defaults)]
-#![feature(once_cell)]
-// FIXME
-#![allow(dead_code)]
-
 #[macro_use]
 extern crate tracing;
 
-use std::{fs, io::Cursor};
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
 
-use color_eyre::{eyre::WrapErr, Report, Result};
-use dex::resolver::Resolver;
+use clap::{ArgAction, Parser as _, Subcommand};
+use color_eyre::{
+	eyre::{bail, eyre, WrapErr},
+	Report,
+	Result,
+};
+use smali::{
+	dex::smali::disassembler::escape_string,
+	Assembler,
+	DexBuilder,
+	DexContainer,
+	DexResolver,
+	Disassembler,
+	Header,
+	MappedDex,
+	Parser,
+	SliceParser,
+	TypeDescriptor,
+};
 
-use crate::dex::parser::FileParser;
+/// Reads, disassembles and assembles Dalvik executables.
+#[derive(clap::Parser)]
+#[command(version)]
+struct Cli {
+	/// Log more, `-vv` for debug and `-vvv` for trace output. Without any of
+	/// `-v`/`-q` the `RUST_LOG` environment variable is used.
+	#[arg(short, long, action = ArgAction::Count, global = true)]
+	verbose: u8,
+	/// Only log errors.
+	#[arg(short, long, global = true, conflicts_with = "verbose")]
+	quiet:   bool,
 
-#[macro_use]
-mod dex;
+	#[command(subcommand)]
+	command: Command,
+}
+
+/// Inputs are plain dex files or APK/AAB/JAR archives with the dex files
+/// inside.
+#[derive(Subcommand)]
+enum Command {
+	/// Print the header and the map list of each dex file.
+	Info { input: PathBuf },
+	/// List the classes, in the order the runtime finds them.
+	Classes { input: PathBuf },
+	/// Print the strings of each dex file, one per line.
+	Strings { input: PathBuf },
+	/// Write every class as a `.smali` file.
+	Disassemble {
+		input:  PathBuf,
+		/// Directory to put the `.smali` files in.
+		#[arg(short, long, default_value = "out")]
+		output: PathBuf,
+	},
+	/// Assemble all `.smali` files in a directory into one dex file.
+	Assemble {
+		sources: PathBuf,
+		#[arg(short, long, default_value = "out.dex")]
+		output:  PathBuf,
+	},
+	/// Print a single class as smali.
+	Dump {
+		input: PathBuf,
+		/// Descriptor of the class, like `Lfoo/Bar;`.
+		#[arg(short, long)]
+		class: TypeDescriptor,
+	},
+	/// Check the checksum, signature and sizes in the header of a dex file.
+	Verify { input: PathBuf },
+}
 
 #[cfg_attr(feature = "trace", instrument)]
 fn main() -> Result<(), Report> {
+	let cli = Cli::parse();
 	color_eyre::install()?;
-	install_tracing();
+	install_tracing(match (cli.quiet, cli.verbose) {
+		(true, _) => Some("error"),
+		(false, 0) => None,
+		(false, 1) => Some("info"),
+		(false, 2) => Some("debug"),
+		(false, _) => Some("trace"),
+	});
+
+	match cli.command {
+		Command::Info { input } => info(&input),
+		Command::Classes { input } => classes(&input),
+		Command::Strings { input } => strings(&input),
+		Command::Disassemble { input, output } => disassemble(&input, &output),
+		Command::Assemble { sources, output } => assemble(&sources, &output),
+		Command::Dump { input, class } => dump(&input, &class),
+		Command::Verify { input } => verify(&input),
+	}
+}
+
+fn open(input: &Path) -> Result<DexContainer> {
+	DexContainer::open(input).wrap_err_with(|| format!("opening {}", input.display()))
+}
+
+fn info(input: &Path) -> Result<()> {
+	let container = open(input)?;
+	for (name, resolver) in container.dex_files() {
+		let dex_file = &resolver.dex_file;
+		let header = &dex_file.header;
+		println!("{}:", name);
+		println!("  version:    {:03}", header.format_version);
+		println!("  byte order: {:?}", header.endian_tag);
+		println!("  file size:  {}", header.file_size);
+		println!("  checksum:   {:#010x}", header.checksum);
+		println!("  strings:    {}", header.string_ids_size);
+		println!("  types:      {}", header.type_ids_size);
+		println!("  protos:     {}", header.proto_ids_size);
+		println!("  fields:     {}", header.field_ids_size);
+		println!("  methods:    {}", header.method_ids_size);
+		println!("  classes:    {}", header.class_defs_size);
+		println!("  map:");
+		for item in dex_file.map_list.list.iter() {
+			println!(
+				"    {:#010x} {:>7} {:?}",
+				item.offset, item.size, item.item_type
+			);
+		}
+	}
+	Ok(())
+}
 
-	let buf = fs::read("./classes2.dex").wrap_err("reading file")?;
-	let reader = Cursor::new(buf);
-	// let reader = fs::File::open("./classes2.dex").wrap_err("opening file")?;
+fn classes(input: &Path) -> Result<()> {
+	let container = open(input)?;
+	for class in container.iter_classes() {
+		let (_, class) = class?;
+		println!("{}", class.name);
+	}
+	Ok(())
+}
 
-	let parser = FileParser::new(reader).wrap_err("creating parser")?;
-	let mut resolver = Resolver::new(parser).wrap_err("creating resolver")?;
-	// info!("map: {:#?}", resolver.dex_file.map_list);
+fn strings(input: &Path) -> Result<()> {
+	// plain dex files are read in place, nothing but the strings is decoded
+	let mapped = MappedDex::open(input)?;
+	if mapped.is_dex() {
+		for string in mapped.view()?.strings() {
+			println!("{}", escape_string(&string?.decode()?));
+		}
+		return Ok(());
+	}
 
-	info!("resolving classes");
-	let classes = resolver.classes()?;
-	info!("class 0: {:#x?}", &classes[0]);
-	// dbg!(resolver.dex_file.string_ids.len());
+	let container = open(input)?;
+	for (_, resolver) in container.dex_files() {
+		for string in resolver.dex_file.string_data.iter() {
+			println!("{}", escape_string(&string.string));
+		}
+	}
+	Ok(())
+}
+
+fn disassemble(input: &Path, output: &Path) -> Result<()> {
+	let container = open(input)?;
+	let mut count = 0;
+	for class in container.iter_classes() {
+		let (resolver, class) = class?;
+		let smali = Disassembler::new(resolver)
+			.class(&class)
+			.wrap_err_with(|| format!("disassembling {}", class.name))?;
+
+		let path = output.join(Disassembler::<DexResolver>::file_name(
+			&class.name.to_string(),
+		));
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)
+				.wrap_err_with(|| format!("creating {}", parent.display()))?;
+		}
+		fs::write(&path, smali).wrap_err_with(|| format!("writing {}", path.display()))?;
+		count += 1;
+	}
+	info!("wrote {} classes to {}", count, output.display());
+	Ok(())
+}
+
+fn assemble(sources: &Path, output: &Path) -> Result<()> {
+	let mut files = Vec::new();
+	smali_files(sources, &mut files)?;
+	if files.is_empty() {
+		bail!("{} doesn't contain any .smali files", sources.display());
+	}
+	// the order of the classes shouldn't depend on the file system
+	files.sort();
+
+	let mut assembler = Assembler::new();
+	let classes = files
+		.iter()
+		.map(|path| {
+			let source =
+				fs::read_to_string(path).wrap_err_with(|| format!("reading {}", path.display()))?;
+			assembler
+				.class(&source)
+				.wrap_err_with(|| format!("assembling {}", path.display()))
+		})
+		.collect::<Result<Vec<_>>>()?;
+
+	let bytes = DexBuilder::new(assembler.pool())
+		.build(&classes)
+		.and_then(|dex_file| dex_file.to_bytes())
+		.wrap_err("building the dex file")?;
+	fs::write(output, bytes).wrap_err_with(|| format!("writing {}", output.display()))?;
+	info!(
+		"assembled {} classes into {}",
+		classes.len(),
+		output.display()
+	);
+	Ok(())
+}
+
+/// Collects the `.smali` files under `path`, or `path` itself if it is one.
+fn smali_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+	if !path.is_dir() {
+		files.push(path.to_path_buf());
+		return Ok(());
+	}
+	for entry in fs::read_dir(path).wrap_err_with(|| format!("reading {}", path.display()))? {
+		let path = entry?.path();
+		if path.is_dir() {
+			smali_files(&path, files)?;
+		} else if path.extension().is_some_and(|ext| ext == "smali") {
+			files.push(path);
+		}
+	}
+	Ok(())
+}
+
+fn dump(input: &Path, name: &TypeDescriptor) -> Result<()> {
+	let container = open(input)?;
+	let (resolver, class) = container
+		.class(name)?
+		.ok_or_else(|| eyre!("{} doesn't define {}", input.display(), name))?;
+	print!("{}", Disassembler::new(resolver).class(&class)?);
+	Ok(())
+}
 
+/// Checks the checksum, signature and sizes in the header of a file, only
+/// the header is parsed so a corrupted file can still be checked.
+fn verify(path: &Path) -> Result<()> {
+	let mapped = MappedDex::open(path)?;
+	let header: Header = SliceParser::new(mapped.data())
+		.and_then(|mut parser| parser.parse())
+		.wrap_err("parsing header")?;
+	header
+		.verify_integrity(mapped.data())
+		.wrap_err_with(|| format!("verifying {}", path.display()))?;
+	println!("{}: ok", path.display());
 	Ok(())
 }
 
-fn install_tracing() {
+/// Logs go to stderr so they don't end up in the output of the commands,
+/// `level` overrides the `RUST_LOG` environment variable.
+fn install_tracing(level: Option<&str>) {
 	use tracing_error::ErrorLayer;
 	use tracing_subscriber::{prelude::*, EnvFilter};
 
-	let filter = EnvFilter::try_from_default_env()
-		.or_else(|_| EnvFilter::try_new("info"))
-		.unwrap();
+	let filter = match level {
+		Some(level) => EnvFilter::try_new(level),
+		None => EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("warn")),
+	}
+	.unwrap();
 
 	let format = tracing_subscriber::fmt::format()
 		.without_time()
@@ -54,6 +275,7 @@ fn install_tracing() {
 	tracing_subscriber::fmt()
 		.with_env_filter(filter)
 		.event_format(format)
+		.with_writer(std::io::stderr)
 		.finish()
 		.with(ErrorLayer::default())
 		.init();
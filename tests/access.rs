@@ -0,0 +1,187 @@
+mod common;
+
+use eyre::Result;
+use smali::{AccessContext, AccessFlags, Assembler, DexBuilder, Disassembler};
+
+use crate::common::{fixture, resolve, LITTLE_ENDIAN};
+
+fn flags(bits: u32) -> AccessFlags {
+	AccessFlags::from_bits(bits).unwrap()
+}
+
+/// The bits that mean different things on fields and methods are named after
+/// what they are set on.
+#[test]
+fn shared_bits_depend_on_the_context() {
+	let cases = [
+		(0x20, AccessContext::Class, ""),
+		(0x20, AccessContext::Method, "synchronized"),
+		(0x40, AccessContext::Field, "volatile"),
+		(0x40, AccessContext::Method, "bridge"),
+		(0x80, AccessContext::Field, "transient"),
+		(0x80, AccessContext::Method, "varargs"),
+	];
+	for (bits, context, smali) in cases {
+		assert_eq!(
+			flags(bits).smali(context),
+			smali,
+			"{:#x} {:?}",
+			bits,
+			context
+		);
+	}
+}
+
+#[test]
+fn smali_and_java_render_differently() {
+	let cases = [
+		// public super
+		(0x21, AccessContext::Class, "public", "public"),
+		(
+			0x601,
+			AccessContext::Class,
+			"public interface abstract",
+			"public",
+		),
+		(
+			0x2601,
+			AccessContext::Class,
+			"public interface abstract annotation",
+			"public",
+		),
+		(
+			0x4019,
+			AccessContext::Field,
+			"public static final enum",
+			"public static final",
+		),
+		(
+			0x1012,
+			AccessContext::Field,
+			"private final synthetic",
+			"private final",
+		),
+		(
+			0x10001,
+			AccessContext::Method,
+			"public constructor",
+			"public",
+		),
+		(
+			0x20001,
+			AccessContext::Method,
+			"public declared-synchronized",
+			"public synchronized",
+		),
+		(
+			0x129,
+			AccessContext::Method,
+			"public static synchronized native",
+			"public static synchronized native",
+		),
+		(
+			0x481,
+			AccessContext::Method,
+			"public varargs abstract",
+			"public abstract",
+		),
+		(
+			0x811,
+			AccessContext::Class,
+			"public final strictfp",
+			"public final strictfp",
+		),
+	];
+	for (bits, context, smali, java) in cases {
+		assert_eq!(
+			flags(bits).smali(context),
+			smali,
+			"{:#x} {:?}",
+			bits,
+			context
+		);
+		assert_eq!(flags(bits).java(context), java, "{:#x} {:?}", bits, context);
+	}
+}
+
+#[test]
+fn names_parse_in_their_context_only() {
+	assert_eq!(
+		AccessFlags::from_name("bridge", AccessContext::Method),
+		Some(AccessFlags::BRIDGE)
+	);
+	assert_eq!(AccessFlags::from_name("bridge", AccessContext::Field), None);
+	assert_eq!(
+		AccessFlags::from_name("volatile", AccessContext::Field),
+		Some(AccessFlags::VOLATILE)
+	);
+	assert_eq!(
+		AccessFlags::from_name("volatile", AccessContext::Method),
+		None
+	);
+	assert_eq!(
+		AccessFlags::from_name("constructor", AccessContext::Class),
+		None
+	);
+	assert_eq!(
+		AccessFlags::from_name("interface", AccessContext::Method),
+		None
+	);
+	assert_eq!(AccessFlags::from_name("super", AccessContext::Class), None);
+	assert_eq!(
+		AccessFlags::from_name("strictfp", AccessContext::Class),
+		Some(AccessFlags::STRICT)
+	);
+	assert_eq!(
+		AccessFlags::from_name("strictfp", AccessContext::Field),
+		None
+	);
+
+	// everything that is written parses back to the same bits
+	for (bits, context) in [
+		(0x2e11, AccessContext::Class),
+		(0x50df, AccessContext::Field),
+		(0x31dff, AccessContext::Method),
+	] {
+		let parsed = flags(bits)
+			.names(context)
+			.map(|name| AccessFlags::from_name(name, context).unwrap())
+			.fold(AccessFlags::empty(), |flags, flag| flags | flag);
+		assert_eq!(parsed, flags(bits), "{:?}", context);
+	}
+}
+
+/// Obfuscators set bits no flag is defined for, they survive resolving and
+/// building.
+#[test]
+fn unknown_bits_are_kept() -> Result<()> {
+	const UNKNOWN: u32 = 0x8000_0000;
+
+	let mut resolver = fixture(LITTLE_ENDIAN)?;
+	resolver.dex_file.class_defs[0].access_flags |= UNKNOWN;
+	let flags = *resolver.dex_file.class_data[0].direct_methods[0].access_flags | UNKNOWN;
+	resolver.dex_file.class_data[0].direct_methods[0].access_flags = flags.into();
+
+	let class = resolver.iter_classes().next().unwrap()?;
+	assert_eq!(class.access_flags.unknown_bits(), UNKNOWN);
+	let method = &class.class_data.as_ref().unwrap().direct_methods[0];
+	assert_eq!(method.access_flags.bits(), flags);
+	// smali has no name for them
+	assert_eq!(
+		class.access_flags.smali(AccessContext::Class),
+		"public final"
+	);
+
+	let mut assembler = Assembler::new();
+	let mut assembled = assembler.class(&Disassembler::new(&resolver).class(&class)?)?;
+	assembled.access_flags = class.access_flags;
+	let bytes = DexBuilder::new(assembler.pool())
+		.build(&[assembled])?
+		.to_bytes()?;
+	let built = resolve(bytes)?;
+	assert_eq!(
+		built.dex_file.class_defs[0].access_flags,
+		resolver.dex_file.class_defs[0].access_flags
+	);
+	Ok(())
+}
@@ -0,0 +1,389 @@
+mod common;
+
+use eyre::Result;
+use smali::{
+	dex::types::id::{AnnotationItem, CodeItem, VISIBILITY_RUNTIME},
+	AssembleError,
+	Assembler,
+	Disassembler,
+	Instruction::*,
+	Opcode,
+	Pool,
+};
+
+use crate::common::{fixture, LITTLE_ENDIAN};
+
+#[test]
+fn annotations_are_assembled() -> Result<()> {
+	let resolver = fixture(LITTLE_ENDIAN)?;
+	let class = resolver.iter_classes().next().unwrap()?;
+	let source = Disassembler::new(&resolver).class(&class)?;
+
+	let mut assembler = Assembler::new();
+	let assembled = assembler.class(&source)?;
+	let pool = assembler.pool();
+	let annotations = assembled.annotations.unwrap();
+	let expected = class.annotations.unwrap();
+
+	let types = |items: &[AnnotationItem]| -> String {
+		items
+			.iter()
+			.map(|item| pool.types[*item.annotation.type_idx as usize].to_string())
+			.collect::<Vec<_>>()
+			.join(" ")
+	};
+	assert_eq!(
+		types(&annotations.class),
+		"Ldalvik/annotation/Signature; Lfoo/Marker;"
+	);
+	assert_eq!(annotations.class[1].visibility, VISIBILITY_RUNTIME);
+	assert_eq!(annotations.class[1].annotation.elements.len(), 4);
+
+	let fields = annotations
+		.fields
+		.iter()
+		.map(|(field, items)| (field, types(items)))
+		.collect::<Vec<_>>();
+	assert_eq!(
+		fields,
+		[(&expected.fields[0].0, "Lfoo/Marker;".to_string())]
+	);
+	let methods = annotations
+		.methods
+		.iter()
+		.map(|(method, items)| format!("{} {}", method.name, types(items)))
+		.collect::<Vec<_>>();
+	assert_eq!(
+		methods,
+		[
+			"nat Ljava/lang/Deprecated;",
+			"run Ldalvik/annotation/Throws;"
+		]
+	);
+
+	let (method, parameters) = &annotations.parameters[0];
+	assert_eq!(method.name, "sw");
+	let parameters = parameters
+		.iter()
+		.map(|items| types(items))
+		.collect::<Vec<_>>();
+	assert_eq!(parameters, ["", "Lfoo/Marker;", ""]);
+	Ok(())
+}
+
+/// Annotations after a `.param` without `.end param` belong to the method.
+#[test]
+fn parameter_annotations_need_end_param() -> Result<()> {
+	let source = "
+.class public Lfoo/Baz;
+.super Ljava/lang/Object;
+
+.method public static native a(I)V
+    .param p0
+    .annotation runtime Lfoo/Marker;
+    .end annotation
+.end method
+
+.method public static native b(I)V
+    .param p0
+    .annotation runtime Lfoo/Marker;
+    .end annotation
+    .end param
+.end method
+";
+	let annotations = Assembler::new().class(source)?.annotations.unwrap();
+	assert_eq!(annotations.methods.len(), 1);
+	assert_eq!(annotations.methods[0].0.name, "a");
+	assert_eq!(annotations.parameters.len(), 1);
+	assert_eq!(annotations.parameters[0].0.name, "b");
+	assert_eq!(annotations.parameters[0].1[0].len(), 1);
+	Ok(())
+}
+
+#[test]
+fn instance_fields_have_no_initial_value() {
+	let source = "
+.class public Lfoo/Baz;
+.super Ljava/lang/Object;
+
+.field public static a:I = 0x1
+.field public b:I = 0x1
+";
+	let error = Assembler::new().class(source).unwrap_err();
+	assert!(error.to_string().contains("line 6"), "{}", error);
+	assert!(error.to_string().contains("initial value"), "{}", error);
+}
+
+/// Assembles `body` as the code of the instance method `a(J)V`, its first
+/// line is line 6.
+fn assemble(body: &str) -> Result<(CodeItem, Pool)> {
+	let source = format!(
+		"
+.class public Lfoo/Baz;
+.super Ljava/lang/Object;
+
+.method public a(J)V
+{}
+.end method
+",
+		body
+	);
+	let mut assembler = Assembler::new();
+	let class = assembler.class(&source)?;
+	let method = class.class_data.unwrap().virtual_methods.remove(0);
+	Ok((method.code.unwrap(), assembler.into_pool()))
+}
+
+fn method_idx(pool: &Pool, name: &str) -> u16 {
+	pool.methods
+		.iter()
+		.position(|method| method.name == name)
+		.unwrap() as u16
+}
+
+/// Parameters are the last registers of the frame, `this` being `p0`.
+#[test]
+fn registers_lists_and_ranges_are_encoded() -> Result<()> {
+	let (code, pool) = assemble(
+		"
+    .registers 5
+    move-object v0, p0
+    move-wide/from16 v0, p1
+    invoke-virtual {p0, p1, p2}, Lfoo/Baz;->a(J)V
+    invoke-virtual/range {p0 .. p2}, Lfoo/Baz;->a(J)V
+    invoke-static/range {v0, v1}, Lfoo/Baz;->b(J)V
+    invoke-static {}, Lfoo/Baz;->c()V
+    return-void
+",
+	)?;
+	let (a, b, c) = (
+		method_idx(&pool, "a"),
+		method_idx(&pool, "b"),
+		method_idx(&pool, "c"),
+	);
+	assert_eq!(
+		(code.registers_size, code.ins_size, code.outs_size),
+		(5, 3, 3)
+	);
+	assert_eq!(
+		code.insns,
+		[
+			Instruction12x(Opcode::MoveObject, (0, 2)),
+			Instruction22x(Opcode::MoveWideFrom16, (0, 3)),
+			Instruction35c(Opcode::InvokeVirtual, (3, 0, a, 0, 4, 3, 2)),
+			Instruction3rc(Opcode::InvokeVirtualRange, (3, a, 2)),
+			Instruction3rc(Opcode::InvokeStaticRange, (2, b, 0)),
+			Instruction35c(Opcode::InvokeStatic, (0, 0, c, 0, 0, 0, 0)),
+			Instruction10x(Opcode::ReturnVoid),
+		]
+	);
+	Ok(())
+}
+
+#[test]
+fn literals_are_encoded() -> Result<()> {
+	let (code, _) = assemble(
+		"
+    .locals 2
+    const/4 v0, -0x1
+    const/16 v0, 'a'
+    const v0, 0xffffffff
+    const/high16 v0, 0x7f000000
+    const-wide/16 v0, -0x2
+    const-wide v0, 0x123456789aL
+    const-wide/high16 v0, -0x8000000000000000L
+    add-int/lit8 v0, v1, -0x80
+    return-void
+",
+	)?;
+	assert_eq!(code.registers_size, 5);
+	assert_eq!(
+		code.insns[..8],
+		[
+			Instruction11n(Opcode::Const4, (0, 0xf)),
+			Instruction21s(Opcode::Const16, (0, 'a' as u16)),
+			Instruction31i(Opcode::CONST, (0, 0xffff_ffff)),
+			Instruction21ih(Opcode::ConstHigh16, (0, 0x7f00_0000)),
+			Instruction21s(Opcode::ConstWide16, (0, 0xfffe)),
+			Instruction51l(Opcode::ConstWide, (0, 0x12_3456_789a)),
+			Instruction21lh(Opcode::ConstWideHigh16, (0, i64::MIN)),
+			Instruction22b(Opcode::AddIntLit8, (0, 1, 0x80)),
+		]
+	);
+	Ok(())
+}
+
+/// Branch offsets count code units from the branching instruction.
+#[test]
+fn labels_are_relative_offsets() -> Result<()> {
+	let (code, _) = assemble(
+		"
+    .registers 3
+    :start
+    goto :end
+    if-eqz v0, :start
+    goto/16 :start
+    goto/32 :end
+    :end
+    return-void
+",
+	)?;
+	assert_eq!(
+		code.insns,
+		[
+			Instruction10t(Opcode::GOTO, (8,)),
+			Instruction21t(Opcode::IfEqz, (0, -1)),
+			Instruction20t(Opcode::Goto16, (-3,)),
+			Instruction30t(Opcode::Goto32, (3,)),
+			Instruction10x(Opcode::ReturnVoid),
+		]
+	);
+	Ok(())
+}
+
+/// Overlapping `.catch` ranges become disjoint try blocks, each with every
+/// handler covering it.
+#[test]
+fn overlapping_catches_are_split() -> Result<()> {
+	let (code, pool) = assemble(
+		"
+    .registers 3
+    :a
+    nop
+    :b
+    nop
+    :c
+    nop
+    :d
+    return-void
+    :h1
+    return-void
+    :h2
+    return-void
+    .catch Ljava/lang/Exception; {:a .. :c} :h1
+    .catchall {:b .. :d} :h2
+",
+	)?;
+	let exception = pool
+		.types
+		.iter()
+		.position(|typ| typ.to_string() == "Ljava/lang/Exception;")
+		.unwrap() as u32;
+
+	// handlers are referred to by their offset in the list
+	let handlers = code.handlers.unwrap();
+	let mut offset = handlers.size.encoded_size();
+	let mut offsets = Vec::new();
+	for handler in handlers.list.iter() {
+		offsets.push((offset as u16, handler));
+		offset += handler.encoded_size();
+	}
+	let tries = code
+		.tries
+		.unwrap()
+		.iter()
+		.map(|item| {
+			let (_, handler) = offsets
+				.iter()
+				.find(|(offset, _)| *offset == item.handler_off)
+				.unwrap();
+			(
+				item.start_addr,
+				item.insn_count,
+				handler
+					.handlers
+					.iter()
+					.map(|pair| (*pair.type_idx, *pair.addr))
+					.collect::<Vec<_>>(),
+				handler.catch_all_addr.map(|addr| *addr),
+			)
+		})
+		.collect::<Vec<_>>();
+	assert_eq!(
+		tries,
+		[
+			(0, 1, vec![(exception, 4)], None),
+			(1, 1, vec![(exception, 4)], Some(5)),
+			(2, 1, vec![], Some(5)),
+		]
+	);
+	assert_eq!(code.tries_size, 3);
+	Ok(())
+}
+
+#[test]
+fn out_of_range_operands_are_rejected() {
+	let cases = [
+		("move v0, v3", "v3 is out of range"),
+		("move v0, p3", "p3 is out of range"),
+		("const/4 v0, 0x8", "literal 8 doesn't fit into 4 bits"),
+		(
+			"const/16 v0, 0x8000",
+			"literal 32768 doesn't fit into 16 bits",
+		),
+		(
+			"add-int/lit8 v0, v0, 0x80",
+			"literal 128 doesn't fit into 8 bits",
+		),
+		(
+			"invoke-static {v0, v0, v0, v0, v0, v0}, Lfoo/Baz;->b()V",
+			"at most 5 registers",
+		),
+		("goto :nowhere", "undefined label :nowhere"),
+	];
+	for (insn, expected) in cases {
+		let error = assemble(&format!("    .registers 3\n    {}\n    return-void", insn))
+			.unwrap_err()
+			.to_string();
+		assert!(error.starts_with("line 7: "), "{}: {}", insn, error);
+		assert!(error.contains(expected), "{}: {}", insn, error);
+	}
+
+	let error = assemble("    .registers 17\n    move v16, v0\n    return-void")
+		.unwrap_err()
+		.to_string();
+	assert!(error.contains("v16 doesn't fit into 4 bits"), "{}", error);
+}
+
+/// Method handles and call sites have no pool entries yet, the smali that
+/// refers to them fails with the feature it needs.
+#[test]
+fn method_handles_and_call_sites_are_unsupported() {
+	let cases = [
+		(
+			"const-method-handle v0, invoke-static@Lfoo/Baz;->a()V",
+			7,
+			"method handle references",
+		),
+		("invoke-custom {}, call_site_0", 7, "call site references"),
+		(
+			".annotation runtime Lfoo/Marker;
+        value = invoke-static@Lfoo/Baz;->a()V
+    .end annotation",
+			8,
+			"method handle values",
+		),
+	];
+	for (code, expected_line, expected_feature) in cases {
+		let source = format!(
+			"
+.class public Lfoo/Baz;
+.super Ljava/lang/Object;
+
+.method public static a()V
+    .registers 1
+    {}
+    return-void
+.end method
+",
+			code
+		);
+		let error = Assembler::new().class(&source).unwrap_err();
+		match error.downcast_ref::<AssembleError>() {
+			Some(AssembleError::Unsupported { line, feature, .. }) => {
+				assert_eq!((*line, *feature), (expected_line, expected_feature))
+			}
+			_ => panic!("{}", error),
+		}
+	}
+}
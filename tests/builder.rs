@@ -0,0 +1,247 @@
+mod common;
+
+use eyre::Result;
+use smali::{
+	dex::types::{
+		debug::{Local, Position},
+		id::HiddenapiClassDataItem,
+		map::TypeCode,
+	},
+	Assembler,
+	DebugInfo,
+	DexBuilder,
+	Disassembler,
+	HiddenapiFlags,
+	HiddenapiRestriction,
+};
+
+use crate::common::{fixture, resolve, LITTLE_ENDIAN};
+
+/// Built files leave out the sections they don't need and still have to parse
+/// to the same classes, annotations included.
+#[test]
+fn built_file_round_trips() -> Result<()> {
+	let resolver = fixture(LITTLE_ENDIAN)?;
+	let sources = resolver
+		.iter_classes()
+		.map(|class| Disassembler::new(&resolver).class(&class?))
+		.collect::<Result<Vec<_>>>()?;
+
+	let mut assembler = Assembler::new();
+	let classes = sources
+		.iter()
+		.map(|source| assembler.class(source))
+		.collect::<Result<Vec<_>>>()?;
+	let bytes = DexBuilder::new(assembler.pool())
+		.build(&classes)?
+		.to_bytes()?;
+
+	let built = resolve(bytes)?;
+	for class in built.iter_classes() {
+		let class = class?;
+		let class_data = class.class_data.unwrap();
+		for field in class_data
+			.static_fields
+			.iter()
+			.chain(class_data.instance_fields.iter())
+		{
+			assert_eq!(field.id.class, class.name);
+		}
+	}
+	for typ in [
+		TypeCode::AnnotationItem,
+		TypeCode::AnnotationSetItem,
+		TypeCode::AnnotationSetRefList,
+		TypeCode::AnnotationsDirectoryItem,
+	] {
+		assert_eq!(
+			built.dex_file.map_list.find(typ).map(|item| item.size),
+			resolver.dex_file.map_list.find(typ).map(|item| item.size),
+			"{:?}",
+			typ
+		);
+	}
+	let map = &built.dex_file.map_list.list;
+	assert!(map.iter().all(|item| item.size != 0));
+	assert!(map.windows(2).all(|pair| pair[0].offset < pair[1].offset));
+	let rebuilt = built
+		.iter_classes()
+		.map(|class| Disassembler::new(&built).class(&class?))
+		.collect::<Result<Vec<_>>>()?;
+	assert_eq!(rebuilt, sources);
+	Ok(())
+}
+
+/// Members without flags next to ones with flags end up with `0`, the
+/// whitelist.
+#[test]
+fn hiddenapi_flags_round_trip() -> Result<()> {
+	let resolver = fixture(LITTLE_ENDIAN)?;
+	let mut assembler = Assembler::new();
+	let mut classes = resolver
+		.iter_classes()
+		.map(|class| assembler.class(&Disassembler::new(&resolver).class(&class?)?))
+		.collect::<Result<Vec<_>>>()?;
+	assert!(classes[0].class_data.as_ref().unwrap().direct_methods[0]
+		.hiddenapi_flags
+		.is_none());
+
+	let blacklisted = HiddenapiFlags(0x2 | 0x8);
+	let class_data = classes[0].class_data.as_mut().unwrap();
+	class_data.direct_methods[0].hiddenapi_flags = Some(blacklisted);
+	let method = class_data.direct_methods[0].id.clone();
+
+	let bytes = DexBuilder::new(assembler.pool())
+		.build(&classes)?
+		.to_bytes()?;
+	let built = resolve(bytes.clone())?;
+	assert!(built.dex_file.hiddenapi_class_data.is_some());
+	assert_eq!(built.dex_file.to_bytes()?, bytes);
+
+	let class_data = built.iter_classes().next().unwrap()?.class_data.unwrap();
+	for resolved in class_data.direct_methods.iter() {
+		let flags = resolved.hiddenapi_flags.unwrap();
+		if resolved.id == method {
+			assert_eq!(flags, blacklisted);
+			assert_eq!(flags.restriction(), HiddenapiRestriction::Blacklist);
+			assert!(flags.is_core_platform_api());
+		} else {
+			assert_eq!(flags.restriction(), HiddenapiRestriction::Whitelist);
+		}
+	}
+	assert!(class_data
+		.static_fields
+		.iter()
+		.all(|field| field.hiddenapi_flags == Some(HiddenapiFlags::default())));
+	Ok(())
+}
+
+/// The flags belong to the class def they are stored for, even if another
+/// class def defines the same class.
+#[test]
+fn hiddenapi_flags_follow_the_class_def() -> Result<()> {
+	let mut resolver = fixture(LITTLE_ENDIAN)?;
+	let class_def = resolver.dex_file.class_defs[0].clone();
+	resolver.dex_file.class_defs.push(class_def);
+
+	let blacklisted = HiddenapiFlags(0x2);
+	let members = resolver.dex_file.class_data[0].static_fields.len()
+		+ resolver.dex_file.class_data[0].instance_fields.len()
+		+ resolver.dex_file.class_data[0].direct_methods.len()
+		+ resolver.dex_file.class_data[0].virtual_methods.len();
+	resolver.dex_file.hiddenapi_class_data = Some(HiddenapiClassDataItem {
+		flags: vec![Vec::new(), vec![blacklisted; members]],
+	});
+
+	let classes = resolver.iter_classes().collect::<Result<Vec<_>>>()?;
+	let flags = |idx: usize| {
+		let class_data = classes[idx].class_data.as_ref().unwrap();
+		class_data
+			.static_fields
+			.iter()
+			.chain(class_data.instance_fields.iter())
+			.map(|field| field.hiddenapi_flags.unwrap())
+			.chain(
+				class_data
+					.direct_methods
+					.iter()
+					.chain(class_data.virtual_methods.iter())
+					.map(|method| method.hiddenapi_flags.unwrap()),
+			)
+			.collect::<Vec<_>>()
+	};
+	assert_eq!(flags(0), vec![HiddenapiFlags::default(); members]);
+	assert_eq!(flags(1), vec![blacklisted; members]);
+	Ok(())
+}
+
+/// The state machine bytecode written for a method's debug info has to run
+/// back to the same state changes, including line and address jumps too big
+/// for a special opcode.
+#[test]
+fn debug_info_round_trips() -> Result<()> {
+	let source = "
+.class public Lfoo/Baz;
+.super Ljava/lang/Object;
+
+.method public static f(I)V
+    .registers 3
+    return-void
+.end method
+";
+	let mut assembler = Assembler::new();
+	let mut class = assembler.class(source)?;
+
+	let local = |register, name: &str, typ: &str, start, end| -> Result<Local> {
+		Ok(Local {
+			register,
+			name: Some(name.to_string()),
+			typ: Some(typ.parse()?),
+			signature: None,
+			start,
+			end,
+			restarted: false,
+		})
+	};
+	let x = local(0, "x", "I", Some(1), Some(5))?;
+	let expected = DebugInfo {
+		line_start:      100,
+		parameter_names: vec![Some("a".to_string())],
+		positions:       vec![
+			Position {
+				address: 0,
+				line:    100,
+			},
+			// a line delta below the special opcode range
+			Position {
+				address: 3,
+				line:    90,
+			},
+			// an address delta too big for a special opcode
+			Position {
+				address: 40,
+				line:    91,
+			},
+			Position {
+				address: 40,
+				line:    200,
+			},
+		],
+		locals:          vec![
+			x.clone(),
+			Local {
+				signature: Some("TT;".to_string()),
+				..local(1, "s", "Ljava/lang/String;", Some(2), None)?
+			},
+			// the parameter is live from the start and only ends
+			Local {
+				register:  2,
+				name:      None,
+				typ:       None,
+				signature: None,
+				start:     None,
+				end:       Some(6),
+				restarted: false,
+			},
+			Local {
+				start: Some(7),
+				end: None,
+				restarted: true,
+				..x
+			},
+		],
+		prologue_ends:   vec![1],
+		epilogue_begins: vec![8],
+		source_files:    vec![(4, Some("Other.java".to_string())), (9, None)],
+	};
+	class.class_data.as_mut().unwrap().direct_methods[0].debug_info = Some(expected.clone());
+
+	let bytes = DexBuilder::new(assembler.pool())
+		.build(&[class])?
+		.to_bytes()?;
+	let built = resolve(bytes)?;
+	let class = built.iter_classes().next().unwrap()?;
+	let method = &class.class_data.unwrap().direct_methods[0];
+	assert_eq!(method.debug_info.as_ref(), Some(&expected));
+	Ok(())
+}
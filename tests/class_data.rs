@@ -0,0 +1,178 @@
+mod common;
+
+use eyre::Result;
+use smali::{
+	dex::types::id::{CodeItem, EncodedField, EncodedMethod},
+	Assembler,
+	DexBuilder,
+	Disassembler,
+	Field,
+	FieldId,
+	Method,
+	MethodId,
+	ResolveFrom,
+};
+
+use crate::common::{fixture, resolve, DexResolver, LITTLE_ENDIAN};
+
+/// Adds up the deltas of a member list, the first one is an index itself.
+fn indices(diffs: impl Iterator<Item = usize>) -> Vec<usize> {
+	diffs
+		.scan(0, |idx, diff| {
+			*idx += diff;
+			Some(*idx)
+		})
+		.collect()
+}
+
+fn field_ids(resolver: &DexResolver, fields: &[EncodedField]) -> Result<Vec<FieldId>> {
+	indices(fields.iter().map(|field| *field.field_idx_diff as usize))
+		.into_iter()
+		.map(|idx| FieldId::resolve_from(&resolver.dex_file.field_ids[idx], resolver))
+		.collect()
+}
+
+fn method_ids(resolver: &DexResolver, methods: &[EncodedMethod]) -> Result<Vec<MethodId>> {
+	indices(
+		methods
+			.iter()
+			.map(|method| *method.method_idx_diff as usize),
+	)
+	.into_iter()
+	.map(|idx| MethodId::resolve_from(&resolver.dex_file.method_ids[idx], resolver))
+	.collect()
+}
+
+/// Every member resolves to the id its accumulated index points at, not the
+/// one its delta alone does.
+fn check_members(resolver: &DexResolver) -> Result<()> {
+	let item = &resolver.dex_file.class_data[0];
+	let class = resolver.iter_classes().next().unwrap()?;
+	let class_data = class.class_data.unwrap();
+
+	let ids = |fields: &[Field]| -> Vec<FieldId> {
+		fields.iter().map(|field| field.id.clone()).collect()
+	};
+	assert_eq!(
+		ids(&class_data.static_fields),
+		field_ids(resolver, &item.static_fields)?
+	);
+	assert_eq!(
+		ids(&class_data.instance_fields),
+		field_ids(resolver, &item.instance_fields)?
+	);
+
+	let ids = |methods: &[Method]| -> Vec<MethodId> {
+		methods.iter().map(|method| method.id.clone()).collect()
+	};
+	assert_eq!(
+		ids(&class_data.direct_methods),
+		method_ids(resolver, &item.direct_methods)?
+	);
+	assert_eq!(
+		ids(&class_data.virtual_methods),
+		method_ids(resolver, &item.virtual_methods)?
+	);
+	Ok(())
+}
+
+#[test]
+fn member_indices_accumulate() -> Result<()> {
+	let resolver = fixture(LITTLE_ENDIAN)?;
+	check_members(&resolver)?;
+
+	let class_data = resolver.iter_classes().next().unwrap()?.class_data.unwrap();
+	let names = |methods: &[Method]| -> Vec<String> {
+		methods
+			.iter()
+			.map(|method| method.id.name.clone())
+			.collect()
+	};
+	assert_eq!(names(&class_data.direct_methods), ["<init>", "main", "nat"]);
+	assert_eq!(names(&class_data.virtual_methods), ["run", "sw"]);
+	let names = class_data
+		.instance_fields
+		.iter()
+		.map(|field| field.id.name.as_str())
+		.collect::<Vec<_>>();
+	assert_eq!(names, ["count", "name"]);
+
+	// the later members really are stored as deltas
+	let item = &resolver.dex_file.class_data[0];
+	for methods in [&item.direct_methods, &item.virtual_methods] {
+		let diffs = methods
+			.iter()
+			.map(|method| *method.method_idx_diff as usize)
+			.collect::<Vec<_>>();
+		assert_ne!(indices(diffs.iter().copied()), diffs);
+	}
+	Ok(())
+}
+
+#[test]
+fn built_members_are_written_as_deltas() -> Result<()> {
+	let resolver = fixture(LITTLE_ENDIAN)?;
+	let mut assembler = Assembler::new();
+	let classes = resolver
+		.iter_classes()
+		.map(|class| assembler.class(&Disassembler::new(&resolver).class(&class?)?))
+		.collect::<Result<Vec<_>>>()?;
+	let bytes = DexBuilder::new(assembler.pool())
+		.build(&classes)?
+		.to_bytes()?;
+	check_members(&resolve(bytes)?)
+}
+
+#[test]
+fn methods_carry_their_code() -> Result<()> {
+	let resolver = fixture(LITTLE_ENDIAN)?;
+	let item = &resolver.dex_file.class_data[0];
+	let class_data = resolver.iter_classes().next().unwrap()?.class_data.unwrap();
+
+	let methods = class_data
+		.direct_methods
+		.iter()
+		.chain(class_data.virtual_methods.iter());
+	let encoded = item
+		.direct_methods
+		.iter()
+		.chain(item.virtual_methods.iter());
+	let mut codes = Vec::new();
+	for (method, encoded) in methods.zip(encoded) {
+		let expected = resolver.dex_file.data_item::<CodeItem>(*encoded.code_off);
+		assert_eq!(method.code.as_ref(), expected, "{}", method.id.name);
+		assert_eq!(
+			method.code.is_none(),
+			method.id.name == "nat",
+			"{}",
+			method.id.name
+		);
+		codes.extend(method.code.as_ref());
+	}
+	// every method has its own code
+	for (idx, code) in codes.iter().enumerate() {
+		assert!(!codes[idx + 1..].contains(code));
+	}
+
+	let code = |name: &str| {
+		class_data
+			.direct_methods
+			.iter()
+			.chain(class_data.virtual_methods.iter())
+			.find(|method| method.id.name == name)
+			.and_then(|method| method.code.clone())
+			.unwrap()
+	};
+	let sw = code("sw");
+	// this, int, the two halves of the long and the string
+	assert_eq!((sw.registers_size, sw.ins_size), (7, 5));
+	let init = code("<init>");
+	assert_eq!(
+		(init.registers_size, init.ins_size, init.outs_size),
+		(1, 1, 1)
+	);
+	let run = code("run");
+	assert_eq!(run.tries_size, 1);
+	assert!(run.handlers.is_some());
+	Ok(())
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use std::{fs, path::Path, process::Command};
+
+use eyre::{ensure, Result};
+
+use crate::common::{BIG_ENDIAN, LITTLE_ENDIAN};
+
+/// Runs the binary and returns what it printed.
+fn smali(args: &[&str]) -> Result<String> {
+	let output = Command::new(env!("CARGO_BIN_EXE_smali"))
+		.args(args)
+		.output()?;
+	ensure!(
+		output.status.success(),
+		"smali {:?} failed: {}",
+		args,
+		String::from_utf8_lossy(&output.stderr)
+	);
+	Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn info_prints_the_header_and_map() -> Result<()> {
+	let info = smali(&["info", LITTLE_ENDIAN])?;
+	assert!(info.contains("version:    035"), "{}", info);
+	assert!(info.contains("classes:    1"), "{}", info);
+	assert!(info.contains("1 AnnotationsDirectoryItem"), "{}", info);
+	assert!(info.contains("1 MapList"), "{}", info);
+	Ok(())
+}
+
+#[test]
+fn classes_and_strings_are_listed() -> Result<()> {
+	for path in [LITTLE_ENDIAN, BIG_ENDIAN] {
+		assert_eq!(smali(&["classes", path])?, "Lfoo/Bar;\n");
+		let strings = smali(&["strings", path])?;
+		assert_eq!(strings.lines().count(), 43);
+		assert!(strings.lines().any(|s| s == "Lfoo/Bar;"), "{}", strings);
+	}
+	Ok(())
+}
+
+#[test]
+fn verify_accepts_the_fixtures() -> Result<()> {
+	for path in [LITTLE_ENDIAN, BIG_ENDIAN] {
+		assert_eq!(smali(&["verify", path])?, format!("{}: ok\n", path));
+	}
+	Ok(())
+}
+
+#[test]
+fn disassembled_classes_assemble_again() -> Result<()> {
+	let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cli");
+	let _ = fs::remove_dir_all(&dir);
+	let sources = dir.join("smali");
+	let output = dir.join("out.dex");
+
+	smali(&[
+		"disassemble",
+		"-o",
+		sources.to_str().unwrap(),
+		LITTLE_ENDIAN,
+	])?;
+	let source = fs::read_to_string(sources.join("foo/Bar.smali"))?;
+	assert_eq!(smali(&["dump", "-c", "Lfoo/Bar;", LITTLE_ENDIAN])?, source);
+
+	smali(&[
+		"assemble",
+		"-o",
+		output.to_str().unwrap(),
+		sources.to_str().unwrap(),
+	])?;
+	let output = output.to_str().unwrap();
+	assert_eq!(smali(&["verify", output])?, format!("{}: ok\n", output));
+	assert_eq!(smali(&["dump", "-c", "Lfoo/Bar;", output])?, source);
+	Ok(())
+}
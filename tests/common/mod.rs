@@ -0,0 +1,22 @@
+//! Fixtures shared by the integration tests, not every test uses all of them.
+#![allow(dead_code)]
+
+use std::{fs, io::Cursor};
+
+use eyre::Result;
+pub use smali::DexResolver;
+use smali::{FileParser, Resolver};
+
+/// The same classes in either byte order.
+pub const LITTLE_ENDIAN: &str = "tests/fixtures/little_endian.dex";
+pub const BIG_ENDIAN: &str = "tests/fixtures/big_endian.dex";
+
+/// Parses and resolves a whole dex file.
+pub fn resolve(bytes: Vec<u8>) -> Result<DexResolver> {
+	Resolver::new(FileParser::new(Cursor::new(bytes))?)
+}
+
+/// Parses and resolves one of the fixtures.
+pub fn fixture(path: &str) -> Result<DexResolver> {
+	resolve(fs::read(path)?)
+}
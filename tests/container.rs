@@ -0,0 +1,88 @@
+mod common;
+
+use std::{
+	fs,
+	io::{Cursor, Write},
+};
+
+use eyre::Result;
+use smali::{DexContainer, TypeDescriptor};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::common::{BIG_ENDIAN, LITTLE_ENDIAN};
+
+fn zip(entries: Vec<(&str, Vec<u8>)>) -> Result<Vec<u8>> {
+	let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+	let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+	for (name, contents) in entries {
+		zip.start_file(name, options)?;
+		zip.write_all(&contents)?;
+	}
+	Ok(zip.finish()?.into_inner())
+}
+
+/// An archive with entries out of order and files that aren't loaded, one of
+/// them a dex file outside of the root.
+fn archive() -> Result<Vec<u8>> {
+	zip(vec![
+		("AndroidManifest.xml", b"<manifest/>".to_vec()),
+		("classes2.dex", fs::read(BIG_ENDIAN)?),
+		("assets/plugin/classes.dex", fs::read(LITTLE_ENDIAN)?),
+		("classes.dex", fs::read(LITTLE_ENDIAN)?),
+		("assets/classes3.dex.txt", Vec::new()),
+	])
+}
+
+#[test]
+fn dex_files_are_in_load_order() -> Result<()> {
+	let container = DexContainer::from_zip(Cursor::new(archive()?))?;
+	let names: Vec<&str> = container.dex_files().map(|(name, _)| name).collect();
+	assert_eq!(names, ["classes.dex", "classes2.dex"]);
+	Ok(())
+}
+
+/// App bundles keep the dex files of the base module in `base/dex/`.
+#[test]
+fn bundles_load_the_base_module() -> Result<()> {
+	let bundle = zip(vec![
+		("base/manifest/AndroidManifest.xml", b"<manifest/>".to_vec()),
+		("base/dex/classes2.dex", fs::read(BIG_ENDIAN)?),
+		("base/dex/classes.dex", fs::read(LITTLE_ENDIAN)?),
+		("base/assets/classes.dex", fs::read(LITTLE_ENDIAN)?),
+		("feature/dex/classes.dex", fs::read(LITTLE_ENDIAN)?),
+	])?;
+	let container = DexContainer::from_zip(Cursor::new(bundle))?;
+	let names: Vec<&str> = container.dex_files().map(|(name, _)| name).collect();
+	assert_eq!(names, ["base/dex/classes.dex", "base/dex/classes2.dex"]);
+	Ok(())
+}
+
+#[test]
+fn nested_dex_files_are_not_loaded() -> Result<()> {
+	let archive = zip(vec![(
+		"assets/plugin/classes.dex",
+		fs::read(LITTLE_ENDIAN)?,
+	)])?;
+	let error = DexContainer::from_zip(Cursor::new(archive)).err().unwrap();
+	assert!(error.to_string().contains("any dex files"), "{}", error);
+	Ok(())
+}
+
+#[test]
+fn first_definition_of_a_class_wins() -> Result<()> {
+	let container = DexContainer::from_zip(Cursor::new(archive()?))?;
+	let (_, classes2) = container.dex_files().nth(1).unwrap();
+
+	// both files define the same classes, only the ones of classes.dex count
+	let classes = container.iter_classes().collect::<Result<Vec<_>>>()?;
+	assert_eq!(classes.len(), classes2.dex_file.class_defs.len());
+	for (resolver, class) in classes {
+		assert!(!std::ptr::eq(resolver, classes2));
+		let (found, _) = container.class(&class.name)?.unwrap();
+		assert!(std::ptr::eq(found, resolver));
+	}
+
+	let missing = TypeDescriptor::Object("does/not/Exist".to_string());
+	assert!(container.class(&missing)?.is_none());
+	Ok(())
+}
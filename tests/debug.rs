@@ -0,0 +1,182 @@
+mod common;
+
+use eyre::Result;
+use smali::{
+	dex::types::{
+		debug::{Local, Position},
+		id::DebugInfoItem,
+	},
+	DebugInfo,
+	Resolve,
+	ResolveFrom,
+};
+
+use crate::common::{fixture, LITTLE_ENDIAN};
+
+const DBG_END_SEQUENCE: u8 = 0x00;
+const DBG_ADVANCE_PC: u8 = 0x01;
+const DBG_ADVANCE_LINE: u8 = 0x02;
+const DBG_START_LOCAL: u8 = 0x03;
+const DBG_END_LOCAL: u8 = 0x05;
+const DBG_RESTART_LOCAL: u8 = 0x06;
+
+/// Runs the state machine over `bytecode`, with the strings and types of the
+/// fixture to refer to.
+fn run(line_start: u32, bytecode: &[u8]) -> Result<DebugInfo> {
+	let resolver = fixture(LITTLE_ENDIAN)?;
+	let item = DebugInfoItem {
+		line_start:      line_start.into(),
+		parameters_size: 0.into(),
+		parameter_names: Vec::new(),
+		bytecode:        bytecode.to_vec(),
+	};
+	DebugInfo::resolve_from(&item, &resolver)
+}
+
+/// Indices of a string and a type of the fixture, plus one like the state
+/// machine stores them.
+fn indices(name: &str, typ: &str) -> Result<(u8, u8)> {
+	let resolver = fixture(LITTLE_ENDIAN)?;
+	let strings = (0..resolver.dex_file.string_data.len())
+		.map(|idx| resolver.string(idx))
+		.collect::<Result<Vec<_>>>()?;
+	let string = |s: &str| strings.iter().position(|string| string == s).unwrap();
+	let type_idx = resolver
+		.dex_file
+		.type_ids
+		.iter()
+		.position(|typ_id| *typ_id.descriptor_idx == string(typ))
+		.unwrap();
+	Ok((string(name) as u8 + 1, type_idx as u8 + 1))
+}
+
+#[test]
+fn lines_follow_special_opcodes_and_advances() -> Result<()> {
+	let info = run(
+		10,
+		&[
+			// line + 1
+			0x0a + 5,
+			DBG_ADVANCE_LINE,
+			0x7d, // -3
+			// address + 2, line + 0
+			0x0a + 4 + 2 * 15,
+			DBG_ADVANCE_PC,
+			0xac,
+			0x02, // 300
+			// line - 4, the lowest a special opcode goes
+			0x0a,
+			DBG_END_SEQUENCE,
+		],
+	)?;
+	assert_eq!(info.line_start, 10);
+	assert_eq!(
+		info.positions,
+		[
+			Position {
+				address: 0,
+				line:    11,
+			},
+			Position {
+				address: 2,
+				line:    8,
+			},
+			Position {
+				address: 302,
+				line:    4,
+			},
+		]
+	);
+	Ok(())
+}
+
+#[test]
+fn locals_end_and_restart() -> Result<()> {
+	let (name, typ) = indices("x", "I")?;
+	let info = run(
+		1,
+		&[
+			DBG_START_LOCAL,
+			0,
+			name,
+			typ,
+			DBG_ADVANCE_PC,
+			2,
+			DBG_END_LOCAL,
+			0,
+			DBG_ADVANCE_PC,
+			1,
+			DBG_RESTART_LOCAL,
+			0,
+			// a parameter isn't started before it ends
+			DBG_END_LOCAL,
+			1,
+			DBG_END_SEQUENCE,
+		],
+	)?;
+
+	let x = Local {
+		register:  0,
+		name:      Some("x".to_string()),
+		typ:       Some("I".parse()?),
+		signature: None,
+		start:     Some(0),
+		end:       Some(2),
+		restarted: false,
+	};
+	assert_eq!(
+		info.locals,
+		[
+			x.clone(),
+			Local {
+				start: Some(3),
+				end: None,
+				restarted: true,
+				..x
+			},
+			Local {
+				register:  1,
+				name:      None,
+				typ:       None,
+				signature: None,
+				start:     None,
+				end:       Some(3),
+				restarted: false,
+			},
+		]
+	);
+	Ok(())
+}
+
+#[test]
+fn string_out_of_bounds_is_an_error() -> Result<()> {
+	let (_, typ) = indices("x", "I")?;
+	// 0xffff
+	let error = run(
+		1,
+		&[DBG_START_LOCAL, 0, 0xff, 0xff, 0x03, typ, DBG_END_SEQUENCE],
+	)
+	.unwrap_err();
+	assert!(error.to_string().contains("out of bounds"), "{}", error);
+	Ok(())
+}
+
+#[test]
+fn address_overflow_is_an_error() {
+	let error = run(
+		1,
+		&[
+			DBG_ADVANCE_PC,
+			0xff,
+			0xff,
+			0xff,
+			0xff,
+			0x0f, // u32::MAX
+			// address + 1
+			0x0a + 4 + 15,
+			DBG_END_SEQUENCE,
+		],
+	)
+	.unwrap_err();
+	assert!(error.to_string().contains("overflows"), "{}", error);
+}
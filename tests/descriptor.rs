@@ -0,0 +1,111 @@
+use eyre::Result;
+use smali::TypeDescriptor;
+
+fn object(name: &str) -> TypeDescriptor {
+	TypeDescriptor::Object(name.to_string())
+}
+
+fn array(dimensions: u8, element: TypeDescriptor) -> TypeDescriptor {
+	TypeDescriptor::Array(dimensions, Box::new(element))
+}
+
+#[test]
+fn descriptors_parse_and_print() -> Result<()> {
+	let cases = [
+		("V", TypeDescriptor::Void, "void", "void"),
+		("Z", TypeDescriptor::Boolean, "boolean", "boolean"),
+		("J", TypeDescriptor::Long, "long", "long"),
+		(
+			"Ljava/lang/String;",
+			object("java/lang/String"),
+			"java.lang.String",
+			"java.lang.String",
+		),
+		(
+			"Lfoo/Bar$Inner;",
+			object("foo/Bar$Inner"),
+			"foo.Bar$Inner",
+			"foo.Bar$Inner",
+		),
+		("[I", array(1, TypeDescriptor::Int), "int[]", "[I"),
+		(
+			"[[Ljava/lang/String;",
+			array(2, object("java/lang/String")),
+			"java.lang.String[][]",
+			"[[Ljava.lang.String;",
+		),
+	];
+	for (descriptor, typ, java, binary) in cases {
+		assert_eq!(descriptor.parse::<TypeDescriptor>()?, typ);
+		assert_eq!(typ.to_string(), descriptor);
+		assert_eq!(typ.java_name(), java);
+		assert_eq!(TypeDescriptor::from_java_name(java)?, typ);
+		assert_eq!(typ.binary_name(), binary);
+		assert_eq!(TypeDescriptor::from_binary_name(binary)?, typ);
+	}
+	Ok(())
+}
+
+#[test]
+fn parameters_parse_one_at_a_time() -> Result<()> {
+	let (first, rest) = TypeDescriptor::parse_prefix("[JLfoo/Bar;I")?;
+	assert_eq!(first, array(1, TypeDescriptor::Long));
+	let (second, rest) = TypeDescriptor::parse_prefix(rest)?;
+	assert_eq!(second, object("foo/Bar"));
+	assert_eq!(rest, "I");
+	Ok(())
+}
+
+#[test]
+fn malformed_descriptors_are_rejected() {
+	for descriptor in [
+		"",
+		"X",
+		"[",
+		"[V",
+		"L;",
+		"Ljava/lang/String",
+		"II",
+		"Lfoo/Bar;I",
+		&"[".repeat(256),
+	] {
+		assert!(
+			descriptor.parse::<TypeDescriptor>().is_err(),
+			"{:?}",
+			descriptor
+		);
+	}
+	for name in ["", "void[]", "java/lang/String", "[I"] {
+		assert!(TypeDescriptor::from_java_name(name).is_err(), "{:?}", name);
+	}
+}
+
+#[test]
+fn register_widths() -> Result<()> {
+	let widths = [
+		("V", 0, false),
+		("I", 1, false),
+		("J", 2, true),
+		("D", 2, true),
+		("Ljava/lang/Object;", 1, false),
+		("[J", 1, false),
+	];
+	for (descriptor, width, wide) in widths {
+		let typ = descriptor.parse::<TypeDescriptor>()?;
+		assert_eq!(typ.register_width(), width, "{}", descriptor);
+		assert_eq!(typ.is_wide(), wide, "{}", descriptor);
+	}
+
+	let matrix = array(2, TypeDescriptor::Double);
+	assert!(matrix.is_reference() && !matrix.is_primitive());
+	assert_eq!(
+		matrix.element_type(),
+		Some(array(1, TypeDescriptor::Double))
+	);
+	assert_eq!(
+		matrix.element_type().and_then(|typ| typ.element_type()),
+		Some(TypeDescriptor::Double)
+	);
+	assert_eq!(TypeDescriptor::Double.element_type(), None);
+	Ok(())
+}
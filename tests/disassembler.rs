@@ -0,0 +1,264 @@
+mod common;
+
+use eyre::Result;
+use smali::{Assembler, DexBuilder, Disassembler};
+
+use crate::common::{fixture, resolve, LITTLE_ENDIAN};
+
+fn disassemble(path: &str) -> Result<String> {
+	let resolver = fixture(path)?;
+	let class = resolver.iter_classes().next().unwrap()?;
+	Disassembler::new(&resolver).class(&class)
+}
+
+/// The text of the method whose `.method` line starts with `header`.
+fn method<'a>(smali: &'a str, header: &str) -> &'a str {
+	let start = smali
+		.find(&format!(".method {}", header))
+		.unwrap_or_else(|| panic!("no method {} in {}", header, smali));
+	let end = smali[start..].find(".end method\n").unwrap() + start;
+	&smali[start..end + ".end method\n".len()]
+}
+
+/// Annotations come in baksmali's layout, with arrays and subannotations
+/// spread over several lines.
+#[test]
+fn annotations_are_written() -> Result<()> {
+	let smali = disassemble(LITTLE_ENDIAN)?;
+
+	let class = "
+.implements Ljava/lang/Runnable;
+
+
+# annotations
+.annotation system Ldalvik/annotation/Signature;
+    value = {
+        \"Ljava/lang/Object;\",
+        \"Ljava/lang/Runnable;\"
+    }
+.end annotation
+
+.annotation runtime Lfoo/Marker;
+    count = -0x12c
+    empty = {}
+    kind = .enum Lfoo/Kind;->A:Lfoo/Kind;
+    nested = .subannotation Lfoo/Inner;
+        flag = true
+    .end subannotation
+.end annotation
+
+
+# static fields
+";
+	assert!(smali.contains(class), "{}", smali);
+
+	let field = "
+.field private count:I
+    .annotation runtime Lfoo/Marker;
+    .end annotation
+.end field
+";
+	assert!(smali.contains(field), "{}", smali);
+
+	// without code the annotations follow the method line
+	let native = "
+.method public static native nat()V
+    .annotation runtime Ljava/lang/Deprecated;
+    .end annotation
+.end method
+";
+	assert!(smali.contains(native), "{}", smali);
+
+	let method = "
+.method public run()V
+    .registers 1
+    .annotation system Ldalvik/annotation/Throws;
+        value = {
+            Ljava/lang/Exception;
+        }
+    .end annotation
+";
+	assert!(smali.contains(method), "{}", smali);
+
+	// the wide parameter has annotations but no name
+	let parameters = "
+    .param p1, \"a\"    # I
+    .param p2    # J
+        .annotation runtime Lfoo/Marker;
+        .end annotation
+    .end param
+    .param p4, \"s\"    # Ljava/lang/String;
+";
+	assert!(smali.contains(parameters), "{}", smali);
+	Ok(())
+}
+
+/// Literals of every width, with wide ones marked `L`.
+#[test]
+fn instructions_and_literals_are_written() -> Result<()> {
+	let smali = disassemble(LITTLE_ENDIAN)?;
+	let expected = r#".method public static main([Ljava/lang/String;)V
+    .registers 2
+
+    sget-object v0, Ljava/lang/System;->out:Ljava/io/PrintStream;
+
+    const-string p0, "hello \"world\"\n"
+
+    invoke-virtual {v0, p0}, Ljava/io/PrintStream;->println(Ljava/lang/String;)V
+
+    const/4 v0, -0x1
+
+    const/high16 v0, 0x7f010000
+
+    const-wide/high16 v0, -0x4000000000000000L
+
+    return-void
+.end method
+"#;
+	assert_eq!(method(&smali, "public static main"), expected);
+	Ok(())
+}
+
+/// Try blocks are labeled around the covered instructions, the handlers and
+/// array payloads where they start.
+#[test]
+fn catches_and_array_payloads_are_written() -> Result<()> {
+	let smali = disassemble(LITTLE_ENDIAN)?;
+	let expected = "
+    const/4 p0, 0x0
+
+    :try_start_0
+    invoke-static {}, Lfoo/Bar;->nat()V
+    :try_end_0
+    .catch Ljava/lang/Exception; {:try_start_0 .. :try_end_0} :catch_0
+    .catchall {:try_start_0 .. :try_end_0} :catchall_0
+
+    return-void
+
+    :catch_0
+    :catchall_0
+    move-exception p0
+
+    throw p0
+
+    fill-array-data p0, :array_0
+
+    return-void
+
+    nop
+
+    :array_0
+    .array-data 2
+        0x1s
+        0x2s
+        -0x1s
+    .end array-data
+.end method
+";
+	let run = method(&smali, "public run()V");
+	assert!(run.ends_with(expected), "{}", run);
+	Ok(())
+}
+
+/// Debug directives at the same address come in baksmali's order, before
+/// the labels: `.prologue`/`.epilogue`, `.source`, `.line`, then locals.
+#[test]
+fn switches_and_debug_info_are_written() -> Result<()> {
+	let smali = disassemble(LITTLE_ENDIAN)?;
+	let expected = r#"
+    .prologue
+    .line 10
+    .local v0, "x":I
+    packed-switch p0, :pswitch_data_0
+
+    .line 11
+    .local v1, "y":Ljava/lang/String;, "TT;"
+    :pswitch_0
+    if-eqz v0, :cond_0
+
+    .line 8
+    .end local v1    # "y":Ljava/lang/String;
+    const/4 v1, 0x0
+
+    :goto_0
+    goto :goto_1
+
+    .epilogue
+    .source "Other.java"
+    .restart local v1    # "y":Ljava/lang/String;
+    :pswitch_1
+    const/4 v1, 0x1
+
+    :cond_0
+    :goto_1
+    goto :goto_0
+
+    return v0
+
+    .end local p4
+    nop
+
+    nop
+
+    :pswitch_data_0
+    .packed-switch 0xa
+        :pswitch_0
+        :pswitch_1
+    .end packed-switch
+.end method
+"#;
+	let sw = method(&smali, "public sw(");
+	assert!(sw.starts_with(".method public sw(IJLjava/lang/String;)I\n    .registers 7\n"));
+	assert!(sw.ends_with(expected), "{}", sw);
+	Ok(())
+}
+
+#[test]
+fn sparse_switches_are_written() -> Result<()> {
+	let source = "
+.class public Lfoo/Baz;
+.super Ljava/lang/Object;
+
+
+# direct methods
+.method public static s(I)I
+    .registers 1
+
+    sparse-switch p0, :sswitch_data_0
+
+    const/4 p0, 0x0
+
+    return p0
+
+    :sswitch_0
+    const/16 p0, 0x64
+
+    return p0
+
+    :sswitch_1
+    const p0, 0x12345
+
+    return p0
+
+    :sswitch_data_0
+    .sparse-switch
+        -0x1 -> :sswitch_0
+        0x3 -> :sswitch_1
+        0x7fffffff -> :sswitch_0
+    .end sparse-switch
+.end method
+";
+	let mut assembler = Assembler::new();
+	let class = assembler.class(source)?;
+	let bytes = DexBuilder::new(assembler.pool())
+		.build(&[class])?
+		.to_bytes()?;
+	let resolver = resolve(bytes)?;
+	let class = resolver.iter_classes().next().unwrap()?;
+	let smali = Disassembler::new(&resolver).class(&class)?;
+	assert_eq!(
+		method(&smali, "public static s"),
+		method(source, "public static s")
+	);
+	Ok(())
+}
@@ -0,0 +1,36 @@
+//! The fixtures hold the same classes, once in the standard little endian
+//! byte order and once byte swapped as a big endian file. They are laid out by
+//! `tests/fixtures/gendex.py` rather than by the crate's writer.
+
+mod common;
+
+use std::fs;
+
+use eyre::Result;
+use smali::Class;
+
+use crate::common::{fixture, BIG_ENDIAN, LITTLE_ENDIAN};
+
+fn classes(path: &str) -> Result<Vec<Class>> {
+	fixture(path)?.classes()
+}
+
+#[test]
+fn both_byte_orders_resolve_the_same() -> Result<()> {
+	assert_eq!(classes(BIG_ENDIAN)?, classes(LITTLE_ENDIAN)?);
+	Ok(())
+}
+
+#[test]
+fn little_endian_is_written_back_unchanged() -> Result<()> {
+	let bytes = fixture(LITTLE_ENDIAN)?.dex_file.to_bytes()?;
+	assert_eq!(bytes, fs::read(LITTLE_ENDIAN)?);
+	Ok(())
+}
+
+#[test]
+fn big_endian_is_written_as_little_endian() -> Result<()> {
+	let bytes = fixture(BIG_ENDIAN)?.dex_file.to_bytes()?;
+	assert_eq!(bytes, fs::read(LITTLE_ENDIAN)?);
+	Ok(())
+}
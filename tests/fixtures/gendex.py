@@ -0,0 +1,570 @@
+#!/usr/bin/env python3
+"""Generates the dex fixtures of the integration tests.
+
+The files are laid out by hand here, independently of the crate's own writer,
+so tests comparing the writer's output with them aren't circular. Both
+fixtures hold the same classes, `big_endian.dex` is the byte swapped version
+of `little_endian.dex`. Regenerate them with
+
+    python3 tests/fixtures/gendex.py
+"""
+import hashlib
+import os
+import struct
+import zlib
+
+
+def uleb(v):
+    out = bytearray()
+    while True:
+        b = v & 0x7f
+        v >>= 7
+        if v:
+            out.append(b | 0x80)
+        else:
+            out.append(b)
+            return bytes(out)
+
+
+def sleb(v):
+    out = bytearray()
+    while True:
+        b = v & 0x7f
+        v >>= 7
+        if (v == 0 and not b & 0x40) or (v == -1 and b & 0x40):
+            out.append(b)
+            return bytes(out)
+        out.append(b | 0x80)
+
+
+def mutf8(s):
+    """Length in UTF-16 code units and the MUTF-8 bytes of a string."""
+    out = bytearray()
+    encoded = s.encode('utf-16-le')
+    units = struct.unpack('<%dH' % (len(encoded) // 2), encoded)
+    for u in units:
+        if u != 0 and u < 0x80:
+            out.append(u)
+        elif u < 0x800:
+            out += bytes([0xc0 | (u >> 6), 0x80 | (u & 0x3f)])
+        else:
+            out += bytes([0xe0 | (u >> 12), 0x80 | ((u >> 6) & 0x3f), 0x80 | (u & 0x3f)])
+    return len(units), bytes(out)
+
+
+def encoded_uint(typ, v):
+    """An encoded value that is zero extended, like indices."""
+    b = v.to_bytes(max(1, (v.bit_length() + 7) // 8), 'little')
+    return bytes([(len(b) - 1) << 5 | typ]) + b
+
+
+def encoded_int(typ, v):
+    """An encoded value that is sign extended."""
+    size = 1
+    while not -(1 << (8 * size - 1)) <= v < 1 << (8 * size - 1):
+        size += 1
+    return bytes([(size - 1) << 5 | typ]) + v.to_bytes(size, 'little', signed=True)
+
+
+class Annotation:
+    """`elements` are `(name, value)` pairs, values are functions from the
+    built `Dex` to their encoding."""
+
+    def __init__(self, visibility, typ, elements=()):
+        self.visibility = visibility
+        self.typ = typ
+        self.elements = list(elements)
+
+    def encode(self, d):
+        out = bytearray(uleb(d.tidx[self.typ]) + uleb(len(self.elements)))
+        for name, value in sorted(self.elements, key=lambda e: d.sidx[e[0]]):
+            out += uleb(d.sidx[name]) + value(d)
+        return bytes(out)
+
+
+VISIBILITY_BUILD, VISIBILITY_RUNTIME, VISIBILITY_SYSTEM = 0, 1, 2
+
+
+class Dex:
+    """Collects the items of a file, `build` sorts and lays them out."""
+
+    def __init__(self):
+        self.strings = set()
+        self.types = set()
+        self.protos = set()
+        self.fields = set()
+        self.methods = set()
+        self.classes = []
+
+    def s(self, x):
+        self.strings.add(x)
+        return x
+
+    def t(self, x):
+        self.s(x)
+        self.types.add(x)
+        return x
+
+    def shorty(self, ret, params):
+        f = lambda t: 'L' if t[0] in 'L[' else t
+        return f(ret) + ''.join(f(p) for p in params)
+
+    def p(self, ret, params):
+        params = tuple(params)
+        self.t(ret)
+        for x in params:
+            self.t(x)
+        self.s(self.shorty(ret, params))
+        self.protos.add((ret, params))
+        return (ret, params)
+
+    def f(self, cls, name, typ):
+        self.t(cls); self.t(typ); self.s(name)
+        self.fields.add((cls, name, typ))
+        return (cls, name, typ)
+
+    def m(self, cls, name, ret, params):
+        self.t(cls); self.s(name)
+        pr = self.p(ret, params)
+        self.methods.add((cls, name, pr))
+        return (cls, name, pr)
+
+    def a(self, visibility, typ, **elements):
+        self.t(typ)
+        for name in elements:
+            self.s(name)
+        return Annotation(visibility, typ, elements.items())
+
+    def cls(self, name, flags=1, sup='Ljava/lang/Object;', interfaces=(), source=None,
+            static_fields=(), instance_fields=(), direct=(), virtual=(), static_values=None,
+            annotations=()):
+        """Members can have `annotations` and methods `parameter_annotations`
+        with a list of annotations per parameter."""
+        self.t(name); self.t(sup)
+        for i in interfaces:
+            self.t(i)
+        if source:
+            self.s(source)
+        self.classes.append(dict(name=name, flags=flags, sup=sup, interfaces=list(interfaces),
+                                 source=source, sf=list(static_fields), inf=list(instance_fields),
+                                 dm=list(direct), vm=list(virtual), sv=static_values,
+                                 annotations=list(annotations)))
+
+    def build(self, big_endian=False):
+        E = '>' if big_endian else '<'
+        strings = sorted(self.strings, key=lambda s: s.encode('utf-16-be'))
+        sidx = {s: i for i, s in enumerate(strings)}
+        types = sorted(self.types, key=lambda t: sidx[t])
+        tidx = {t: i for i, t in enumerate(types)}
+        protos = sorted(self.protos, key=lambda p: (tidx[p[0]], [tidx[x] for x in p[1]]))
+        pidx = {p: i for i, p in enumerate(protos)}
+        fields = sorted(self.fields, key=lambda f: (tidx[f[0]], sidx[f[1]], tidx[f[2]]))
+        fidx = {f: i for i, f in enumerate(fields)}
+        methods = sorted(self.methods, key=lambda m: (tidx[m[0]], sidx[m[1]], pidx[m[2]]))
+        midx = {m: i for i, m in enumerate(methods)}
+        self.sidx, self.tidx, self.pidx, self.fidx, self.midx = sidx, tidx, pidx, fidx, midx
+
+        header_size = 0x70
+        off = header_size
+        string_ids_off = off; off += 4 * len(strings)
+        type_ids_off = off; off += 4 * len(types)
+        proto_ids_off = off; off += 12 * len(protos)
+        field_ids_off = off; off += 8 * len(fields)
+        method_ids_off = off; off += 8 * len(methods)
+        class_defs_off = off; off += 32 * len(self.classes)
+        data_off = off
+
+        data = bytearray()
+        mapitems = []
+
+        def pos():
+            return data_off + len(data)
+
+        def align(n):
+            while pos() % n:
+                data.append(0)
+
+        u16 = lambda v: struct.pack(E + 'H', v & 0xffff)
+        u32 = lambda v: struct.pack(E + 'I', v & 0xffffffff)
+
+        # type lists
+        typelists = {}
+        lists = set(p[1] for p in protos if p[1]) | set(tuple(c['interfaces']) for c in self.classes if c['interfaces'])
+        align(4)
+        tl_start = pos()
+        for l in sorted(lists):
+            align(4)
+            typelists[l] = pos()
+            data.extend(u32(len(l)))
+            for t in l:
+                data.extend(u16(tidx[t]))
+        if lists:
+            mapitems.append((0x1001, len(lists), tl_start))
+
+        # debug info
+        dbg_offs = {}
+        dbg_start = pos()
+        ndbg = 0
+        for c in self.classes:
+            for m in c['dm'] + c['vm']:
+                code = m.get('code')
+                if code is None or code.get('debug') is None:
+                    continue
+                dbg_offs[id(m)] = pos()
+                ndbg += 1
+                data.extend(code['debug'](self))
+        if ndbg:
+            mapitems.append((0x2003, ndbg, dbg_start))
+
+        # code items
+        code_offs = {}
+        align(4)
+        code_start = pos()
+        ncode = 0
+        for c in self.classes:
+            for m in c['dm'] + c['vm']:
+                code = m.get('code')
+                if code is None:
+                    continue
+                align(4)
+                code_offs[id(m)] = pos()
+                ncode += 1
+                insns = code['insns'](self) if callable(code['insns']) else code['insns']
+                tries = code.get('tries', [])
+                data.extend(u16(code['registers']) + u16(code['ins']) + u16(code['outs']) + u16(len(tries)))
+                data.extend(u32(dbg_offs.get(id(m), 0)))
+                data.extend(u32(len(insns)))
+                for i in insns:
+                    data.extend(u16(i))
+                if tries:
+                    if len(insns) % 2:
+                        data.extend(u16(0))
+                    # handlers
+                    hl = bytearray(uleb(len(tries)))
+                    hoffs = []
+                    for tr in tries:
+                        hoffs.append(len(hl))
+                        catches = tr['catches']
+                        ca = tr.get('catch_all')
+                        hl += sleb(len(catches) if ca is None else -len(catches))
+                        for typ, addr in catches:
+                            hl += uleb(tidx[typ]) + uleb(addr)
+                        if ca is not None:
+                            hl += uleb(ca)
+                    for tr, ho in zip(tries, hoffs):
+                        data.extend(u32(tr['start']) + u16(tr['count']) + u16(ho))
+                    data.extend(hl)
+        if ncode:
+            mapitems.append((0x2001, ncode, code_start))
+
+        # string data
+        sd_start = pos()
+        sdoffs = []
+        for s in strings:
+            sdoffs.append(pos())
+            n, b = mutf8(s)
+            data.extend(uleb(n) + b + b'\0')
+        mapitems.append((0x2002, len(strings), sd_start))
+
+        # annotation items
+        def members(c):
+            return c['sf'] + c['inf'] + c['dm'] + c['vm']
+
+        def annotations(c):
+            for a in c['annotations']:
+                yield a
+            for member in members(c):
+                for a in member.get('annotations', []):
+                    yield a
+                for parameter in member.get('parameter_annotations', []):
+                    for a in parameter:
+                        yield a
+
+        ai_offs = {}
+        ai_start = pos()
+        for c in self.classes:
+            for a in annotations(c):
+                ai_offs[id(a)] = pos()
+                data.extend(bytes([a.visibility]) + a.encode(self))
+        if ai_offs:
+            mapitems.append((0x2004, len(ai_offs), ai_start))
+
+        # class data
+        cd_offs = {}
+        cd_start = pos()
+        ncd = 0
+        for i, c in enumerate(self.classes):
+            if not (c['sf'] or c['inf'] or c['dm'] or c['vm']):
+                continue
+            cd_offs[i] = pos()
+            ncd += 1
+            data.extend(uleb(len(c['sf'])) + uleb(len(c['inf'])) + uleb(len(c['dm'])) + uleb(len(c['vm'])))
+            for lst in (c['sf'], c['inf']):
+                prev = 0
+                for fl in sorted(lst, key=lambda x: fidx[x['ref']]):
+                    ix = fidx[fl['ref']]
+                    data.extend(uleb(ix - prev) + uleb(fl['flags']))
+                    prev = ix
+            for lst in (c['dm'], c['vm']):
+                prev = 0
+                for m in sorted(lst, key=lambda x: midx[x['ref']]):
+                    ix = midx[m['ref']]
+                    data.extend(uleb(ix - prev) + uleb(m['flags']) + uleb(code_offs.get(id(m), 0)))
+                    prev = ix
+        if ncd:
+            mapitems.append((0x2000, ncd, cd_start))
+
+        # encoded arrays (static values)
+        ea_offs = {}
+        ea_start = pos()
+        nea = 0
+        for i, c in enumerate(self.classes):
+            if c['sv'] is None:
+                continue
+            ea_offs[i] = pos()
+            nea += 1
+            data.extend(c['sv'](self))
+        if nea:
+            mapitems.append((0x2005, nea, ea_start))
+
+        # annotation sets, sorted by type
+        set_offs = {}
+        align(4)
+        as_start = pos()
+
+        def annotation_set(annotations):
+            align(4)
+            set_offs[id(annotations)] = pos()
+            data.extend(u32(len(annotations)))
+            for a in sorted(annotations, key=lambda a: tidx[a.typ]):
+                data.extend(u32(ai_offs[id(a)]))
+
+        for c in self.classes:
+            if c['annotations']:
+                annotation_set(c['annotations'])
+            for member in members(c):
+                if member.get('annotations'):
+                    annotation_set(member['annotations'])
+                for parameter in member.get('parameter_annotations', []):
+                    if parameter:
+                        annotation_set(parameter)
+        if set_offs:
+            mapitems.append((0x1003, len(set_offs), as_start))
+
+        # annotation set ref lists, one set per parameter
+        ref_offs = {}
+        align(4)
+        rl_start = pos()
+        for c in self.classes:
+            for m in c['dm'] + c['vm']:
+                if 'parameter_annotations' not in m:
+                    continue
+                align(4)
+                ref_offs[id(m)] = pos()
+                data.extend(u32(len(m['parameter_annotations'])))
+                for parameter in m['parameter_annotations']:
+                    data.extend(u32(set_offs.get(id(parameter), 0)))
+        if ref_offs:
+            mapitems.append((0x1002, len(ref_offs), rl_start))
+
+        # annotations directories
+        dir_offs = {}
+        align(4)
+        ad_start = pos()
+        for i, c in enumerate(self.classes):
+            annotated_fields = sorted(((fidx[f['ref']], f) for f in c['sf'] + c['inf'] if f.get('annotations')),
+                            key=lambda x: x[0])
+            annotated_methods = sorted(((midx[m['ref']], m) for m in c['dm'] + c['vm'] if m.get('annotations')),
+                             key=lambda x: x[0])
+            annotated_parameters = sorted(((midx[m['ref']], m) for m in c['dm'] + c['vm'] if id(m) in ref_offs),
+                                key=lambda x: x[0])
+            if not (c['annotations'] or annotated_fields or annotated_methods or annotated_parameters):
+                continue
+            align(4)
+            dir_offs[i] = pos()
+            data.extend(u32(set_offs[id(c['annotations'])] if c['annotations'] else 0))
+            data.extend(u32(len(annotated_fields)) + u32(len(annotated_methods))
+                        + u32(len(annotated_parameters)))
+            for ix, f in annotated_fields:
+                data.extend(u32(ix) + u32(set_offs[id(f['annotations'])]))
+            for ix, m in annotated_methods:
+                data.extend(u32(ix) + u32(set_offs[id(m['annotations'])]))
+            for ix, m in annotated_parameters:
+                data.extend(u32(ix) + u32(ref_offs[id(m)]))
+        if dir_offs:
+            mapitems.append((0x2006, len(dir_offs), ad_start))
+
+        align(4)
+        map_off = pos()
+        mapitems = [(0, 1, 0)] + \
+            ([(1, len(strings), string_ids_off)] if strings else []) + \
+            ([(2, len(types), type_ids_off)] if types else []) + \
+            ([(3, len(protos), proto_ids_off)] if protos else []) + \
+            ([(4, len(fields), field_ids_off)] if fields else []) + \
+            ([(5, len(methods), method_ids_off)] if methods else []) + \
+            ([(6, len(self.classes), class_defs_off)] if self.classes else []) + \
+            sorted(mapitems, key=lambda x: x[2]) + [(0x1000, 1, map_off)]
+        data.extend(u32(len(mapitems)))
+        for t, n, o in mapitems:
+            data.extend(u16(t) + u16(0) + u32(n) + u32(o))
+
+        ids = bytearray()
+        for o in sdoffs:
+            ids += u32(o)
+        for t in types:
+            ids += u32(sidx[t])
+        for p in protos:
+            ids += u32(sidx[self.shorty(*p)]) + u32(tidx[p[0]]) + u32(typelists.get(p[1], 0))
+        for f in fields:
+            ids += u16(tidx[f[0]]) + u16(tidx[f[2]]) + u32(sidx[f[1]])
+        for m in methods:
+            ids += u16(tidx[m[0]]) + u16(pidx[m[2]]) + u32(sidx[m[1]])
+        for i, c in enumerate(self.classes):
+            ids += u32(tidx[c['name']]) + u32(c['flags']) + u32(tidx[c['sup']]) + \
+                u32(typelists.get(tuple(c['interfaces']), 0)) + \
+                u32(sidx[c['source']] if c['source'] else 0xffffffff) + u32(dir_offs.get(i, 0)) + \
+                u32(cd_offs.get(i, 0)) + u32(ea_offs.get(i, 0))
+        assert len(ids) == data_off - header_size
+
+        file_size = header_size + len(ids) + len(data)
+        hdr = bytearray(b'dex\n035\0')
+        hdr += b'\0' * 4 + b'\0' * 20
+        hdr += u32(file_size) + u32(header_size) + u32(0x12345678)
+        hdr += u32(0) + u32(0) + u32(map_off)
+        hdr += u32(len(strings)) + u32(string_ids_off if strings else 0)
+        hdr += u32(len(types)) + u32(type_ids_off if types else 0)
+        hdr += u32(len(protos)) + u32(proto_ids_off if protos else 0)
+        hdr += u32(len(fields)) + u32(field_ids_off if fields else 0)
+        hdr += u32(len(methods)) + u32(method_ids_off if methods else 0)
+        hdr += u32(len(self.classes)) + u32(class_defs_off if self.classes else 0)
+        hdr += u32(len(data)) + u32(data_off)
+        out = bytearray(hdr + ids + data)
+        sig = hashlib.sha1(out[32:]).digest()
+        out[12:32] = sig
+        out[8:12] = struct.pack(E + 'I', zlib.adler32(bytes(out[12:])))
+        return bytes(out)
+
+
+def sample(d=None):
+    """`Lfoo/Bar;`, with code covering branches, switches, array data, try
+    blocks and debug info."""
+    d = d or Dex()
+    obj = 'Ljava/lang/Object;'
+    S = 'Ljava/lang/String;'
+    ps = 'Ljava/io/PrintStream;'
+    init = d.m(obj, '<init>', 'V', [])
+    println = d.m(ps, 'println', 'V', [S])
+    out = d.f('Ljava/lang/System;', 'out', ps)
+    cnt = d.f('Lfoo/Bar;', 'count', 'I')
+    tag = d.f('Lfoo/Bar;', 'TAG', S)
+    name_f = d.f('Lfoo/Bar;', 'name', S)
+    d.t('Ljava/lang/Exception;')
+    d.t('Ljava/lang/Runnable;')
+    d.s('hello "world"\n')
+    bar_init = d.m('Lfoo/Bar;', '<init>', 'V', [])
+    main = d.m('Lfoo/Bar;', 'main', 'V', ['[' + S])
+    sw = d.m('Lfoo/Bar;', 'sw', 'I', ['I', 'J', S])
+    run = d.m('Lfoo/Bar;', 'run', 'V', [])
+    nat = d.m('Lfoo/Bar;', 'nat', 'V', [])
+
+    def init_code(d):
+        return [0x1070, d.midx[init], 0x0000, 0x000e]  # invoke-direct {p0}, Object.<init>; return-void
+
+    def main_code(d):
+        return [
+            0x0062, d.fidx[out],               # sget-object v0, System.out
+            0x011a, d.sidx['hello "world"\n'],  # const-string v1
+            0x206e, d.midx[println], 0x0010,  # invoke-virtual {v0, v1}
+            0xf012,                            # const/4 v0, -1
+            0x0015, 0x7f01,                    # const/high16 v0, 0x7f010000
+            0x0019, 0xc000,                    # const-wide/high16 v0, 0xc000...
+            0x0e,                              # return-void
+        ]
+
+    def sw_code(d):
+        # registers: v0, v1 ; params p0=this p1=int p2,p3=long p4=string => 7 regs, ins 5
+        return [
+            0x022b, 0x000c, 0x0000,  # 0: packed-switch v2(=p0), +12
+            0x0038, 0x0005,          # 3: if-eqz v0, +5 -> 8
+            0x0112,                  # 5: const/4 v1, 0
+            0x0228,                  # 6: goto +2 -> 8
+            0x1112,                  # 7: const/4 v1, 1
+            0xfe28,                  # 8: goto -2 -> 6   (backward)
+            0x000f,                  # 9: return v0
+            0x0000,                  # 10: nop (align)
+            0x0000,                  # 11: nop
+            0x0100, 0x0002, 0x000a, 0x0000, 0x0003, 0x0000, 0x0007, 0x0000,  # 12: packed payload first=10 targets +3,+7
+        ]
+
+    def run_code(d):
+        return [
+            0x0012,                     # 0: const/4 v0, 0
+            0x0071, d.midx[nat], 0x0000,  # 1: invoke-static {}, nat
+            0x000e,                     # 4: return-void
+            0x000d,                     # 5: move-exception v0
+            0x0027,                     # 6: throw v0
+            0x0026, 0x0005, 0x0000,     # 7: fill-array-data v0, +5 -> 12
+            0x000e,                     # 10: return-void
+            0x0000,                     # 11: nop
+            0x0300, 0x0002, 0x0003, 0x0000, 0x0001, 0x0002, 0xffff,  # 12: array payload
+        ]
+
+    for x in ('x', 'y', 'a', 's', 'TT;', 'Other.java'):
+        d.s(x)
+
+    def sw_debug(d):
+        sp = lambda x: uleb(d.sidx[x] + 1)
+        tp = lambda x: uleb(d.tidx[x] + 1)
+        return (uleb(10) + uleb(3) + sp('a') + uleb(0) + sp('s')
+                + bytes([0x07, 0x0e])                                   # prologue, line 10 @0
+                + bytes([0x03]) + uleb(0) + sp('x') + tp('I')           # .local v0 x:I
+                + bytes([0x0a + 5 + 3 * 15])                            # line 11 @3
+                + bytes([0x04]) + uleb(1) + sp('y') + tp(S) + sp('TT;')  # .local v1 y:String, "TT;"
+                + bytes([0x01]) + uleb(2) + bytes([0x05]) + uleb(1)     # @5 end local v1
+                + bytes([0x02]) + sleb(-3) + bytes([0x0e])              # line 8 @5
+                + bytes([0x01]) + uleb(2) + bytes([0x06]) + uleb(1)     # @7 restart local v1
+                + bytes([0x08, 0x09]) + sp('Other.java')                # epilogue, set file
+                + bytes([0x01]) + uleb(3) + bytes([0x05]) + uleb(6)     # @10 end local p4
+                + bytes([0x00]))
+
+    def sv(d):
+        # the initial value of TAG, the only static field
+        return uleb(1) + bytes([0x17]) + bytes([d.sidx['hello "world"\n']])
+
+    kind_a = d.f('Lfoo/Kind;', 'A', 'Lfoo/Kind;')
+    marker = lambda: d.a(VISIBILITY_RUNTIME, 'Lfoo/Marker;')
+    inner = d.a(VISIBILITY_BUILD, 'Lfoo/Inner;', flag=lambda d: bytes([0x3f]))
+    class_annotations = [
+        d.a(VISIBILITY_SYSTEM, 'Ldalvik/annotation/Signature;',
+            value=lambda d: bytes([0x1c]) + uleb(2) + encoded_uint(0x17, d.sidx[obj])
+            + encoded_uint(0x17, d.sidx['Ljava/lang/Runnable;'])),
+        d.a(VISIBILITY_RUNTIME, 'Lfoo/Marker;',
+            count=lambda d: encoded_int(0x04, -300),
+            kind=lambda d: encoded_uint(0x1b, d.fidx[kind_a]),
+            nested=lambda d: bytes([0x1d]) + inner.encode(d),
+            empty=lambda d: bytes([0x1c]) + uleb(0)),
+    ]
+
+    d.cls('Lfoo/Bar;', flags=0x11, interfaces=['Ljava/lang/Runnable;'], source=d.s('Bar.java'),
+          static_fields=[dict(ref=tag, flags=0x19)],
+          instance_fields=[dict(ref=cnt, flags=0x2, annotations=[marker()]), dict(ref=name_f, flags=0x1)],
+          direct=[dict(ref=bar_init, flags=0x10001, code=dict(registers=1, ins=1, outs=1, insns=init_code)),
+                  dict(ref=main, flags=0x9, code=dict(registers=2, ins=1, outs=2, insns=main_code)),
+                  dict(ref=nat, flags=0x109,
+                       annotations=[d.a(VISIBILITY_RUNTIME, 'Ljava/lang/Deprecated;')])],
+          virtual=[dict(ref=sw, flags=0x1, code=dict(registers=7, ins=5, outs=0, insns=sw_code, debug=sw_debug),
+                        parameter_annotations=[[], [marker()], []]),
+                   dict(ref=run, flags=0x1, annotations=[
+                       d.a(VISIBILITY_SYSTEM, 'Ldalvik/annotation/Throws;',
+                           value=lambda d: bytes([0x1c]) + uleb(1) + encoded_uint(0x18, d.tidx['Ljava/lang/Exception;']))],
+                        code=dict(registers=1, ins=1, outs=0, insns=run_code,
+                                                      tries=[dict(start=1, count=3, catches=[('Ljava/lang/Exception;', 5)], catch_all=5)]))],
+          static_values=sv, annotations=class_annotations)
+    return d
+
+
+if __name__ == '__main__':
+    fixtures = os.path.dirname(os.path.abspath(__file__))
+    for name, big_endian in (('little_endian.dex', False), ('big_endian.dex', True)):
+        with open(os.path.join(fixtures, name), 'wb') as f:
+            f.write(sample().build(big_endian=big_endian))
@@ -0,0 +1,241 @@
+mod common;
+
+use std::{collections::HashSet, io::Cursor};
+
+use eyre::Result;
+use smali::{
+	dex::{
+		asm::{instruction::ArrayElements, opcode::ReferenceType},
+		types::map::TypeCode,
+	},
+	FileParser,
+	Format,
+	Instruction,
+	Opcode,
+	Parse,
+	ParseError,
+	Parser,
+	Resolver,
+	SliceParser,
+};
+
+use crate::common::{fixture, LITTLE_ENDIAN};
+
+/// Where the endian tag sits in the header.
+const ENDIAN_TAG_OFFSET: usize = 0x28;
+
+/// Parses a single instruction from its code units, behind just enough of a
+/// header for the parser to find the byte order.
+fn decode(units: &[u16]) -> Result<Instruction> {
+	let mut bytes = vec![0; ENDIAN_TAG_OFFSET];
+	bytes.extend(0x12345678u32.to_le_bytes());
+	let start = bytes.len() as u32;
+	bytes.extend(units.iter().flat_map(|unit| unit.to_le_bytes()));
+
+	let mut parser = SliceParser::new(&bytes)?;
+	parser.set_offset(start)?;
+	Instruction::parse(&mut parser)
+}
+
+#[test]
+fn registers_expand_lists_and_ranges() {
+	// invoke-virtual {v3, v4, v5}, method@7
+	let invoke = Instruction::Instruction35c(Opcode::InvokeVirtual, (3, 0, 7, 0, 5, 4, 3));
+	assert_eq!(invoke.registers(), vec![3, 4, 5]);
+	assert_eq!(invoke.reference(), Some((ReferenceType::Method, 7)));
+	assert_eq!(invoke.reference_2(), None);
+
+	// invoke-virtual/range {v10 .. v12}, method@7
+	let range = Instruction::Instruction3rc(Opcode::InvokeVirtualRange, (3, 7, 10));
+	assert_eq!(range.registers(), vec![10, 11, 12]);
+}
+
+#[test]
+fn literals_and_offsets_are_sign_extended() {
+	// const/4 v1, -0x1
+	assert_eq!(
+		Instruction::Instruction11n(Opcode::Const4, (1, 0xf)).literal(),
+		Some(-1)
+	);
+	// add-int/lit8 v0, v1, -0x80
+	assert_eq!(
+		Instruction::Instruction22b(Opcode::AddIntLit8, (0, 1, 0x80)).literal(),
+		Some(-0x80)
+	);
+	// const-wide/high16 v0, -0x4000000000000000L
+	assert_eq!(
+		Instruction::Instruction21lh(Opcode::ConstWideHigh16, (0, -0x4000000000000000)).literal(),
+		Some(-0x4000000000000000)
+	);
+
+	// if-eqz v0, -3
+	let branch = Instruction::Instruction21t(Opcode::IfEqz, (0, -3));
+	assert_eq!(branch.branch_offset(), Some(-3));
+	assert_eq!(branch.branch_target(10), Some(7));
+	assert_eq!(branch.literal(), None);
+	assert_eq!(branch.reference(), None);
+}
+
+#[test]
+fn branches_target_instructions() -> Result<()> {
+	let resolver = fixture(LITTLE_ENDIAN)?;
+	let mut branches = 0;
+	for code in resolver.dex_file.code.iter() {
+		let addresses: HashSet<u32> = code
+			.instructions_with_offsets()
+			.map(|(address, _)| address)
+			.collect();
+		for (address, insn) in code.instructions_with_offsets() {
+			if let Some(target) = insn.branch_target(address) {
+				assert!(addresses.contains(&target), "{:?} at {}", insn, address);
+				branches += 1;
+			}
+		}
+	}
+	assert!(branches > 0);
+	Ok(())
+}
+
+#[test]
+fn payloads_are_found_from_their_instructions() -> Result<()> {
+	let resolver = fixture(LITTLE_ENDIAN)?;
+	let (mut arrays, mut switches) = (0, 0);
+	for code in resolver.dex_file.code.iter() {
+		for (address, insn) in code.instructions_with_offsets() {
+			match insn.opcode() {
+				Opcode::FillArrayData => {
+					assert_eq!(
+						code.array_data(address)?,
+						ArrayElements::Short(vec![1, 2, -1])
+					);
+					arrays += 1;
+				}
+				Opcode::PackedSwitch => {
+					let targets = code.switch_targets(address)?;
+					assert!(!targets.is_empty());
+					for (i, (key, target)) in targets.iter().enumerate() {
+						assert_eq!(*key, targets[0].0 + i as i32);
+						assert!(code.instruction_at(*target).is_some());
+					}
+					switches += 1;
+				}
+				_ => assert!(code.payload(address).is_err()),
+			}
+		}
+	}
+	assert_eq!((arrays, switches), (1, 1));
+	Ok(())
+}
+
+/// Packers put garbage in the unused byte after opcodes like `return-void`.
+#[test]
+fn unused_bytes_fail_unless_lenient() -> Result<()> {
+	let mut bytes = std::fs::read(LITTLE_ENDIAN)?;
+	let dex_file = FileParser::new(Cursor::new(bytes.clone()))?.parse_file()?;
+	let code_off = dex_file.map_list.find(TypeCode::CodeItem).unwrap().offset;
+	let (address, insn) = dex_file.code[0]
+		.instructions_with_offsets()
+		.find(|(_, insn)| insn.opcode().format() == Format::Format10x)
+		.unwrap();
+	// the instructions start after the 16 byte code item header
+	let offset = code_off + 16 + address * 2;
+	bytes[offset as usize + 1] = 0x5a;
+
+	let err = FileParser::new(Cursor::new(bytes.clone()))?
+		.parse_file()
+		.unwrap_err();
+	match err.downcast_ref::<ParseError>() {
+		Some(&ParseError::UnusedByte {
+			opcode,
+			offset: at,
+			value,
+		}) => assert_eq!((opcode, at, value), (insn.opcode(), offset, 0x5a)),
+		_ => panic!("unexpected error: {:?}", err),
+	}
+
+	let mut parser = FileParser::new(Cursor::new(bytes.clone()))?.lenient();
+	let lenient = parser.parse_file()?;
+	assert_eq!(lenient.code, dex_file.code);
+	match parser.warnings() {
+		[ParseError::UnusedByte {
+			opcode,
+			offset: at,
+			value,
+		}] => assert_eq!((*opcode, *at, *value), (insn.opcode(), offset, 0x5a)),
+		warnings => panic!("unexpected warnings: {:?}", warnings),
+	}
+
+	// a lazy resolver only sees the byte once it parses the code
+	let resolver = Resolver::lazy(FileParser::new(Cursor::new(bytes))?.lenient())?;
+	assert!(resolver.warnings().is_empty());
+	for class in resolver.iter_classes() {
+		class?;
+	}
+	assert_eq!(resolver.warnings().len(), 1);
+	Ok(())
+}
+
+/// Only the high 16 bits of the literal are stored, the rest are zeros.
+#[test]
+fn high16_literals_are_shifted() -> Result<()> {
+	let resolver = fixture(LITTLE_ENDIAN)?;
+	let high16 = resolver
+		.dex_file
+		.code
+		.iter()
+		.flat_map(|code| code.insns.iter())
+		.filter(|insn| {
+			matches!(
+				insn.opcode().format(),
+				Format::Format21ih | Format::Format21lh
+			)
+		})
+		.collect::<Vec<_>>();
+	// const/high16 v0, 0x7f010000 and const-wide/high16 v0, -0x4000000000000000L
+	assert_eq!(
+		high16,
+		[
+			&Instruction::Instruction21ih(Opcode::ConstHigh16, (0, 0x7f010000)),
+			&Instruction::Instruction21lh(Opcode::ConstWideHigh16, (0, -0x4000000000000000)),
+		]
+	);
+	assert_eq!(high16[0].encode()?, [0x0015, 0x7f01]);
+	assert_eq!(high16[1].encode()?, [0x0019, 0xc000]);
+
+	let cases = [
+		(
+			[0x0115, 0xffff],
+			Instruction::Instruction21ih(Opcode::ConstHigh16, (1, -0x10000)),
+			-0x10000,
+		),
+		(
+			[0x0215, 0x8000],
+			Instruction::Instruction21ih(Opcode::ConstHigh16, (2, i32::MIN)),
+			i32::MIN as i64,
+		),
+		(
+			[0x0319, 0x8000],
+			Instruction::Instruction21lh(Opcode::ConstWideHigh16, (3, i64::MIN)),
+			i64::MIN,
+		),
+		(
+			[0x0419, 0x0001],
+			Instruction::Instruction21lh(Opcode::ConstWideHigh16, (4, 0x1000000000000)),
+			0x1000000000000,
+		),
+	];
+	for (units, insn, literal) in cases {
+		assert_eq!(decode(&units)?, insn);
+		assert_eq!(insn.literal(), Some(literal));
+		assert_eq!(insn.encode()?, units);
+	}
+	Ok(())
+}
+
+#[test]
+fn high16_literals_need_zero_low_bits() {
+	let insn = Instruction::Instruction21ih(Opcode::ConstHigh16, (0, 0x10001));
+	assert!(insn.encode().is_err());
+	let insn = Instruction::Instruction21lh(Opcode::ConstWideHigh16, (0, 0x1_0000_0000));
+	assert!(insn.encode().is_err());
+}
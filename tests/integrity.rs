@@ -0,0 +1,30 @@
+mod common;
+
+use std::{fs, io::Cursor};
+
+use eyre::Result;
+use smali::{FileParser, Header, Parser};
+
+use crate::common::LITTLE_ENDIAN;
+
+fn verify(bytes: &[u8]) -> Result<()> {
+	let header: Header = FileParser::new(Cursor::new(bytes))?.parse()?;
+	header.verify_integrity(bytes)
+}
+
+#[test]
+fn fixture_is_intact() -> Result<()> {
+	verify(&fs::read(LITTLE_ENDIAN)?)
+}
+
+#[test]
+fn changed_byte_is_detected() -> Result<()> {
+	let mut bytes = fs::read(LITTLE_ENDIAN)?;
+	let last = bytes.len() - 1;
+	bytes[last] ^= 0xff;
+
+	let error = verify(&bytes).unwrap_err().to_string();
+	assert!(error.contains("checksum"), "{}", error);
+	assert!(error.contains("signature"), "{}", error);
+	Ok(())
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use std::{fs, io::Cursor};
+
+use eyre::Result;
+use smali::{dex::types::map::TypeCode, FileParser, Resolver};
+
+use crate::common::{resolve, BIG_ENDIAN, LITTLE_ENDIAN};
+
+/// An opcode no instruction uses.
+const UNUSED_OPCODE: u8 = 0x3e;
+
+#[test]
+fn lazy_resolves_the_same_classes() -> Result<()> {
+	for path in [LITTLE_ENDIAN, BIG_ENDIAN] {
+		let bytes = fs::read(path)?;
+		let eager = resolve(bytes.clone())?;
+		let lazy = Resolver::lazy(FileParser::new(Cursor::new(bytes))?)?;
+		assert!(lazy.dex_file.code.is_empty());
+
+		let expected = eager.iter_classes().collect::<Result<Vec<_>>>()?;
+		let classes = lazy.iter_classes().collect::<Result<Vec<_>>>()?;
+		assert_eq!(classes, expected);
+		// the second time around the items come out of the cache
+		let classes = lazy.iter_classes().collect::<Result<Vec<_>>>()?;
+		assert_eq!(classes, expected);
+	}
+	Ok(())
+}
+
+#[test]
+fn lazy_defers_broken_code() -> Result<()> {
+	let mut bytes = fs::read(LITTLE_ENDIAN)?;
+	let eager = resolve(bytes.clone())?;
+	// the instructions follow the 16 byte header of the code item
+	let code_off = eager.dex_file.item_offsets[&TypeCode::CodeItem][0];
+	bytes[code_off as usize + 16] = UNUSED_OPCODE;
+
+	assert!(resolve(bytes.clone()).is_err());
+	let lazy = Resolver::lazy(FileParser::new(Cursor::new(bytes))?)?;
+	let class_def = &lazy.dex_file.class_defs[0];
+	assert!(class_def.class_type(&lazy)?.descriptor(&lazy).is_ok());
+	assert!(lazy.iter_classes().any(|class| class.is_err()));
+	Ok(())
+}
+
+#[test]
+fn lazy_files_are_not_written() -> Result<()> {
+	let bytes = fs::read(LITTLE_ENDIAN)?;
+	let lazy = Resolver::lazy(FileParser::new(Cursor::new(bytes.clone()))?)?;
+	let error = lazy.dex_file.to_bytes().unwrap_err();
+	assert!(error.to_string().contains("only the ids"), "{}", error);
+
+	let eager = resolve(bytes.clone())?;
+	assert_eq!(eager.dex_file.to_bytes()?, bytes);
+	Ok(())
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use eyre::Result;
+use smali::{Proto, ResolveFrom, TypeDescriptor};
+
+use crate::common::{fixture, DexResolver, LITTLE_ENDIAN};
+
+fn protos(resolver: &DexResolver) -> Result<Vec<Proto>> {
+	resolver
+		.dex_file
+		.proto_ids
+		.iter()
+		.map(|item| Proto::resolve_from(item, resolver))
+		.collect()
+}
+
+#[test]
+fn parameters_are_resolved() -> Result<()> {
+	let protos = protos(&fixture(LITTLE_ENDIAN)?)?;
+	let sw = protos
+		.iter()
+		.find(|proto| proto.shorty_descriptor == "IIJL")
+		.unwrap();
+	assert_eq!(sw.return_type, TypeDescriptor::Int);
+	assert_eq!(
+		sw.parameters.as_deref(),
+		Some(
+			&[
+				TypeDescriptor::Int,
+				TypeDescriptor::Long,
+				TypeDescriptor::Object("java/lang/String".to_string()),
+			][..]
+		)
+	);
+
+	// no parameters at all is `None` rather than an empty list
+	let run = protos
+		.iter()
+		.find(|proto| proto.shorty_descriptor == "V")
+		.unwrap();
+	assert_eq!(run.parameters, None);
+	Ok(())
+}
+
+#[test]
+fn shorty_shortens_references() -> Result<()> {
+	let parameters = [
+		"[I".parse()?,
+		"Ljava/lang/Object;".parse()?,
+		"D".parse()?,
+		"[[Ljava/lang/String;".parse()?,
+	];
+	assert_eq!(
+		Proto::shorty_of(&TypeDescriptor::Void, &parameters),
+		"VLLDL"
+	);
+	assert_eq!(Proto::shorty_of(&"[J".parse()?, &[]), "L");
+	Ok(())
+}
+
+#[test]
+fn mismatched_shorty_is_rejected() -> Result<()> {
+	let mut resolver = fixture(LITTLE_ENDIAN)?;
+	let protos = protos(&resolver)?;
+	let void = protos
+		.iter()
+		.position(|proto| proto.shorty_descriptor == "V")
+		.unwrap();
+	let sw = protos
+		.iter()
+		.position(|proto| proto.shorty_descriptor == "IIJL")
+		.unwrap();
+
+	let proto_ids = &mut resolver.dex_file.proto_ids;
+	proto_ids[sw].shorty_idx = proto_ids[void].shorty_idx;
+	let error = Proto::resolve_from(&resolver.dex_file.proto_ids[sw], &resolver).unwrap_err();
+	assert!(error.to_string().contains("`V`"), "{}", error);
+	assert!(error.to_string().contains("`IIJL`"), "{}", error);
+	Ok(())
+}
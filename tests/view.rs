@@ -0,0 +1,110 @@
+mod common;
+
+use std::borrow::Cow;
+
+use eyre::Result;
+use smali::{
+	dex::types::id::{StringDataItem, StringDataRef},
+	DexView,
+	MappedDex,
+	Resolve,
+};
+
+use crate::common::{resolve, BIG_ENDIAN, LITTLE_ENDIAN};
+
+/// The view has to agree with the fully parsed file in either byte order.
+fn compare(path: &str) -> Result<()> {
+	let mapped = MappedDex::open(path)?;
+	assert!(mapped.is_dex());
+	let view = mapped.view()?;
+	let resolver = resolve(mapped.data().to_vec())?;
+
+	let strings = view
+		.strings()
+		.map(|string| Ok(string?.decode()?.into_owned()))
+		.collect::<Result<Vec<_>>>()?;
+	let expected = (0..strings.len())
+		.map(|idx| resolver.string(idx))
+		.collect::<Result<Vec<_>>>()?;
+	assert_eq!(strings, expected);
+
+	for (class_def, expected) in view.class_defs().zip(resolver.dex_file.class_defs.iter()) {
+		let class_def = class_def?;
+		assert_eq!(&class_def, expected);
+		let name = view.type_descriptor(*class_def.class_idx)?.decode()?;
+		assert_eq!(
+			name,
+			class_def
+				.class_type(&resolver)?
+				.descriptor(&resolver)?
+				.to_string()
+		);
+	}
+	Ok(())
+}
+
+#[test]
+fn view_matches_resolver() -> Result<()> {
+	compare(LITTLE_ENDIAN)?;
+	compare(BIG_ENDIAN)
+}
+
+#[test]
+fn truncated_id_tables_are_rejected() -> Result<()> {
+	let bytes = std::fs::read(LITTLE_ENDIAN)?;
+	assert!(DexView::new(&bytes[..0x100]).is_err());
+	Ok(())
+}
+
+/// NUL and characters outside the BMP are what MUTF-8 does differently from
+/// UTF-8, the first takes two bytes and the others a surrogate pair.
+#[test]
+fn mutf8_strings_decode() -> Result<()> {
+	let encoded: [(&str, u32, &[u8]); 6] = [
+		("plain", 5, b"plain"),
+		("\0", 1, &[0xc0, 0x80]),
+		("\u{e9}", 1, &[0xc3, 0xa9]),
+		("\u{20ac}", 1, &[0xe2, 0x82, 0xac]),
+		("\u{1f600}", 2, &[0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80]),
+		(
+			"a\0\u{1f600}",
+			4,
+			&[b'a', 0xc0, 0x80, 0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80],
+		),
+	];
+	for (string, size, data) in encoded {
+		let item = StringDataItem::new(string);
+		assert_eq!(*item.size, size, "{:?}", string);
+		assert_eq!(item.data, data, "{:?}", string);
+
+		let string_ref = StringDataRef {
+			size: size.into(),
+			data,
+		};
+		let decoded = string_ref.decode()?;
+		assert_eq!(decoded, string);
+		// only plain ASCII is borrowed
+		assert_eq!(matches!(decoded, Cow::Borrowed(_)), string == "plain");
+		assert_eq!(string_ref.to_item()?, item);
+	}
+	Ok(())
+}
+
+#[test]
+fn malformed_mutf8_is_an_error() {
+	let malformed: [(u32, &[u8]); 3] = [
+		// a continuation byte that isn't one
+		(1, &[0xc3, 0x41]),
+		// an overlong encoding of `A`
+		(1, &[0xc1, 0x81]),
+		// fewer characters than the size says
+		(3, b"ab"),
+	];
+	for (size, data) in malformed {
+		let string_ref = StringDataRef {
+			size: size.into(),
+			data,
+		};
+		assert!(string_ref.decode().is_err(), "{:x?}", data);
+	}
+}
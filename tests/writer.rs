@@ -0,0 +1,54 @@
+mod common;
+
+use std::fs;
+
+use eyre::Result;
+use smali::dex::types::{id::StringDataItem, map::TypeCode};
+
+use crate::common::{resolve, LITTLE_ENDIAN};
+
+/// Growing a string moves everything after the string data, the offsets
+/// that point there have to follow the items they refer to.
+#[test]
+fn offsets_follow_moved_items() -> Result<()> {
+	let bytes = fs::read(LITTLE_ENDIAN)?;
+	let mut resolver = resolve(bytes.clone())?;
+	// the last string stays the last one in sort order
+	let last = resolver.dex_file.string_data.len() - 1;
+	let string = format!("{}yyyyyy", resolver.dex_file.string_data[last].string);
+	resolver.dex_file.string_data[last] = StringDataItem::new(&string);
+	let expected = resolver.classes()?;
+
+	let written = resolver.dex_file.to_bytes()?;
+	assert!(written.len() > bytes.len());
+
+	let mut moved = resolve(written.clone())?;
+	moved.dex_file.verify_integrity(&written)?;
+	assert_eq!(moved.dex_file.header.file_size as usize, written.len());
+	assert_eq!(moved.dex_file.string_data[last].string, string);
+	assert!(moved.dex_file.header.map_off > resolver.dex_file.header.map_off);
+	assert_eq!(
+		moved.dex_file.header.map_off,
+		moved.dex_file.item_offsets[&TypeCode::MapList][0]
+	);
+
+	let before = &resolver.dex_file.item_offsets;
+	let after = &moved.dex_file.item_offsets;
+	for typ in [TypeCode::TypeList, TypeCode::CodeItem] {
+		assert_eq!(after[&typ], before[&typ], "{:?}", typ);
+	}
+	for typ in [
+		TypeCode::AnnotationItem,
+		TypeCode::ClassDataItem,
+		TypeCode::EncodedArrayItem,
+		TypeCode::AnnotationSetItem,
+		TypeCode::AnnotationSetRefList,
+		TypeCode::AnnotationsDirectoryItem,
+	] {
+		assert!(after[&typ][0] > before[&typ][0], "{:?}", typ);
+	}
+
+	assert_eq!(moved.classes()?, expected);
+	assert_eq!(moved.dex_file.to_bytes()?, written);
+	Ok(())
+}